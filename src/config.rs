@@ -0,0 +1,636 @@
+use crate::discover::Manifest;
+use crate::formatting::{
+    BracketPlacement, DoctypeCase, FormatOptions, IndentStyle, QuoteStyle, VoidStyle,
+    WhitespaceSensitivity,
+};
+use crate::html_parser::attribute::{ClassOrder, TAILWIND_UTILITIES, TAILWIND_VARIANTS};
+use crate::template_parser::registry::TagRegistry;
+use glob::Pattern;
+use serde::Deserialize;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The config file that's only for djfmt.
+pub const CONFIG_FILE: &str = "djfmt.toml";
+/// Python's project file, which can hold the config in a `[tool.djfmt]` table.
+pub const PYPROJECT_FILE: &str = "pyproject.toml";
+
+/// Whether to indent with tabs or spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndentKind {
+    Tab,
+    Space,
+}
+
+/// How to order the classes in a `class` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClassOrderKind {
+    Source,
+    Alphabetical,
+    Tailwind,
+}
+
+/// A block tag that isn't built into Django, like `{% component %}…{% endcomponent %}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CustomTag {
+    pub name: String,
+    /// Defaults to the name prefixed with `end`
+    pub end_tag: Option<String>,
+    /// Tags that split the block into branches, like `{% else %}`
+    #[serde(default)]
+    pub intermediate_tags: Vec<String>,
+    /// Whether the body is kept exactly as it was written, like in `{% verbatim %}`
+    #[serde(default)]
+    pub raw: bool,
+}
+
+/// Which lint rules to report, by name.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// The only rules to report, or every rule if not set
+    pub select: Option<Vec<String>>,
+    /// Rules to never report, even if they're selected
+    pub ignore: Option<Vec<String>>,
+}
+
+/// The settings from a `djfmt.toml`, or from the `[tool.djfmt]` table in a `pyproject.toml`.
+/// Everything is optional so that configs in nested directories only need to set what they
+/// change.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub indent_style: Option<IndentKind>,
+    /// The number of spaces to indent with, which implies `indent-style = "space"`. With
+    /// `indent-style = "tab"`, the number of columns a tab counts as instead
+    pub indent_width: Option<usize>,
+    pub line_width: Option<usize>,
+    pub quote_style: Option<QuoteStyle>,
+    pub template_quote_style: Option<QuoteStyle>,
+    pub void_style: Option<VoidStyle>,
+    pub bracket_placement: Option<BracketPlacement>,
+    pub doctype_case: Option<DoctypeCase>,
+    pub whitespace_sensitivity: Option<WhitespaceSensitivity>,
+    pub max_blank_lines: Option<usize>,
+    pub final_newline: Option<bool>,
+    pub insert_implied_end_tags: Option<bool>,
+    pub long_comments_as_blocks: Option<bool>,
+    pub sort_attributes: Option<bool>,
+    pub dedup_attributes: Option<bool>,
+    pub class_order: Option<ClassOrderKind>,
+    /// The variants for `class-order = "tailwind"`, in order, instead of Tailwind's own. Ones
+    /// ending in `*` like `group-*` match any variant that starts with the rest
+    pub tailwind_variants: Option<Vec<String>>,
+    /// The utilities, or prefixes like `bg`, for `class-order = "tailwind"`, in order, instead of
+    /// Tailwind's own
+    pub tailwind_utilities: Option<Vec<String>>,
+    /// Globs for the files to format, relative to the config file. Every file is formatted if not
+    /// set
+    pub include: Option<Vec<String>>,
+    /// Globs for files not to format, relative to the config file
+    pub exclude: Option<Vec<String>>,
+    /// The extensions of the files to format, without the dot. Defaults to just `html`
+    pub extensions: Option<Vec<String>>,
+    pub custom_tags: Option<Vec<CustomTag>>,
+    /// A manifest written by `djfmt discover-tags`, relative to the config file
+    pub tag_manifest: Option<String>,
+    /// What was read from `tag_manifest`, when the config was loaded
+    #[serde(skip)]
+    pub manifest: Option<Manifest>,
+    pub lint: LintConfig,
+}
+
+/// A config file that couldn't be read.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "error: invalid config in {}: {}",
+            self.path.to_string_lossy(),
+            self.message.trim_end()
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The part of a `pyproject.toml` that djfmt cares about.
+#[derive(Deserialize)]
+struct PyProject {
+    tool: Option<PyProjectTools>,
+}
+
+#[derive(Deserialize)]
+struct PyProjectTools {
+    djfmt: Option<Config>,
+}
+
+impl Config {
+    /// Parses a config file, or returns `None` for a `pyproject.toml` without a `[tool.djfmt]`
+    /// table. Globs are made relative to the directory the file is in.
+    pub fn parse(path: &Path, contents: &str) -> Result<Option<Self>, ConfigError> {
+        let error = |error: toml::de::Error| ConfigError {
+            path: path.to_path_buf(),
+            message: error.to_string(),
+        };
+        let config = if path.file_name().is_some_and(|name| name == PYPROJECT_FILE) {
+            let pyproject: PyProject = toml::from_str(contents).map_err(error)?;
+            pyproject.tool.and_then(|tool| tool.djfmt)
+        } else {
+            Some(toml::from_str(contents).map_err(error)?)
+        };
+
+        let root = path.parent().unwrap_or(Path::new(""));
+        Ok(config.map(|config| config.relative_to(root)))
+    }
+
+    /// Reads the config in `dir`, preferring a `djfmt.toml` over a `pyproject.toml`.
+    pub fn load(dir: &Path) -> Result<Option<Self>, ConfigError> {
+        for name in [CONFIG_FILE, PYPROJECT_FILE] {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let contents = fs::read_to_string(&path).map_err(|error| ConfigError {
+                path: path.clone(),
+                message: error.to_string(),
+            })?;
+            if let Some(mut config) = Self::parse(&path, &contents)? {
+                if let Some(manifest) = &config.tag_manifest {
+                    config.manifest = Some(Manifest::load(Path::new(manifest))?);
+                }
+                return Ok(Some(config));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds the config for the files in `dir` by walking up from it. The nearest config file
+    /// takes precedence, with anything it doesn't set taken from the ones further up.
+    pub fn find(dir: &Path) -> Result<Self, ConfigError> {
+        Self::find_below(dir, None)
+    }
+
+    /// Like [`Config::find`], but stops at `top` rather than going all the way up, if given.
+    pub fn find_below(dir: &Path, top: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        for ancestor in dir.ancestors() {
+            if let Some(parent) = Self::load(ancestor)? {
+                config = config.or(parent);
+            }
+            if top == Some(ancestor) {
+                break;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Fills in whatever isn't set with the settings from `parent`.
+    pub fn or(self, parent: Self) -> Self {
+        // The indent style and width go together, so a nearer width isn't overridden by the
+        // parent's tabs, but a nearer `indent-style = "space"` still takes the parent's width
+        let (indent_style, indent_width) = match (self.indent_style, self.indent_width) {
+            (None, None) => (parent.indent_style, parent.indent_width),
+            (Some(IndentKind::Space), None) => (self.indent_style, parent.indent_width),
+            indent => indent,
+        };
+        Self {
+            indent_style,
+            indent_width,
+            line_width: self.line_width.or(parent.line_width),
+            quote_style: self.quote_style.or(parent.quote_style),
+            template_quote_style: self.template_quote_style.or(parent.template_quote_style),
+            void_style: self.void_style.or(parent.void_style),
+            bracket_placement: self.bracket_placement.or(parent.bracket_placement),
+            doctype_case: self.doctype_case.or(parent.doctype_case),
+            whitespace_sensitivity: self
+                .whitespace_sensitivity
+                .or(parent.whitespace_sensitivity),
+            max_blank_lines: self.max_blank_lines.or(parent.max_blank_lines),
+            final_newline: self.final_newline.or(parent.final_newline),
+            insert_implied_end_tags: self
+                .insert_implied_end_tags
+                .or(parent.insert_implied_end_tags),
+            long_comments_as_blocks: self
+                .long_comments_as_blocks
+                .or(parent.long_comments_as_blocks),
+            sort_attributes: self.sort_attributes.or(parent.sort_attributes),
+            dedup_attributes: self.dedup_attributes.or(parent.dedup_attributes),
+            class_order: self.class_order.or(parent.class_order),
+            tailwind_variants: self.tailwind_variants.or(parent.tailwind_variants),
+            tailwind_utilities: self.tailwind_utilities.or(parent.tailwind_utilities),
+            include: self.include.or(parent.include),
+            exclude: self.exclude.or(parent.exclude),
+            extensions: self.extensions.or(parent.extensions),
+            custom_tags: self.custom_tags.or(parent.custom_tags),
+            tag_manifest: self.tag_manifest.or(parent.tag_manifest),
+            manifest: self.manifest.or(parent.manifest),
+            lint: LintConfig {
+                select: self.lint.select.or(parent.lint.select),
+                ignore: self.lint.ignore.or(parent.lint.ignore),
+            },
+        }
+    }
+
+    fn relative_to(self, root: &Path) -> Self {
+        let resolve = |globs: Option<Vec<String>>| {
+            globs.map(|globs| {
+                globs
+                    .iter()
+                    .map(|glob| root.join(glob).to_string_lossy().into_owned())
+                    .collect()
+            })
+        };
+        Self {
+            include: resolve(self.include),
+            exclude: resolve(self.exclude),
+            tag_manifest: self
+                .tag_manifest
+                .map(|path| root.join(path).to_string_lossy().into_owned()),
+            ..self
+        }
+    }
+
+    pub fn format_options(&self) -> FormatOptions {
+        let default = FormatOptions::default();
+        let indent_style = match (self.indent_style, self.indent_width) {
+            (Some(IndentKind::Tab), _) => IndentStyle::Tabs,
+            (Some(IndentKind::Space), None) => IndentStyle::Spaces(4),
+            (_, Some(width)) => IndentStyle::Spaces(width),
+            (None, None) => default.indent_style,
+        };
+        let tab_width = match (self.indent_style, self.indent_width) {
+            (Some(IndentKind::Tab), Some(width)) => width,
+            _ => default.tab_width,
+        };
+        FormatOptions {
+            indent_style,
+            tab_width,
+            line_width: self.line_width.unwrap_or(default.line_width),
+            quote_style: self.quote_style.unwrap_or(default.quote_style),
+            template_quote_style: self
+                .template_quote_style
+                .unwrap_or(default.template_quote_style),
+            void_style: self.void_style.unwrap_or(default.void_style),
+            bracket_placement: self.bracket_placement.unwrap_or(default.bracket_placement),
+            doctype_case: self.doctype_case.unwrap_or(default.doctype_case),
+            whitespace_sensitivity: self
+                .whitespace_sensitivity
+                .unwrap_or(default.whitespace_sensitivity),
+            max_blank_lines: self.max_blank_lines.or(default.max_blank_lines),
+            final_newline: self.final_newline.unwrap_or(default.final_newline),
+            insert_implied_end_tags: self
+                .insert_implied_end_tags
+                .unwrap_or(default.insert_implied_end_tags),
+            long_comments_as_blocks: self
+                .long_comments_as_blocks
+                .unwrap_or(default.long_comments_as_blocks),
+        }
+    }
+
+    /// Whether a file found while walking a directory should be formatted.
+    pub fn includes(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy());
+        let has_extension = match (&self.extensions, &extension) {
+            (Some(extensions), Some(extension)) => extensions.iter().any(|e| e == extension),
+            (None, Some(extension)) => extension == "html",
+            (_, None) => false,
+        };
+        let matches = |globs: &Option<Vec<String>>| {
+            globs.as_ref().map(|globs| {
+                globs
+                    .iter()
+                    .any(|glob| Pattern::new(glob).is_ok_and(|pattern| pattern.matches_path(path)))
+            })
+        };
+
+        has_extension
+            && matches(&self.include).unwrap_or(true)
+            && !matches(&self.exclude).unwrap_or(false)
+    }
+
+    /// Django's built-in block tags along with the ones from the tag manifest, then the custom
+    /// ones, which take precedence.
+    pub fn tags(&self) -> TagRegistry {
+        let mut custom_tags = self
+            .manifest
+            .as_ref()
+            .map(Manifest::custom_tags)
+            .unwrap_or_default();
+        custom_tags.extend(self.custom_tags.iter().flatten().cloned());
+        TagRegistry::with_custom_tags(&custom_tags)
+    }
+
+    /// The order to put classes in, with any Tailwind variants or utilities that were set taking
+    /// the place of Tailwind's own.
+    pub fn class_order(&self) -> ClassOrder {
+        match self.class_order {
+            None | Some(ClassOrderKind::Source) => ClassOrder::Source,
+            Some(ClassOrderKind::Alphabetical) => ClassOrder::Alphabetical,
+            Some(ClassOrderKind::Tailwind) => {
+                let or_default = |list: &Option<Vec<String>>, default: &[&str]| {
+                    list.clone()
+                        .unwrap_or_else(|| default.iter().map(|s| s.to_string()).collect())
+                };
+                ClassOrder::Tailwind {
+                    variants: or_default(&self.tailwind_variants, TAILWIND_VARIANTS),
+                    utilities: or_default(&self.tailwind_utilities, TAILWIND_UTILITIES),
+                }
+            }
+        }
+    }
+
+    /// Whether the lint rule with this name should be reported.
+    pub fn rule_enabled(&self, rule: &str) -> bool {
+        let selected = self
+            .lint
+            .select
+            .as_ref()
+            .is_none_or(|select| select.iter().any(|r| r == rule));
+        let ignored = self
+            .lint
+            .ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.iter().any(|r| r == rule));
+        selected && !ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::discover::MANIFEST_FILE;
+
+    #[rstest]
+    fn test_parsing_config() {
+        let contents = r#"
+            indent-width = 2
+            quote-style = "single"
+            template-quote-style = "prefer-double"
+            void-style = "bare"
+            bracket-placement = "same-line"
+            class-order = "tailwind"
+            tailwind-utilities = ["flex", "p"]
+            exclude = ["vendor/**"]
+
+            [[custom-tags]]
+            name = "component"
+
+            [lint]
+            ignore = ["duplicate-attribute"]
+        "#;
+        let config = Config::parse(Path::new("/project/djfmt.toml"), contents)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            config.format_options(),
+            FormatOptions {
+                indent_style: IndentStyle::Spaces(2),
+                quote_style: QuoteStyle::Single,
+                template_quote_style: QuoteStyle::Double,
+                void_style: VoidStyle::Bare,
+                bracket_placement: BracketPlacement::SameLine,
+                ..FormatOptions::default()
+            }
+        );
+        assert_eq!(
+            config.class_order(),
+            ClassOrder::Tailwind {
+                variants: TAILWIND_VARIANTS.iter().map(|v| v.to_string()).collect(),
+                utilities: vec!["flex".to_string(), "p".to_string()],
+            }
+        );
+        assert_eq!(config.exclude, Some(vec!["/project/vendor/**".to_string()]));
+        assert_eq!(
+            config.custom_tags,
+            Some(vec![CustomTag {
+                name: "component".to_string(),
+                end_tag: None,
+                intermediate_tags: vec![],
+                raw: false,
+            }])
+        );
+        assert!(!config.rule_enabled("duplicate-attribute"));
+    }
+
+    #[rstest]
+    #[case("[tool.black]\nline-length = 88", None)]
+    #[case("[tool.djfmt]\nline-width = 88", Some(88))]
+    fn test_parsing_pyproject(#[case] contents: &str, #[case] line_width: Option<usize>) {
+        let config = Config::parse(Path::new("pyproject.toml"), contents).unwrap();
+        assert_eq!(config.map(|config| config.line_width), line_width.map(Some));
+    }
+
+    #[rstest]
+    #[case("indent-width = \"two\"")]
+    #[case("unknown-key = 1")]
+    fn test_parsing_invalid_config(#[case] contents: &str) {
+        assert!(Config::parse(Path::new("djfmt.toml"), contents).is_err());
+    }
+
+    #[rstest]
+    fn test_finding_config() {
+        let root = std::env::temp_dir().join(format!("djfmt-config-{}", std::process::id()));
+        let emails = root.join("templates").join("emails");
+        let admin = root.join("admin").join("templates");
+        fs::create_dir_all(&emails).unwrap();
+        fs::create_dir_all(&admin).unwrap();
+        fs::write(
+            root.join(PYPROJECT_FILE),
+            "[tool.djfmt]\nindent-width = 2\nline-width = 80",
+        )
+        .unwrap();
+        fs::write(emails.join(CONFIG_FILE), "indent-style = \"tab\"").unwrap();
+        fs::write(
+            root.join("admin").join(CONFIG_FILE),
+            "indent-style = \"tab\"",
+        )
+        .unwrap();
+        fs::write(admin.join(CONFIG_FILE), "indent-width = 4").unwrap();
+
+        let find = |dir: &Path| Config::find_below(dir, Some(&root)).unwrap();
+        let templates = find(&root.join("templates"));
+        let emails = find(&emails);
+        let admin = find(&admin);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            templates.format_options().indent_style,
+            IndentStyle::Spaces(2)
+        );
+        assert_eq!(emails.format_options().indent_style, IndentStyle::Tabs);
+        assert_eq!(emails.format_options().line_width, 80);
+        // A width set nearer means spaces, even when a parent config sets tabs
+        assert_eq!(admin.format_options().indent_style, IndentStyle::Spaces(4));
+    }
+
+    #[rstest]
+    fn test_loading_tag_manifest() {
+        let root = std::env::temp_dir().join(format!("djfmt-manifest-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join(MANIFEST_FILE),
+            "filters = [\"money\"]\n\n[[tags]]\nname = \"card\"\nend-tag = \"endcard\"\n\n[[tags]]\nname = \"icon\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(CONFIG_FILE),
+            "tag-manifest = \"djfmt-tags.toml\"\n\n[[custom-tags]]\nname = \"panel\"",
+        )
+        .unwrap();
+
+        let config = Config::find_below(&root, Some(&root)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let tags = config.tags();
+        assert_eq!(tags.find("card").map(|tag| tag.end_tag), Some("endcard"));
+        assert_eq!(tags.find("panel").map(|tag| tag.end_tag), Some("endpanel"));
+        assert_eq!(tags.find("icon"), None);
+        assert_eq!(
+            config.manifest.map(|manifest| manifest.filters),
+            Some(vec!["money".to_string()])
+        );
+    }
+
+    #[rstest]
+    fn test_missing_tag_manifest() {
+        let root = std::env::temp_dir().join(format!("djfmt-no-manifest-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(CONFIG_FILE), "tag-manifest = \"missing.toml\"").unwrap();
+
+        let error = Config::find_below(&root, Some(&root)).unwrap_err();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(error.path, root.join("missing.toml"));
+    }
+
+    #[rstest]
+    fn test_nearest_config_takes_precedence() {
+        let nearest = Config {
+            indent_width: Some(2),
+            ..Config::default()
+        };
+        let parent = Config {
+            indent_width: Some(4),
+            line_width: Some(80),
+            ..Config::default()
+        };
+        let config = nearest.or(parent);
+        assert_eq!(config.indent_width, Some(2));
+        assert_eq!(config.line_width, Some(80));
+    }
+
+    #[rstest]
+    #[case::nearer_width(None, Some(2), Some(IndentKind::Tab), None, IndentStyle::Spaces(2))]
+    #[case::nearer_tabs(Some(IndentKind::Tab), None, None, Some(2), IndentStyle::Tabs)]
+    #[case::nearer_spaces(
+        Some(IndentKind::Space),
+        None,
+        Some(IndentKind::Tab),
+        Some(2),
+        IndentStyle::Spaces(2)
+    )]
+    #[case::parent_only(None, None, Some(IndentKind::Space), Some(2), IndentStyle::Spaces(2))]
+    fn test_merging_indent(
+        #[case] nearest_style: Option<IndentKind>,
+        #[case] nearest_width: Option<usize>,
+        #[case] parent_style: Option<IndentKind>,
+        #[case] parent_width: Option<usize>,
+        #[case] expected: IndentStyle,
+    ) {
+        let nearest = Config {
+            indent_style: nearest_style,
+            indent_width: nearest_width,
+            ..Config::default()
+        };
+        let parent = Config {
+            indent_style: parent_style,
+            indent_width: parent_width,
+            ..Config::default()
+        };
+        assert_eq!(nearest.or(parent).format_options().indent_style, expected);
+    }
+
+    #[rstest]
+    #[case::tab_width(Some(IndentKind::Tab), Some(2), 2)]
+    #[case::default_tab_width(Some(IndentKind::Tab), None, 4)]
+    #[case::spaces(None, Some(2), 4)]
+    fn test_tab_width(
+        #[case] indent_style: Option<IndentKind>,
+        #[case] indent_width: Option<usize>,
+        #[case] expected: usize,
+    ) {
+        let config = Config {
+            indent_style,
+            indent_width,
+            ..Config::default()
+        };
+        assert_eq!(config.format_options().tab_width, expected);
+    }
+
+    #[rstest]
+    #[case(None, None, None, "/p/templates/a.html", true)]
+    #[case(None, None, None, "/p/templates/a.txt", false)]
+    #[case(Some(vec!["txt"]), None, None, "/p/templates/a.txt", true)]
+    #[case(None, Some(vec!["/p/templates/**"]), None, "/p/templates/a.html", true)]
+    #[case(None, Some(vec!["/p/templates/**"]), None, "/p/static/a.html", false)]
+    #[case(None, None, Some(vec!["/p/**/vendor/*"]), "/p/app/vendor/a.html", false)]
+    fn test_including_files(
+        #[case] extensions: Option<Vec<&str>>,
+        #[case] include: Option<Vec<&str>>,
+        #[case] exclude: Option<Vec<&str>>,
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let strings = |values: Option<Vec<&str>>| {
+            values.map(|values| values.into_iter().map(String::from).collect())
+        };
+        let config = Config {
+            extensions: strings(extensions),
+            include: strings(include),
+            exclude: strings(exclude),
+            ..Config::default()
+        };
+        assert_eq!(config.includes(Path::new(path)), expected);
+    }
+
+    #[rstest]
+    #[case(None, None, true)]
+    #[case(Some(vec!["other"]), None, false)]
+    #[case(Some(vec!["duplicate-attribute"]), None, true)]
+    #[case(None, Some(vec!["duplicate-attribute"]), false)]
+    fn test_rule_selection(
+        #[case] select: Option<Vec<&str>>,
+        #[case] ignore: Option<Vec<&str>>,
+        #[case] expected: bool,
+    ) {
+        let strings = |values: Option<Vec<&str>>| {
+            values.map(|values| values.into_iter().map(String::from).collect())
+        };
+        let config = Config {
+            lint: LintConfig {
+                select: strings(select),
+                ignore: strings(ignore),
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.rule_enabled("duplicate-attribute"), expected);
+    }
+}
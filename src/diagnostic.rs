@@ -0,0 +1,16 @@
+use crate::span::Span;
+use std::fmt;
+
+/// A problem found in a template that doesn't stop it from being formatted.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Where in the source the problem is
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
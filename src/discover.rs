@@ -0,0 +1,594 @@
+use crate::config::{ConfigError, CustomTag};
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path},
+};
+
+/// Where `djfmt discover-tags` writes the manifest by default.
+pub const MANIFEST_FILE: &str = "djfmt-tags.toml";
+
+/// Directories that hold someone else's code rather than the project's, so aren't scanned.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "site-packages"];
+
+/// The custom tags and filters a Django project registers in its `templatetags` modules.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Manifest {
+    pub filters: Vec<String>,
+    pub tags: Vec<DiscoveredTag>,
+}
+
+/// A tag registered with `@register.tag` or one of its shortcuts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DiscoveredTag {
+    pub name: String,
+    /// The tag that closes the block, or `None` for a tag that stands on its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub intermediate_tags: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let error = |message: String| ConfigError {
+            path: path.to_path_buf(),
+            message,
+        };
+        let contents = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        toml::from_str(&contents).map_err(|e| error(e.to_string()))
+    }
+
+    /// Finds every tag and filter registered in the `templatetags` modules under `root`, without
+    /// running any Python. Hidden directories, like virtualenvs in `.venv`, are skipped.
+    pub fn scan(root: &Path) -> io::Result<Self> {
+        let pattern = root.join("**").join("templatetags").join("*.py");
+        let mut paths = glob(&pattern.to_string_lossy())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?
+            .filter_map(Result::ok)
+            .filter(|path| !is_skipped(path.strip_prefix(root).unwrap_or(path)))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut manifest = Self::default();
+        for path in paths {
+            let module = Self::scan_module(&fs::read_to_string(&path)?);
+            manifest.filters.extend(module.filters);
+            manifest.tags.extend(module.tags);
+        }
+        manifest.filters.sort();
+        manifest.filters.dedup();
+        manifest.tags.sort_by(|a, b| a.name.cmp(&b.name));
+        manifest.tags.dedup_by(|a, b| a.name == b.name);
+        Ok(manifest)
+    }
+
+    /// Finds the tags and filters registered in the source of a single Python module.
+    pub fn scan_module(source: &str) -> Self {
+        let lines = logical_lines(source);
+        let functions = function_bodies(&lines);
+
+        let mut manifest = Self::default();
+        let mut decorators = vec![];
+        for (_, line) in &lines {
+            if let Some(decorator) = line.strip_prefix('@') {
+                decorators.extend(Registration::parse(decorator.trim()));
+                continue;
+            }
+            if let Some(function) = def_name(line) {
+                for mut registration in decorators.drain(..) {
+                    registration
+                        .function
+                        .get_or_insert_with(|| function.to_string());
+                    registration.add_to(&mut manifest, &functions);
+                }
+                continue;
+            }
+            decorators.clear();
+            if let Some(registration) = Registration::parse(line) {
+                registration.add_to(&mut manifest, &functions);
+            }
+        }
+        manifest
+    }
+
+    /// The block tags, to add to the tag registry.
+    pub fn custom_tags(&self) -> Vec<CustomTag> {
+        self.tags
+            .iter()
+            .filter_map(|tag| {
+                Some(CustomTag {
+                    name: tag.name.clone(),
+                    end_tag: Some(tag.end_tag.clone()?),
+                    intermediate_tags: tag.intermediate_tags.clone(),
+                    raw: false,
+                })
+            })
+            .collect()
+    }
+}
+
+fn is_skipped(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_ref())
+        }
+        _ => false,
+    })
+}
+
+/// The ways of registering tags and filters with a `template.Library`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RegistrationKind {
+    Tag,
+    SimpleTag,
+    SimpleBlockTag,
+    InclusionTag,
+    Filter,
+}
+
+/// A `register.<kind>(…)` call, either as a decorator or on its own.
+#[derive(Debug, PartialEq)]
+struct Registration {
+    kind: RegistrationKind,
+    name: Option<String>,
+    /// The function that compiles the tag, or implements the filter
+    function: Option<String>,
+    /// The `end_name` given to `simple_block_tag`
+    end_name: Option<String>,
+}
+
+impl Registration {
+    /// Parses a line like `register.tag("name", function)`, or a decorator without the `@`.
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix("register.")?;
+        let kind_end = rest
+            .find(|c: char| !is_identifier_char(c))
+            .unwrap_or(rest.len());
+        let kind = match &rest[..kind_end] {
+            "tag" => RegistrationKind::Tag,
+            "simple_tag" => RegistrationKind::SimpleTag,
+            "simple_block_tag" => RegistrationKind::SimpleBlockTag,
+            "inclusion_tag" => RegistrationKind::InclusionTag,
+            "filter" => RegistrationKind::Filter,
+            _ => return None,
+        };
+
+        let mut registration = Self {
+            kind,
+            name: None,
+            function: None,
+            end_name: None,
+        };
+        let Some(arguments) = rest[kind_end..].trim_start().strip_prefix('(') else {
+            return Some(registration);
+        };
+        let arguments = &arguments[..closing_paren(arguments)?];
+
+        let mut positional = vec![];
+        for argument in split_arguments(arguments) {
+            match argument.split_once('=') {
+                Some((key, value)) if key.trim().chars().all(is_identifier_char) => {
+                    match (key.trim(), string_literal(value.trim())) {
+                        ("name", Some(name)) => registration.name = Some(name),
+                        ("end_name", Some(name)) => registration.end_name = Some(name),
+                        _ => {}
+                    }
+                }
+                _ => positional.push(argument.trim()),
+            }
+        }
+        // Only `tag` and `filter` take the name first, `inclusion_tag` takes a template
+        let takes_name = matches!(kind, RegistrationKind::Tag | RegistrationKind::Filter);
+        for argument in positional {
+            match string_literal(argument) {
+                Some(name) if takes_name => {
+                    registration.name.get_or_insert(name);
+                }
+                Some(_) => {}
+                None if argument.chars().all(is_identifier_char) => {
+                    registration.function.get_or_insert(argument.to_string());
+                }
+                None => {}
+            }
+        }
+        Some(registration)
+    }
+
+    fn add_to(self, manifest: &mut Manifest, functions: &HashMap<&str, Vec<&str>>) {
+        let Some(name) = self.name.or(self.function.clone()) else {
+            return;
+        };
+        let tag = match self.kind {
+            RegistrationKind::Filter => {
+                manifest.filters.push(name);
+                return;
+            }
+            RegistrationKind::SimpleTag | RegistrationKind::InclusionTag => DiscoveredTag {
+                name,
+                end_tag: None,
+                intermediate_tags: vec![],
+            },
+            RegistrationKind::SimpleBlockTag => DiscoveredTag {
+                end_tag: Some(self.end_name.unwrap_or_else(|| format!("end{name}"))),
+                name,
+                intermediate_tags: vec![],
+            },
+            RegistrationKind::Tag => {
+                let body = self
+                    .function
+                    .and_then(|function| functions.get(function.as_str()));
+                let (end_tag, intermediate_tags) = match body {
+                    Some(body) => infer_block_tags(&name, body),
+                    None => (None, vec![]),
+                };
+                DiscoveredTag {
+                    name,
+                    end_tag,
+                    intermediate_tags,
+                }
+            }
+        };
+        manifest.tags.push(tag);
+    }
+}
+
+/// Works out a tag's end and intermediate tags from the `parser.parse((…))` calls in its
+/// compile function, which list the tags that end each part of the block.
+fn infer_block_tags(name: &str, body: &[&str]) -> (Option<String>, Vec<String>) {
+    let mut end_tags = vec![];
+    let mut intermediate_tags = vec![];
+    for line in body {
+        for (start, _) in line.match_indices("parser.parse(") {
+            let arguments = &line[start + "parser.parse(".len()..];
+            let Some(end) = closing_paren(arguments) else {
+                continue;
+            };
+            for (literal, rest) in string_literals(&arguments[..end]) {
+                // Like `"end" + bits[0]`, which is always the tag's own name
+                let tag = if literal == "end" && rest.trim_start().starts_with('+') {
+                    format!("end{name}")
+                } else {
+                    literal
+                };
+                if tag.starts_with("end") {
+                    end_tags.push(tag);
+                } else if !intermediate_tags.contains(&tag) {
+                    intermediate_tags.push(tag);
+                }
+            }
+        }
+    }
+    let own_end_tag = format!("end{name}");
+    let end_tag = if end_tags.contains(&own_end_tag) {
+        Some(own_end_tag)
+    } else {
+        end_tags.into_iter().next()
+    };
+    match end_tag {
+        Some(end_tag) => (Some(end_tag), intermediate_tags),
+        None => (None, vec![]),
+    }
+}
+
+/// Joins up lines that are continued within brackets, and drops comments and blank lines.
+/// Each line comes with its indentation.
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut indent = 0;
+    let mut depth = 0usize;
+    let mut quote: Option<&str> = None;
+
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if line.is_empty() && depth == 0 && quote.is_none() && (c == ' ' || c == '\t') {
+            indent += 1;
+            rest = &rest[1..];
+            continue;
+        }
+        if let Some(delimiter) = quote {
+            if c == '\\' {
+                let escaped = rest[1..].chars().next().map_or(0, char::len_utf8);
+                line.push_str(&rest[..1 + escaped]);
+                rest = &rest[1 + escaped..];
+                continue;
+            }
+            if rest.starts_with(delimiter) {
+                quote = None;
+                line.push_str(delimiter);
+                rest = &rest[delimiter.len()..];
+                continue;
+            }
+        } else {
+            match c {
+                '#' => {
+                    rest = rest.find('\n').map_or("", |end| &rest[end..]);
+                    continue;
+                }
+                '"' | '\'' => {
+                    let delimiter = if rest.starts_with("\"\"\"") {
+                        "\"\"\""
+                    } else if rest.starts_with("'''") {
+                        "'''"
+                    } else {
+                        &rest[..1]
+                    };
+                    quote = Some(delimiter);
+                    line.push_str(delimiter);
+                    rest = &rest[delimiter.len()..];
+                    continue;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                '\n' if depth == 0 => {
+                    if !line.is_empty() {
+                        lines.push((indent, std::mem::take(&mut line)));
+                    }
+                    indent = 0;
+                    rest = &rest[1..];
+                    continue;
+                }
+                '\n' | '\r' => {
+                    line.push(' ');
+                    rest = &rest[1..];
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        line.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !line.is_empty() {
+        lines.push((indent, line));
+    }
+    lines
+}
+
+/// The lines of every function in the module, by name.
+fn function_bodies(lines: &[(usize, String)]) -> HashMap<&str, Vec<&str>> {
+    let mut functions = HashMap::new();
+    for (i, (indent, line)) in lines.iter().enumerate() {
+        let Some(name) = def_name(line) else {
+            continue;
+        };
+        let body = lines[i + 1..]
+            .iter()
+            .take_while(|(body_indent, _)| body_indent > indent)
+            .map(|(_, line)| line.as_str())
+            .collect();
+        functions.insert(name, body);
+    }
+    functions
+}
+
+/// The name of the function a line like `def name(…):` defines.
+fn def_name(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("async ").unwrap_or(line);
+    let rest = rest.strip_prefix("def ")?.trim_start();
+    let end = rest.find(|c: char| !is_identifier_char(c))?;
+    Some(&rest[..end])
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Where the bracket closing the one just before `text` is.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') if depth == 0 => return Some(i),
+            (None, ')' | ']' | '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits call arguments on the commas that aren't within brackets or strings.
+fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = arguments.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(&arguments[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&arguments[start..]);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+/// The value of a Python string literal that makes up the whole of `text`.
+fn string_literal(text: &str) -> Option<String> {
+    match string_literals(text).as_slice() {
+        [(literal, rest)] if rest.trim().is_empty() && text.starts_with(['"', '\'']) => {
+            Some(literal.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Every string literal in `text`, along with the text after each one.
+fn string_literals(text: &str) -> Vec<(String, &str)> {
+    let mut literals = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(['"', '\'']) {
+        let quote = rest[start..].chars().next().unwrap();
+        let mut literal = String::new();
+        let mut chars = rest[start + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        literal.push(escaped);
+                    }
+                }
+                c if c == quote => {
+                    end = Some(start + 1 + i + 1);
+                    break;
+                }
+                c => literal.push(c),
+            }
+        }
+        let Some(end) = end else {
+            break;
+        };
+        rest = &rest[end..];
+        literals.push((literal, rest));
+    }
+    literals
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn tag(name: &str, end_tag: Option<&str>, intermediate_tags: &[&str]) -> DiscoveredTag {
+        DiscoveredTag {
+            name: name.to_string(),
+            end_tag: end_tag.map(str::to_string),
+            intermediate_tags: intermediate_tags.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[rstest]
+    #[case::decorated_tag(
+        "@register.tag\ndef icon(parser, token):\n    return IconNode()",
+        vec![tag("icon", None, &[])],
+    )]
+    #[case::named_tag(
+        "@register.tag(name='show')\ndef do_show(parser, token):\n    pass",
+        vec![tag("show", None, &[])],
+    )]
+    #[case::block_tag(
+        "@register.tag\ndef component(parser, token):\n    nodelist = parser.parse(('slot', 'endcomponent',))\n    parser.delete_first_token()",
+        vec![tag("component", Some("endcomponent"), &["slot"])],
+    )]
+    #[case::registered_by_call(
+        "def do_cache(parser, token):\n    nodelist = parser.parse(\n        (\"endcache\",)\n    )\n\nregister.tag(\"cache\", do_cache)",
+        vec![tag("cache", Some("endcache"), &[])],
+    )]
+    #[case::end_tag_from_name(
+        "@register.tag('panel')\ndef do_panel(parser, token):\n    bits = token.split_contents()\n    nodelist = parser.parse(('end' + bits[0],))",
+        vec![tag("panel", Some("endpanel"), &[])],
+    )]
+    #[case::simple_tags(
+        "@register.simple_tag(takes_context=True)\ndef current_time(context):\n    pass\n\n@register.inclusion_tag('results.html', name='show_results')\ndef results(poll):\n    pass",
+        vec![tag("current_time", None, &[]), tag("show_results", None, &[])],
+    )]
+    #[case::simple_block_tag(
+        "@register.simple_block_tag(end_name='stop')\ndef chart(content):\n    pass",
+        vec![tag("chart", Some("stop"), &[])],
+    )]
+    #[case::commented_out("# @register.tag\ndef icon(parser, token):\n    pass", vec![])]
+    fn test_scanning_tags(#[case] source: &str, #[case] expected: Vec<DiscoveredTag>) {
+        assert_eq!(Manifest::scan_module(source).tags, expected);
+    }
+
+    #[rstest]
+    #[case("@register.filter\ndef cut(value, arg):\n    pass", vec!["cut"])]
+    #[case("@register.filter(is_safe=True)\n@stringfilter\ndef lower(value):\n    pass", vec!["lower"])]
+    #[case("@register.filter('currency')\ndef format_currency(value):\n    pass", vec!["currency"])]
+    #[case("@register.filter(name=\"md\")\ndef markdown(value):\n    pass", vec!["md"])]
+    #[case("def cut(value, arg):\n    pass\n\nregister.filter('cut', cut)", vec!["cut"])]
+    #[case("register.filter(cut)", vec!["cut"])]
+    #[case("other.filter(cut)", vec![])]
+    fn test_scanning_filters(#[case] source: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(Manifest::scan_module(source).filters, expected);
+    }
+
+    #[rstest]
+    fn test_scanning_project() {
+        let root = std::env::temp_dir().join(format!("djfmt-discover-{}", std::process::id()));
+        let app = root.join("app").join("templatetags");
+        let venv = root.join(".venv").join("lib").join("templatetags");
+        fs::create_dir_all(&app).unwrap();
+        fs::create_dir_all(&venv).unwrap();
+        fs::write(
+            app.join("ui.py"),
+            "@register.simple_block_tag\ndef card(content):\n    pass\n\n@register.filter\ndef money(value):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            venv.join("other.py"),
+            "@register.filter\ndef vendored(value):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("app").join("views.py"),
+            "@register.filter\ndef view(value):\n    pass\n",
+        )
+        .unwrap();
+
+        let manifest = Manifest::scan(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            manifest,
+            Manifest {
+                filters: vec!["money".to_string()],
+                tags: vec![tag("card", Some("endcard"), &[])],
+            }
+        );
+        assert_eq!(
+            manifest.custom_tags(),
+            vec![CustomTag {
+                name: "card".to_string(),
+                end_tag: Some("endcard".to_string()),
+                intermediate_tags: vec![],
+                raw: false,
+            }]
+        );
+    }
+
+    #[rstest]
+    fn test_manifest_round_trip() {
+        let manifest = Manifest {
+            filters: vec!["money".to_string()],
+            tags: vec![
+                tag("component", Some("endcomponent"), &["slot"]),
+                tag("icon", None, &[]),
+            ],
+        };
+        let written = toml::to_string(&manifest).unwrap();
+        assert_eq!(toml::from_str::<Manifest>(&written).unwrap(), manifest);
+    }
+}
@@ -0,0 +1,456 @@
+//! An intermediate representation of formatted output, after Wadler's "A prettier printer" and
+//! prettier's own documents. Printers describe where lines can be broken with a [`Doc`], and
+//! [`Doc::print`] works out which of those breaks are needed for the output to fit the line width.
+
+use crate::formatting::FormatOptions;
+use std::borrow::Cow;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+/// A document to print, describing where lines can be broken.
+#[derive(Clone)]
+pub enum Doc<'a> {
+    Nil,
+    /// Text printed as it is. Any newlines within it are kept as they are, without indenting the
+    /// lines after them, and break every group the text is in
+    Text(Cow<'a, str>),
+    /// A space, or a line break if the group it's in is broken
+    Line,
+    /// Nothing, or a line break if the group it's in is broken
+    SoftLine,
+    /// A line break, which breaks every group it's in
+    HardLine,
+    Concat(Vec<Doc<'a>>),
+    /// Indents the lines broken within it by one more level
+    Indent(Box<Doc<'a>>),
+    /// Contents that are printed flat, on a single line, if they fit within the line width, and
+    /// broken otherwise
+    Group {
+        contents: Box<Doc<'a>>,
+        should_break: bool,
+    },
+    /// One document or the other, depending on whether the group it's in is broken
+    IfBreak {
+        broken: Box<Doc<'a>>,
+        flat: Box<Doc<'a>>,
+    },
+    /// A document that depends on the indent level it's printed at
+    Nesting(Rc<dyn Fn(usize) -> Doc<'a> + 'a>),
+}
+
+impl<'a> Doc<'a> {
+    pub fn text(text: impl Into<Cow<'a, str>>) -> Self {
+        Doc::Text(text.into())
+    }
+
+    pub fn concat(docs: impl IntoIterator<Item = Doc<'a>>) -> Self {
+        Doc::Concat(docs.into_iter().collect())
+    }
+
+    pub fn indent(doc: Doc<'a>) -> Self {
+        Doc::Indent(Box::new(doc))
+    }
+
+    /// A group that's broken whenever it contains a hard line break, as it can't be flat then.
+    pub fn group(doc: Doc<'a>) -> Self {
+        Doc::Group {
+            should_break: doc.has_hard_line(),
+            contents: Box::new(doc),
+        }
+    }
+
+    pub fn if_break(broken: Doc<'a>, flat: Doc<'a>) -> Self {
+        Doc::IfBreak {
+            broken: Box::new(broken),
+            flat: Box::new(flat),
+        }
+    }
+
+    pub fn nesting(f: impl Fn(usize) -> Doc<'a> + 'a) -> Self {
+        Doc::Nesting(Rc::new(f))
+    }
+
+    /// The documents with `separator` between each of them.
+    pub fn join(separator: Doc<'a>, docs: impl IntoIterator<Item = Doc<'a>>) -> Self {
+        let mut joined = vec![];
+        for doc in docs {
+            if !joined.is_empty() {
+                joined.push(separator.clone());
+            }
+            joined.push(doc);
+        }
+        Doc::Concat(joined)
+    }
+
+    /// Whether the document can't be printed flat. Nested documents aren't known until they're
+    /// printed, so they're never counted.
+    fn has_hard_line(&self) -> bool {
+        match self {
+            Doc::HardLine => true,
+            Doc::Text(text) => text.contains('\n'),
+            Doc::Concat(docs) => docs.iter().any(Doc::has_hard_line),
+            Doc::Indent(doc) => doc.has_hard_line(),
+            Doc::Group { should_break, .. } => *should_break,
+            Doc::IfBreak { flat, .. } => flat.has_hard_line(),
+            Doc::Nil | Doc::Line | Doc::SoftLine | Doc::Nesting(_) => false,
+        }
+    }
+
+    /// Prints the document as if it started `indent_level` levels deep. The first line isn't
+    /// indented, as it carries on from whatever came before it.
+    pub fn print(&self, indent_level: usize, options: &FormatOptions) -> String {
+        let mut printer = Printer {
+            options,
+            output: String::new(),
+            column: indent_width(indent_level, options),
+            pending_indent: None,
+        };
+        printer.print(self, indent_level, Mode::Break);
+        printer.output
+    }
+
+    /// Prints the document on a single line, apart from the groups that can't be flat.
+    pub fn print_flat(&self, options: &FormatOptions) -> String {
+        let mut printer = Printer {
+            options,
+            output: String::new(),
+            column: 0,
+            pending_indent: None,
+        };
+        printer.print(self, 0, Mode::Flat);
+        printer.output
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// A document still to be printed, along with its indent level and the mode of its group. It's
+/// owned when a nesting made it while printing.
+type Command<'d, 'a> = (usize, Mode, Cow<'d, Doc<'a>>);
+
+struct Printer<'o> {
+    options: &'o FormatOptions,
+    output: String,
+    /// The width of the current line so far, including its indentation
+    column: usize,
+    /// The indent level of the line that was just broken, which is only written once there's
+    /// something on the line so that blank lines stay empty
+    pending_indent: Option<usize>,
+}
+
+impl Printer<'_> {
+    fn print(&mut self, doc: &Doc, indent_level: usize, mode: Mode) {
+        let mut commands: Vec<Command> = vec![(indent_level, mode, Cow::Borrowed(doc))];
+        while let Some((indent_level, mode, doc)) = commands.pop() {
+            match &*doc {
+                Doc::Nil => {}
+                Doc::Text(text) => self.write(text),
+                Doc::Line if mode == Mode::Flat => self.write(" "),
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => self.newline(indent_level),
+                Doc::Concat(_) => {
+                    let docs = into_children(doc);
+                    commands.extend(docs.into_iter().rev().map(|doc| (indent_level, mode, doc)));
+                }
+                Doc::Indent(_) => {
+                    commands.extend(
+                        into_children(doc)
+                            .into_iter()
+                            .map(|doc| (indent_level + 1, mode, doc)),
+                    );
+                }
+                Doc::Group {
+                    contents,
+                    should_break,
+                } => {
+                    let mode = if *should_break {
+                        Mode::Break
+                    } else if mode == Mode::Flat
+                        || self.fits((indent_level, Mode::Flat, contents), &commands)
+                    {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+                    commands.extend(
+                        into_children(doc)
+                            .into_iter()
+                            .map(|doc| (indent_level, mode, doc)),
+                    );
+                }
+                Doc::IfBreak { .. } => {
+                    // The broken document comes first
+                    let docs = into_children(doc);
+                    let doc = docs
+                        .into_iter()
+                        .nth(if mode == Mode::Break { 0 } else { 1 });
+                    commands.extend(doc.map(|doc| (indent_level, mode, doc)));
+                }
+                Doc::Nesting(f) => {
+                    commands.push((indent_level, mode, Cow::Owned(f(indent_level))));
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(indent_level) = self.pending_indent.take() {
+            self.output.push_str(&self.options.indent(indent_level));
+        }
+        self.output.push_str(text);
+        self.column = match text.rfind('\n') {
+            Some(newline) => self.options.width(&text[newline + 1..]),
+            None => self.column + self.options.width(text),
+        };
+    }
+
+    fn newline(&mut self, indent_level: usize) {
+        self.output.push('\n');
+        self.pending_indent = Some(indent_level);
+        self.column = indent_width(indent_level, self.options);
+    }
+
+    /// Whether `next` fits on the rest of the current line, along with whatever comes after it up
+    /// to the next line break.
+    fn fits<'a>(&self, next: (usize, Mode, &Doc<'a>), rest: &[Command<'_, 'a>]) -> bool {
+        let mut width = self.options.line_width as isize - self.column as isize;
+        let rest = rest
+            .iter()
+            .rev()
+            .map(|(indent_level, mode, doc)| (*indent_level, *mode, &**doc));
+        for (indent_level, mode, doc) in std::iter::once(next).chain(rest) {
+            let fits = measure(doc, indent_level, mode, &mut width, self.options);
+            if let ControlFlow::Break(fits) = fits {
+                return fits;
+            }
+        }
+        true
+    }
+}
+
+/// The documents directly within `doc`, borrowed or owned like it is.
+fn into_children<'d, 'a>(doc: Cow<'d, Doc<'a>>) -> Vec<Cow<'d, Doc<'a>>> {
+    match doc {
+        Cow::Borrowed(doc) => match doc {
+            Doc::Concat(docs) => docs.iter().map(Cow::Borrowed).collect(),
+            Doc::Indent(doc) | Doc::Group { contents: doc, .. } => vec![Cow::Borrowed(&**doc)],
+            Doc::IfBreak { broken, flat } => vec![Cow::Borrowed(&**broken), Cow::Borrowed(&**flat)],
+            _ => vec![],
+        },
+        Cow::Owned(doc) => match doc {
+            Doc::Concat(docs) => docs.into_iter().map(Cow::Owned).collect(),
+            Doc::Indent(doc) | Doc::Group { contents: doc, .. } => vec![Cow::Owned(*doc)],
+            Doc::IfBreak { broken, flat } => vec![Cow::Owned(*broken), Cow::Owned(*flat)],
+            _ => vec![],
+        },
+    }
+}
+
+/// Takes the width of `doc` up to its first line break from `width`, breaking with whether it fit
+/// once that's known.
+fn measure(
+    doc: &Doc,
+    indent_level: usize,
+    mode: Mode,
+    width: &mut isize,
+    options: &FormatOptions,
+) -> ControlFlow<bool> {
+    match doc {
+        Doc::Nil => {}
+        Doc::Text(text) => {
+            let first_line = text.split('\n').next().unwrap_or_default();
+            *width -= options.width(first_line) as isize;
+            if text.contains('\n') {
+                return ControlFlow::Break(*width >= 0);
+            }
+        }
+        Doc::Line if mode == Mode::Flat => *width -= 1,
+        Doc::SoftLine if mode == Mode::Flat => {}
+        Doc::Line | Doc::SoftLine | Doc::HardLine => return ControlFlow::Break(true),
+        Doc::Concat(docs) => {
+            for doc in docs {
+                measure(doc, indent_level, mode, width, options)?;
+            }
+        }
+        Doc::Indent(doc) => measure(doc, indent_level + 1, mode, width, options)?,
+        Doc::Group {
+            contents,
+            should_break,
+        } => {
+            let mode = if *should_break { Mode::Break } else { mode };
+            measure(contents, indent_level, mode, width, options)?;
+        }
+        Doc::IfBreak { broken, flat } => {
+            let doc = if mode == Mode::Break { broken } else { flat };
+            measure(doc, indent_level, mode, width, options)?;
+        }
+        Doc::Nesting(f) => measure(&f(indent_level), indent_level, mode, width, options)?,
+    }
+    if *width < 0 {
+        ControlFlow::Break(false)
+    } else {
+        ControlFlow::Continue(())
+    }
+}
+
+fn indent_width(indent_level: usize, options: &FormatOptions) -> usize {
+    options.width(&options.indent(indent_level))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::formatting::IndentStyle;
+
+    /// `[a, b, c]`, or one item per line when that's too long.
+    fn list(items: &[&'static str]) -> Doc<'static> {
+        Doc::group(Doc::concat([
+            Doc::text("["),
+            Doc::indent(Doc::concat([
+                Doc::SoftLine,
+                Doc::join(
+                    Doc::concat([Doc::text(","), Doc::Line]),
+                    items.iter().map(|&item| Doc::text(item)),
+                ),
+                Doc::if_break(Doc::text(","), Doc::Nil),
+            ])),
+            Doc::SoftLine,
+            Doc::text("]"),
+        ]))
+    }
+
+    #[rstest]
+    #[case::fits(20, 0, "[one, two, three]")]
+    #[case::exactly_fits(17, 0, "[one, two, three]")]
+    #[case::too_long(16, 0, "[\n\tone,\n\ttwo,\n\tthree,\n]")]
+    #[case::too_long_once_indented(17, 1, "[\n\t\tone,\n\t\ttwo,\n\t\tthree,\n\t]")]
+    fn test_printing_group(
+        #[case] line_width: usize,
+        #[case] indent_level: usize,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            line_width,
+            ..FormatOptions::default()
+        };
+        let doc = list(&["one", "two", "three"]);
+        assert_eq!(doc.print(indent_level, &options), expected);
+    }
+
+    #[rstest]
+    #[case::wide_tabs(4, "[\n\t\tone,\n\t\ttwo,\n\t\tthree,\n\t]")]
+    #[case::narrow_tabs(2, "[one, two, three]")]
+    fn test_printing_group_counts_tab_width(#[case] tab_width: usize, #[case] expected: &str) {
+        let options = FormatOptions {
+            line_width: 20,
+            tab_width,
+            ..FormatOptions::default()
+        };
+        let doc = list(&["one", "two", "three"]);
+        assert_eq!(doc.print(1, &options), expected);
+    }
+
+    #[rstest]
+    fn test_printing_nested_groups_breaks_outer_first() {
+        let options = FormatOptions {
+            line_width: 12,
+            indent_style: IndentStyle::Spaces(2),
+            ..FormatOptions::default()
+        };
+        let doc = Doc::group(Doc::concat([
+            Doc::text("f("),
+            Doc::indent(Doc::concat([Doc::SoftLine, list(&["a", "b"])])),
+            Doc::SoftLine,
+            Doc::text(") + x"),
+        ]));
+        assert_eq!(doc.print(0, &options), "f(\n  [a, b]\n) + x");
+    }
+
+    #[rstest]
+    fn test_printing_group_counts_text_after_it() {
+        let options = FormatOptions {
+            line_width: 10,
+            ..FormatOptions::default()
+        };
+        let doc = Doc::concat([list(&["a", "b"]), Doc::text(" + 12345")]);
+        assert_eq!(doc.print(0, &options), "[\n\ta,\n\tb,\n] + 12345");
+    }
+
+    #[rstest]
+    fn test_printing_nesting_counts_text_after_it() {
+        let options = FormatOptions {
+            line_width: 10,
+            ..FormatOptions::default()
+        };
+        let doc = Doc::concat([Doc::nesting(|_| list(&["a", "b"])), Doc::text(" + 12345")]);
+        assert_eq!(doc.print(0, &options), "[\n\ta,\n\tb,\n] + 12345");
+    }
+
+    #[rstest]
+    #[case::hard_line(Doc::HardLine)]
+    #[case::text_with_newline(Doc::text("\n"))]
+    fn test_printing_group_with_hard_line_breaks(#[case] line: Doc<'static>) {
+        let doc = Doc::group(Doc::concat([
+            Doc::text("a"),
+            Doc::Line,
+            Doc::text("b"),
+            line,
+            Doc::text("c"),
+        ]));
+        assert!(doc
+            .print(0, &FormatOptions::default())
+            .starts_with("a\nb\n"));
+    }
+
+    #[rstest]
+    fn test_printing_blank_lines_without_indentation() {
+        let doc = Doc::indent(Doc::concat([
+            Doc::text("a"),
+            Doc::HardLine,
+            Doc::HardLine,
+            Doc::text("b"),
+            Doc::HardLine,
+        ]));
+        assert_eq!(doc.print(0, &FormatOptions::default()), "a\n\n\tb\n");
+    }
+
+    #[rstest]
+    fn test_printing_text_with_newlines_as_is() {
+        let doc = Doc::indent(Doc::concat([
+            Doc::HardLine,
+            Doc::text("a\n  b"),
+            Doc::HardLine,
+            Doc::text("c"),
+        ]));
+        assert_eq!(doc.print(0, &FormatOptions::default()), "\n\ta\n  b\n\tc");
+    }
+
+    #[rstest]
+    fn test_printing_flat() {
+        let options = FormatOptions {
+            line_width: 4,
+            ..FormatOptions::default()
+        };
+        let doc = list(&["one", "two", "three"]);
+        assert_eq!(doc.print_flat(&options), "[one, two, three]");
+    }
+
+    #[rstest]
+    fn test_printing_nesting_at_its_indent_level() {
+        let doc = Doc::indent(Doc::concat([
+            Doc::HardLine,
+            Doc::nesting(|indent_level| Doc::text(indent_level.to_string())),
+        ]));
+        assert_eq!(doc.print(2, &FormatOptions::default()), "\n\t\t\t3");
+    }
+}
@@ -0,0 +1,257 @@
+use crate::span::{line_col, Input};
+use std::{fmt, ops::Range, path::PathBuf};
+use winnow::{
+    error::{AddContext, ContextError, ErrMode, StrContext, StrContextValue},
+    stream::Stream,
+};
+
+/// A template that couldn't be parsed.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    /// The file the template came from, if it came from one
+    pub path: Option<PathBuf>,
+    /// Byte range of the source that couldn't be parsed
+    pub span: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+    /// What was being parsed, like `if` for an `{% if %}` block
+    pub label: Option<String>,
+    pub expected: Vec<String>,
+    pub found: String,
+    /// The whole line of source the error is on
+    source_line: String,
+}
+
+impl Error {
+    /// Creates an error from a failed parse of `source` that stopped at `offset`.
+    pub fn new(source: &str, offset: usize, error: &ContextError) -> Self {
+        let (line, column) = line_col(source, offset);
+        let rest = &source[offset..];
+        let token = token_at(rest);
+
+        let mut label = None;
+        let mut expected = vec![];
+        for context in error.context() {
+            match context {
+                StrContext::Label(name) => label = Some(name.to_string()),
+                StrContext::Expected(StrContextValue::StringLiteral(literal)) => {
+                    expected.push(format!("`{literal}`"))
+                }
+                StrContext::Expected(value) => expected.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = rest.find('\n').map_or(source.len(), |i| offset + i);
+
+        Self {
+            path: None,
+            span: offset..offset + token.len(),
+            line,
+            column,
+            label,
+            expected,
+            found: if token.is_empty() {
+                "end of file".to_string()
+            } else {
+                format!("`{token}`")
+            },
+            source_line: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        }
+    }
+
+    pub fn with_path(self, path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    /// A one line description of the error, like ``invalid if: expected `endif`, found end of
+    /// file``.
+    pub fn message(&self) -> String {
+        let found = match self.expected.as_slice() {
+            [] => format!("unexpected {}", self.found),
+            [expected] => format!("expected {expected}, found {}", self.found),
+            [expected @ .., last] => {
+                format!(
+                    "expected {} or {last}, found {}",
+                    expected.join(", "),
+                    self.found
+                )
+            }
+        };
+        match &self.label {
+            Some(label) => format!("invalid {label}: {found}"),
+            None => found,
+        }
+    }
+}
+
+/// The tag or word at the start of `input`, for pointing out what couldn't be parsed.
+pub fn token_at(input: &str) -> &str {
+    let end = if input.starts_with("{{") {
+        input.find("}}").map(|i| i + 2)
+    } else if input.starts_with("{%") {
+        input.find("%}").map(|i| i + 2)
+    } else if input.starts_with("{#") {
+        input.find("#}").map(|i| i + 2)
+    } else if input.starts_with('<') {
+        input.find('>').map(|i| i + 1)
+    } else if ["%}", "}}", "#}"].iter().any(|end| input.starts_with(end)) {
+        Some(2)
+    } else {
+        None
+    };
+
+    // Never point past the end of the line
+    let line_end = input.find('\n').unwrap_or(input.len());
+    let end = end.unwrap_or_else(|| {
+        input
+            .find(char::is_whitespace)
+            .unwrap_or(input.len())
+            .max(input.chars().next().map_or(0, char::len_utf8))
+    });
+    &input[..end.min(line_end)]
+}
+
+/// Turns a failure to parse the tag or variable at `start` into a cut error if it's closed by `end`
+/// on the same line, so that it's kept as it was written rather than being taken as text.
+pub fn cut_unparsed_token<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+    label: &'static str,
+    end: &'static str,
+    error: ErrMode<ContextError>,
+) -> ErrMode<ContextError> {
+    let ErrMode::Backtrack(_) = error else {
+        return error;
+    };
+    let mut token = *input;
+    token.reset(start);
+    if !token_at(&token).ends_with(end) {
+        return error;
+    }
+    let failed_at = input.checkpoint();
+    ErrMode::Cut(
+        ContextError::new()
+            .add_context(input, &failed_at, StrContext::Label(label))
+            .add_context(
+                input,
+                &failed_at,
+                StrContext::Expected(StrContextValue::StringLiteral(end)),
+            ),
+    )
+}
+
+impl fmt::Display for Error {
+    /// Displays the error along with the source it points at, like rustc does.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let location = match &self.path {
+            Some(path) => format!("{}:{}:{}", path.to_string_lossy(), self.line, self.column),
+            None => format!("{}:{}", self.line, self.column),
+        };
+        // Tabs are kept so the carets line up however wide they're displayed
+        let padding = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = self
+            .source_line
+            .chars()
+            .skip(self.column - 1)
+            .scan(self.span.len(), |remaining, c| {
+                (*remaining > 0).then(|| *remaining = remaining.saturating_sub(c.len_utf8()))
+            })
+            .count();
+        let carets = "^".repeat(width.max(1));
+
+        writeln!(f, "error: {}", self.message())?;
+        writeln!(f, "{gutter}--> {location}")?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.source_line)?;
+        write!(f, "{gutter} | {padding}{carets}")
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::template_parser::Template;
+
+    #[rstest]
+    #[case::unclosed("{% if a %}yes", 13..13, (1, 14), "invalid if: expected `elif`, `else` or `endif`, found end of file")]
+    #[case::mismatched(
+        "<div>\n\t{% for a in b %}{% endif %}\n</div>",
+        23..34,
+        (2, 18),
+        "invalid for: expected `empty` or `endfor`, found `{% endif %}`"
+    )]
+    #[case::unopened("a\nb {% endif %}", 4..15, (2, 3), "invalid block: expected a matching opening tag, found `{% endif %}`")]
+    #[case::stray_closing_tag(
+        "a</div>b",
+        1..7,
+        (1, 2),
+        "invalid element: expected a matching opening tag, found `</div>`"
+    )]
+    #[case::dangling_operator(
+        "{% if a and %}b{% endif %}",
+        12..14,
+        (1, 13),
+        "invalid condition: expected a value, found `%}`"
+    )]
+    #[case::missing_operator(
+        "{% if a %}{% elif a b %}{% endif %}",
+        20..21,
+        (1, 21),
+        "invalid condition: expected an operator, found `b`"
+    )]
+    fn test_parse_errors(
+        #[case] source: &str,
+        #[case] span: Range<usize>,
+        #[case] line_col: (usize, usize),
+        #[case] message: &str,
+    ) {
+        let error = Template::parse_source(source)
+            .unwrap()
+            .errors(source)
+            .remove(0);
+        assert_eq!(error.span, span);
+        assert_eq!((error.line, error.column), line_col);
+        assert_eq!(error.message(), message);
+    }
+
+    #[rstest]
+    #[case("x {% endif %}", None, "error: invalid block: expected a matching opening tag, found `{% endif %}`\n --> 1:3\n  |\n1 | x {% endif %}\n  |   ^^^^^^^^^^^")]
+    #[case(
+        "<p>\n\t\té {% if a %}",
+        Some("a.html"),
+        "error: invalid if: expected `elif`, `else` or `endif`, found end of file\n --> a.html:2:15\n  |\n2 | \t\té {% if a %}\n  | \t\t            ^"
+    )]
+    fn test_displaying_errors(
+        #[case] source: &str,
+        #[case] path: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        let mut error = Template::parse_source(source)
+            .unwrap()
+            .errors(source)
+            .remove(0);
+        if let Some(path) = path {
+            error = error.with_path(path.into());
+        }
+        assert_eq!(error.to_string(), expected);
+    }
+}
@@ -1,3 +1,220 @@
+use crate::doc::Doc;
+use serde::Deserialize;
+
 pub trait Formatable {
-    fn formatted(&self, indent_level: usize) -> String;
+    /// The document the value is printed from, describing where its lines can be broken.
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a>;
+
+    /// Prints the value as if it started `indent_level` levels deep, without indenting its first
+    /// line.
+    fn formatted(&self, indent_level: usize, options: &FormatOptions) -> String {
+        self.to_doc(options).print(indent_level, options)
+    }
+}
+
+/// How each level of indentation is written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+/// The quote character to wrap quoted values in. The other one is used for values that contain
+/// the preferred one.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteStyle {
+    #[serde(alias = "prefer-double")]
+    Double,
+    #[serde(alias = "prefer-single")]
+    Single,
+    /// Keep whichever quote the value was written with, or double quotes if it wasn't quoted
+    Preserve,
+}
+
+impl QuoteStyle {
+    /// The quote character to use for `value`, which was written in `original` quotes if it was
+    /// quoted, avoiding one that would end it early.
+    pub fn quote_for(&self, value: &str, original: Option<char>) -> char {
+        let (preferred, other) = match (self, original) {
+            (QuoteStyle::Single, _) | (QuoteStyle::Preserve, Some('\'')) => ('\'', '"'),
+            _ => ('"', '\''),
+        };
+        match (value.contains(preferred), value.contains(other), original) {
+            // Neither would do, so it must have been fine as it was
+            (true, true, Some(original)) => original,
+            (true, false, _) => other,
+            _ => preferred,
+        }
+    }
+}
+
+/// How elements without any content, like `<br>`, are written.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VoidStyle {
+    /// `<br />`
+    SelfClosing,
+    /// `<br>`, for the HTML void elements only. Other self-closed elements like `<div />` stay
+    /// self-closed, as there'd be no way to tell them apart from an unclosed element otherwise.
+    Bare,
+}
+
+/// Where the `>` or `/>` of an opening tag goes when its attributes are wrapped onto their own
+/// lines.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BracketPlacement {
+    /// On a line of its own, lined up with the start of the tag
+    NewLine,
+    /// At the end of the last attribute, like prettier's `bracketSameLine`
+    SameLine,
+}
+
+/// Which whitespace around elements affects how the page is shown, and so can't be changed. Like
+/// prettier's `htmlWhitespaceSensitivity`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WhitespaceSensitivity {
+    /// Whitespace around elements that are inline by default, like `<a>` and `<span>`
+    Css,
+    /// Whitespace around every element, as any of them could be styled to be inline
+    Strict,
+    /// None of it, so inline elements only stay within the text around them where they fit
+    Ignore,
+}
+
+/// How the `DOCTYPE` keyword is written in `<!DOCTYPE html>`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DoctypeCase {
+    Upper,
+    Lower,
+}
+
+/// Everything that can be configured about how templates are formatted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    pub indent_style: IndentStyle,
+    /// How many columns a tab takes up when fitting lines within the line width
+    pub tab_width: usize,
+    /// How long lines can get before they're wrapped, where they can be
+    pub line_width: usize,
+    /// The quotes around HTML attribute values
+    pub quote_style: QuoteStyle,
+    /// The quotes around string literals in template tags, variables and filter arguments
+    pub template_quote_style: QuoteStyle,
+    pub void_style: VoidStyle,
+    pub bracket_placement: BracketPlacement,
+    pub doctype_case: DoctypeCase,
+    pub whitespace_sensitivity: WhitespaceSensitivity,
+    /// The most blank lines to keep in a row within text, or `None` to keep them all
+    pub max_blank_lines: Option<usize>,
+    /// Whether the formatted file ends with a newline
+    pub final_newline: bool,
+    /// Whether closing tags that were left out, like `</li>` before another `<li>`, are written
+    pub insert_implied_end_tags: bool,
+    /// Whether `{# comments #}` that would go past the line width are turned into
+    /// `{% comment %}` blocks
+    pub long_comments_as_blocks: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_style: IndentStyle::Tabs,
+            tab_width: 4,
+            line_width: 120,
+            quote_style: QuoteStyle::Double,
+            template_quote_style: QuoteStyle::Preserve,
+            void_style: VoidStyle::SelfClosing,
+            bracket_placement: BracketPlacement::NewLine,
+            doctype_case: DoctypeCase::Upper,
+            whitespace_sensitivity: WhitespaceSensitivity::Css,
+            max_blank_lines: None,
+            final_newline: true,
+            insert_implied_end_tags: false,
+            long_comments_as_blocks: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// The whitespace at the start of a line nested `indent_level` levels deep.
+    pub fn indent(&self, indent_level: usize) -> String {
+        match self.indent_style {
+            IndentStyle::Tabs => "\t".repeat(indent_level),
+            IndentStyle::Spaces(width) => " ".repeat(width * indent_level),
+        }
+    }
+
+    /// The number of columns `text` takes up, with each tab counting as `tab_width`.
+    pub fn width(&self, text: &str) -> usize {
+        text.chars()
+            .map(|c| if c == '\t' { self.tab_width } else { 1 })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(IndentStyle::Tabs, 2, "\t\t")]
+    #[case(IndentStyle::Spaces(2), 0, "")]
+    #[case(IndentStyle::Spaces(2), 3, "      ")]
+    #[case(IndentStyle::Spaces(4), 1, "    ")]
+    fn test_indent(
+        #[case] indent_style: IndentStyle,
+        #[case] level: usize,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            indent_style,
+            ..FormatOptions::default()
+        };
+        assert_eq!(options.indent(level), expected);
+    }
+
+    #[rstest]
+    #[case(IndentStyle::Tabs, 4, "\t\ta", 9)]
+    #[case(IndentStyle::Tabs, 2, "\t\ta", 5)]
+    #[case(IndentStyle::Spaces(2), 4, "  é", 3)]
+    fn test_width(
+        #[case] indent_style: IndentStyle,
+        #[case] tab_width: usize,
+        #[case] text: &str,
+        #[case] expected: usize,
+    ) {
+        let options = FormatOptions {
+            indent_style,
+            tab_width,
+            ..FormatOptions::default()
+        };
+        assert_eq!(options.width(text), expected);
+    }
+
+    #[rstest]
+    #[case(QuoteStyle::Double, "a", None, '"')]
+    #[case(QuoteStyle::Double, "a", Some('\''), '"')]
+    #[case(QuoteStyle::Double, "say \"hi\"", None, '\'')]
+    #[case(QuoteStyle::Single, "a", None, '\'')]
+    #[case(QuoteStyle::Single, "it's", None, '"')]
+    #[case(QuoteStyle::Preserve, "a", None, '"')]
+    #[case(QuoteStyle::Preserve, "a", Some('\''), '\'')]
+    #[case(QuoteStyle::Preserve, "a", Some('"'), '"')]
+    #[case(QuoteStyle::Preserve, "it's", Some('\''), '"')]
+    #[case(QuoteStyle::Double, "say \"it's\"", Some('\''), '\'')]
+    fn test_quote_for(
+        #[case] style: QuoteStyle,
+        #[case] value: &str,
+        #[case] original: Option<char>,
+        #[case] expected: char,
+    ) {
+        assert_eq!(style.quote_for(value, original), expected);
+    }
 }
@@ -1,48 +1,305 @@
-use std::collections::HashMap;
+use super::node::Node;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable, QuoteStyle};
+use crate::span::{Equivalent, Input, Span, Spanned};
+use crate::template_parser::{block::Block, comment::Comment, tag::Tag, variable::VariableTag};
 use std::iter::Iterator;
 use winnow::{
     ascii::{multispace0, multispace1},
     combinator::{alt, delimited, opt, separated, separated_pair},
+    error::ErrMode,
+    stream::{Location, Stream},
     token::take_while,
     PResult, Parser,
 };
 
 const INVALID_ATTRIBUTE_CHARS: &[char] = &['>', '<', '/', '=', '"', '\'', ' ', '\t', '\n', '\r'];
 const INVALID_UNQUOTED_ATTRIBUTE_CHARS: &[char] = INVALID_ATTRIBUTE_CHARS;
-const INVALID_DOUBLE_QUOTED_ATTRIBUTE_CHARS: &[char] = &['"'];
-const INVALID_SINGLE_QUOTED_ATTRIBUTE_CHARS: &[char] = &['\''];
+
+/// The value of an HTML attribute, which can contain template syntax like
+/// `href="{% url 'home' %}"`.
+#[derive(Debug, PartialEq)]
+pub struct AttributeValue<'i> {
+    /// The value as it was written, without its quotes
+    pub raw: &'i str,
+    /// The quote the value was wrapped in, if any
+    pub quote: Option<char>,
+    pub nodes: Vec<Node<'i>>,
+}
+
+/// The raw value and its quotes don't matter, so `{%url 'a'%}` and `{% url 'a' %}` are the same
+/// value. Neither does how text is split between nodes.
+impl Equivalent for AttributeValue<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        value_parts(&self.nodes).is_equivalent(&value_parts(&other.nodes))
+    }
+}
+
+/// A piece of an attribute value, with neighbouring text merged into one.
+enum ValuePart<'a, 'i> {
+    Text(String),
+    Node(&'a Node<'i>),
+}
+
+impl Equivalent for ValuePart<'_, '_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ValuePart::Text(text), ValuePart::Text(other)) => text == other,
+            (ValuePart::Node(node), ValuePart::Node(other)) => node.is_equivalent(other),
+            _ => false,
+        }
+    }
+}
+
+fn value_parts<'a, 'i>(nodes: &'a [Node<'i>]) -> Vec<ValuePart<'a, 'i>> {
+    let mut parts = vec![];
+    for node in nodes {
+        match (node, parts.last_mut()) {
+            (Node::Text(text), Some(ValuePart::Text(merged))) => merged.push_str(text.value),
+            (Node::Text(text), _) => parts.push(ValuePart::Text(text.value.to_string())),
+            (node, _) => parts.push(ValuePart::Node(node)),
+        }
+    }
+    parts
+}
+
+impl<'i> AttributeValue<'i> {
+    /// Tidies up the value of a `class` attribute: classes are separated by single spaces, and
+    /// only the first of any repeated class is kept, then they're put in `order`. Template syntax
+    /// is part of the class it's written against, like `btn-{{ size }}`, and is never moved, so
+    /// only the plain classes between two pieces of template syntax are sorted.
+    pub fn normalize_classes(&mut self, order: &ClassOrder) {
+        let mut classes: Vec<Vec<Node<'i>>> = vec![];
+        let mut class = vec![];
+        for node in std::mem::take(&mut self.nodes) {
+            let Node::Text(text) = node else {
+                class.push(node);
+                continue;
+            };
+            for (i, part) in text
+                .value
+                .split(|c: char| c.is_ascii_whitespace())
+                .enumerate()
+            {
+                if i > 0 && !class.is_empty() {
+                    classes.push(std::mem::take(&mut class));
+                }
+                if !part.is_empty() {
+                    let start =
+                        text.span.start + (part.as_ptr() as usize - text.value.as_ptr() as usize);
+                    class.push(Node::Text(Spanned {
+                        value: part,
+                        span: (start..start + part.len()).into(),
+                    }));
+                }
+            }
+        }
+        if !class.is_empty() {
+            classes.push(class);
+        }
+
+        let mut unique: Vec<Vec<Node<'i>>> = vec![];
+        for class in classes {
+            if !unique.iter().any(|seen| seen.is_equivalent(&class)) {
+                unique.push(class);
+            }
+        }
+        for plain in
+            unique.split_mut(|class| class.iter().any(|node| !matches!(node, Node::Text(_))))
+        {
+            order.sort(plain);
+        }
+
+        for (i, class) in unique.into_iter().enumerate() {
+            if i > 0 {
+                self.nodes.push(Node::Text(" ".into()));
+            }
+            self.nodes.extend(class);
+        }
+    }
+}
+
+impl<'i> From<&'i str> for AttributeValue<'i> {
+    fn from(raw: &'i str) -> Self {
+        let nodes = if raw.is_empty() {
+            vec![]
+        } else {
+            vec![Node::Text(raw.into())]
+        };
+        Self {
+            raw,
+            quote: None,
+            nodes,
+        }
+    }
+}
+
+impl<'i> Formatable for AttributeValue<'i> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        inline_nodes_doc(&self.nodes, options)
+    }
+}
+
+/// The nodes without any whitespace around them, as whitespace is significant within attribute
+/// values.
+fn inline_nodes_doc<'a>(nodes: &'a [Node], options: &'a FormatOptions) -> Doc<'a> {
+    Doc::concat(nodes.iter().map(|node| match node {
+        Node::Text(text) => Doc::text(text.value),
+        Node::Block(block) => {
+            let mut docs = vec![
+                block.opening_tag.to_doc(options),
+                inline_nodes_doc(&block.children, options),
+            ];
+            for branch in &block.branches {
+                docs.push(branch.tag.to_doc(options));
+                docs.push(inline_nodes_doc(&branch.children, options));
+            }
+            docs.push(block.closing_tag.to_doc(options));
+            Doc::concat(docs)
+        }
+        _ => node.content_doc(options),
+    }))
+}
 
 /// Parse the key of a HTML attribute
-fn parse_key<'i>(input: &mut &'i str) -> PResult<&'i str> {
+fn parse_key<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
     take_while(1.., |c: char| !INVALID_ATTRIBUTE_CHARS.contains(&c)).parse_next(input)
 }
 
 /// Parses an HTML attribute value that is not quoted.
-fn parse_unquoted_val<'i>(input: &mut &'i str) -> PResult<&'i str> {
+fn parse_unquoted_val<'i>(input: &mut Input<'i>) -> PResult<AttributeValue<'i>> {
     take_while(1.., |c: char| {
         !INVALID_UNQUOTED_ATTRIBUTE_CHARS.contains(&c)
     })
+    .with_span()
+    .map(|(raw, span)| AttributeValue {
+        raw,
+        quote: None,
+        nodes: vec![Node::Text(Spanned {
+            value: raw,
+            span: span.into(),
+        })],
+    })
     .parse_next(input)
 }
 
 /// Parse the value of an HTML attribute
-fn parse_double_quoted_val<'i>(input: &mut &'i str) -> PResult<&'i str> {
-    let inner = take_while(0.., |c: char| {
-        !INVALID_DOUBLE_QUOTED_ATTRIBUTE_CHARS.contains(&c)
-    });
-    delimited('"', inner, '"').parse_next(input)
+fn parse_double_quoted_val<'i>(input: &mut Input<'i>) -> PResult<AttributeValue<'i>> {
+    parse_quoted_val('"', input)
+}
+
+fn parse_single_quoted_val<'i>(input: &mut Input<'i>) -> PResult<AttributeValue<'i>> {
+    parse_quoted_val('\'', input)
 }
 
-fn parse_single_quoted_val<'i>(input: &mut &'i str) -> PResult<&'i str> {
-    let inner = take_while(0.., |c: char| {
-        !INVALID_SINGLE_QUOTED_ATTRIBUTE_CHARS.contains(&c)
-    });
-    delimited('\'', inner, '\'').parse_next(input)
+fn parse_quoted_val<'i>(mut quote: char, input: &mut Input<'i>) -> PResult<AttributeValue<'i>> {
+    quote.parse_next(input)?;
+    let start = *input;
+    let nodes = parse_quoted_nodes(quote, input)?;
+    let raw = &start[..start.len() - input.len()];
+    quote.parse_next(input)?;
+    Ok(AttributeValue {
+        raw,
+        quote: Some(quote),
+        nodes,
+    })
+}
+
+/// Parses the nodes in a quoted attribute value up until the closing quote. Quotes within
+/// template syntax don't end the value.
+fn parse_quoted_nodes<'i>(quote: char, input: &mut Input<'i>) -> PResult<Vec<Node<'i>>> {
+    let mut nodes = vec![];
+
+    while !input.is_empty() && !input.starts_with(quote) {
+        let start = input.checkpoint();
+
+        if let Ok((value, span)) = Comment::parse.with_span().parse_next(input) {
+            nodes.push(Node::TemplateComment(Spanned {
+                value,
+                span: span.into(),
+            }));
+            continue;
+        }
+        input.reset(&start);
+
+        match VariableTag::parse.parse_next(input) {
+            Ok(variable) => {
+                nodes.push(Node::Variable(variable));
+                continue;
+            }
+            Err(ErrMode::Cut(error)) => return Err(ErrMode::Cut(error)),
+            Err(_) => input.reset(&start),
+        }
+
+        let tag = match Tag::parse.parse_next(input) {
+            Ok(tag) => Some(tag),
+            Err(ErrMode::Cut(error)) => return Err(ErrMode::Cut(error)),
+            Err(_) => None,
+        };
+        if let Some(tag) = tag {
+            let tags = input.state;
+            if tags.is_boundary(tag.tag_type) {
+                input.reset(&start);
+                break;
+            }
+            match tags.find(tag.tag_type) {
+                Some(definition) => {
+                    let parse_children = |input: &mut Input<'i>| parse_quoted_nodes(quote, input);
+                    let block = Block::parse_rest(tag, definition, parse_children, input)?;
+                    nodes.push(Node::Block(block));
+                }
+                None => nodes.push(Node::Tag(tag)),
+            }
+            continue;
+        }
+        input.reset(&start);
+
+        // Anything at the very start has already failed to parse as template syntax
+        let end = input
+            .char_indices()
+            .find(|&(i, c)| {
+                i > 0
+                    && (c == quote || ["{{", "{%", "{#"].iter().any(|s| input[i..].starts_with(s)))
+            })
+            .map_or(input.len(), |(i, _)| i);
+        let text_start = input.location();
+        let value = input.next_slice(end);
+        nodes.push(Node::Text(Spanned {
+            value,
+            span: (text_start..input.location()).into(),
+        }));
+    }
+
+    Ok(nodes)
+}
+
+/// An HTML attribute, like `key="val"` or just `key`.
+#[derive(Debug, PartialEq)]
+pub struct Attribute<'i> {
+    pub key: &'i str,
+    pub value: Option<AttributeValue<'i>>,
+    pub span: Span,
+}
+
+impl Equivalent for Attribute<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.key == other.key && self.value.is_equivalent(&other.value)
+    }
+}
+
+impl<'i> From<(&'i str, Option<AttributeValue<'i>>)> for Attribute<'i> {
+    fn from((key, value): (&'i str, Option<AttributeValue<'i>>)) -> Self {
+        Self {
+            key,
+            value,
+            span: Span::default(),
+        }
+    }
 }
 
 /// Parses an HTML attribute.
 /// Looks something like `key="val"`.
-fn parse_attribute<'i>(input: &mut &'i str) -> PResult<(&'i str, Option<&'i str>)> {
+fn parse_attribute<'i>(input: &mut Input<'i>) -> PResult<Attribute<'i>> {
     separated_pair(
         parse_key,
         opt(delimited(multispace0, '=', multispace0)),
@@ -52,55 +309,600 @@ fn parse_attribute<'i>(input: &mut &'i str) -> PResult<(&'i str, Option<&'i str>
             parse_unquoted_val,
         ))),
     )
+    .with_span()
+    .map(|((key, value), span)| Attribute {
+        key,
+        value,
+        span: span.into(),
+    })
     .parse_next(input)
 }
 
-/// HTML attributes
-#[derive(Debug)]
+/// HTML attributes, in the order they were written
+#[derive(Debug, Default, PartialEq)]
 pub struct Attributes<'i> {
-    pub kvs: HashMap<&'i str, Option<&'i str>>,
+    pub kvs: Vec<Attribute<'i>>,
 }
 
-impl<'i> Default for Attributes<'i> {
-    fn default() -> Self {
-        let kvs: HashMap<&'i str, Option<&'i str>> = HashMap::default();
-        Attributes { kvs }
+impl Equivalent for Attributes<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.kvs.is_equivalent(&other.kvs)
     }
 }
 
-impl<'i> PartialEq for Attributes<'i> {
-    fn eq(&self, other: &Self) -> bool {
-        self.kvs == other.kvs
+impl<'i> FromIterator<(&'i str, Option<AttributeValue<'i>>)> for Attributes<'i> {
+    fn from_iter<T: IntoIterator<Item = (&'i str, Option<AttributeValue<'i>>)>>(iter: T) -> Self {
+        Self {
+            kvs: iter.into_iter().map(Attribute::from).collect(),
+        }
     }
 }
 
 impl<'i> Attributes<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
         let kvs = separated(0.., parse_attribute, multispace1).parse_next(input)?;
         Ok(Self { kvs })
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&'i str, &Option<&'i str>)> {
-        self.kvs.iter().map(|(&k, v)| (k, v))
+    pub fn iter(&self) -> impl Iterator<Item = (&'i str, &Option<AttributeValue<'i>>)> {
+        self.kvs
+            .iter()
+            .map(|attribute| (attribute.key, &attribute.value))
     }
 
-    pub fn insert(&mut self, key: &'i str, value: Option<&'i str>) {
-        self.kvs.insert(key, value);
+    /// Sets the value of an attribute, adding it to the end if it doesn't exist yet.
+    pub fn insert(&mut self, key: &'i str, value: Option<AttributeValue<'i>>) {
+        match self.kvs.iter_mut().find(|attribute| attribute.key == key) {
+            Some(existing) => existing.value = value,
+            None => self.kvs.push((key, value).into()),
+        }
+    }
+
+    pub fn get(&self, key: &'i str) -> Option<&AttributeValue<'i>> {
+        let attribute = self.kvs.iter().find(|attribute| attribute.key == key)?;
+        attribute.value.as_ref()
+    }
+
+    pub fn pop(&mut self, key: &'i str) -> Option<AttributeValue<'i>> {
+        let index = self.kvs.iter().position(|attribute| attribute.key == key)?;
+        self.kvs.remove(index).value
+    }
+
+    /// The attributes whose key has already been set earlier in the tag.
+    pub fn duplicates(&self) -> Vec<&Attribute<'i>> {
+        self.kvs
+            .iter()
+            .enumerate()
+            .filter(|(i, attribute)| {
+                self.kvs[..*i]
+                    .iter()
+                    .any(|earlier| earlier.key == attribute.key)
+            })
+            .map(|(_, attribute)| attribute)
+            .collect()
+    }
+
+    /// Removes duplicate attributes, keeping the first occurrence like browsers do.
+    pub fn dedup(&mut self) {
+        let mut seen = vec![];
+        self.kvs.retain(|attribute| {
+            let duplicate = seen.contains(&attribute.key);
+            seen.push(attribute.key);
+            !duplicate
+        });
     }
 
-    pub fn get(&self, key: &'i str) -> Option<&'i str> {
-        let value = self.kvs.get(key)?;
-        if let Some(value) = value {
-            return Some(value);
+    /// Reorders the attributes according to `order`. Attributes that compare equal keep their
+    /// relative order.
+    pub fn sort(&mut self, order: &AttributeOrder) {
+        match order {
+            AttributeOrder::Source => {}
+            AttributeOrder::Groups(groups) => self.kvs.sort_by_key(|attribute| {
+                (
+                    group_index(groups, attribute.key),
+                    attribute.key.to_ascii_lowercase(),
+                )
+            }),
         }
-        None
     }
-    pub fn pop(&mut self, key: &'i str) -> Option<&'i str> {
-        let value = self.kvs.remove(key)?;
-        if let Some(value) = value {
-            return Some(value);
+}
+
+/// The order to put attributes in when formatting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum AttributeOrder {
+    /// Keep attributes in the order they were written
+    #[default]
+    Source,
+    /// Put attributes in the order of the first group they match, then alphabetically. Groups are
+    /// either an attribute name like `id`, or a prefix ending in `*` like `data-*`. Attributes
+    /// that don't match any group go last.
+    Groups(Vec<String>),
+}
+
+impl AttributeOrder {
+    /// `id` and `class` first, then `name`, `data-*` and `aria-*`, then everything else
+    /// alphabetically.
+    pub fn conventional() -> Self {
+        Self::Groups(
+            ["id", "class", "name", "data-*", "aria-*"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        )
+    }
+}
+
+fn group_index(groups: &[String], key: &str) -> usize {
+    let key = key.to_ascii_lowercase();
+    groups
+        .iter()
+        .position(|group| match group.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == *group,
+        })
+        .unwrap_or(groups.len())
+}
+
+/// The order to put the classes in a `class` attribute in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ClassOrder {
+    /// Keep classes in the order they were written
+    #[default]
+    Source,
+    Alphabetical,
+    /// The order Tailwind's Prettier plugin uses. Classes that aren't Tailwind utilities go
+    /// first, then classes without variants like `hover:` before those with them, in the order
+    /// of their last variant in `variants`, and then of the utility they match. Utilities are
+    /// either a class like `flex`, or a prefix like `bg` that matches `bg-red-500`, and the
+    /// longest match wins.
+    Tailwind {
+        variants: Vec<String>,
+        utilities: Vec<String>,
+    },
+}
+
+impl ClassOrder {
+    /// Tailwind's own variant and utility order.
+    pub fn tailwind() -> Self {
+        Self::Tailwind {
+            variants: TAILWIND_VARIANTS.iter().map(|v| v.to_string()).collect(),
+            utilities: TAILWIND_UTILITIES.iter().map(|u| u.to_string()).collect(),
+        }
+    }
+
+    /// Sorts classes made up of only text, keeping classes that compare equal in their order.
+    fn sort(&self, classes: &mut [Vec<Node>]) {
+        let text = |class: &Vec<Node>| -> String {
+            class
+                .iter()
+                .filter_map(|node| match node {
+                    Node::Text(text) => Some(text.value),
+                    _ => None,
+                })
+                .collect()
+        };
+        match self {
+            Self::Source => {}
+            Self::Alphabetical => classes.sort_by_cached_key(text),
+            Self::Tailwind {
+                variants,
+                utilities,
+            } => {
+                classes.sort_by_cached_key(|class| tailwind_key(&text(class), variants, utilities))
+            }
+        }
+    }
+}
+
+/// Where a class goes in Tailwind's order, with classes that aren't Tailwind utilities first.
+fn tailwind_key(class: &str, variants: &[String], utilities: &[String]) -> Option<(usize, usize)> {
+    let mut parts: Vec<&str> = class.split(':').collect();
+    let utility = parts.pop()?;
+    let utility = utility.strip_prefix('!').unwrap_or(utility);
+    let utility = utility.strip_prefix('-').unwrap_or(utility);
+    let utility_index = utilities
+        .iter()
+        .enumerate()
+        .filter(|(_, prefix)| {
+            utility == prefix.as_str()
+                || utility
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.starts_with('-') || rest.starts_with('['))
+        })
+        .max_by_key(|(i, prefix)| (prefix.len(), std::cmp::Reverse(*i)))?
+        .0;
+    let variant_index = parts
+        .iter()
+        .map(|variant| {
+            variants
+                .iter()
+                .position(|known| match known.strip_suffix('*') {
+                    Some(prefix) => variant.starts_with(prefix),
+                    None => variant == known,
+                })
+                .unwrap_or(variants.len())
+                + 1
+        })
+        .max()
+        .unwrap_or(0);
+    Some((variant_index, utility_index))
+}
+
+/// Tailwind's variants, in the order it generates them.
+pub const TAILWIND_VARIANTS: &[&str] = &[
+    "first-letter",
+    "first-line",
+    "marker",
+    "selection",
+    "file",
+    "placeholder",
+    "backdrop",
+    "before",
+    "after",
+    "first",
+    "last",
+    "only",
+    "odd",
+    "even",
+    "first-of-type",
+    "last-of-type",
+    "only-of-type",
+    "visited",
+    "target",
+    "open",
+    "default",
+    "checked",
+    "indeterminate",
+    "placeholder-shown",
+    "autofill",
+    "optional",
+    "required",
+    "valid",
+    "invalid",
+    "in-range",
+    "out-of-range",
+    "read-only",
+    "empty",
+    "focus-within",
+    "hover",
+    "focus",
+    "focus-visible",
+    "active",
+    "enabled",
+    "disabled",
+    "has-*",
+    "group-*",
+    "peer-*",
+    "aria-*",
+    "data-*",
+    "rtl",
+    "ltr",
+    "motion-safe",
+    "motion-reduce",
+    "dark",
+    "print",
+    "portrait",
+    "landscape",
+    "contrast-more",
+    "contrast-less",
+    "sm",
+    "md",
+    "lg",
+    "xl",
+    "2xl",
+    "max-*",
+    "min-*",
+];
+
+/// Tailwind's utilities, or their prefixes, in the order it generates them.
+pub const TAILWIND_UTILITIES: &[&str] = &[
+    "container",
+    "sr-only",
+    "not-sr-only",
+    "pointer-events",
+    "visible",
+    "invisible",
+    "collapse",
+    "static",
+    "fixed",
+    "absolute",
+    "relative",
+    "sticky",
+    "inset",
+    "inset-x",
+    "inset-y",
+    "start",
+    "end",
+    "top",
+    "right",
+    "bottom",
+    "left",
+    "isolate",
+    "isolation",
+    "z",
+    "order",
+    "col",
+    "col-span",
+    "col-start",
+    "col-end",
+    "row",
+    "row-span",
+    "row-start",
+    "row-end",
+    "float",
+    "clear",
+    "m",
+    "mx",
+    "my",
+    "ms",
+    "me",
+    "mt",
+    "mr",
+    "mb",
+    "ml",
+    "box",
+    "line-clamp",
+    "block",
+    "inline-block",
+    "inline",
+    "flex",
+    "inline-flex",
+    "table",
+    "inline-table",
+    "table-caption",
+    "table-cell",
+    "table-column",
+    "table-row",
+    "flow-root",
+    "grid",
+    "inline-grid",
+    "contents",
+    "list-item",
+    "hidden",
+    "aspect",
+    "size",
+    "h",
+    "max-h",
+    "min-h",
+    "w",
+    "min-w",
+    "max-w",
+    "flex-1",
+    "flex-auto",
+    "flex-initial",
+    "flex-none",
+    "shrink",
+    "grow",
+    "basis",
+    "table-auto",
+    "table-fixed",
+    "caption",
+    "border-collapse",
+    "border-separate",
+    "border-spacing",
+    "origin",
+    "translate-x",
+    "translate-y",
+    "rotate",
+    "skew-x",
+    "skew-y",
+    "scale",
+    "scale-x",
+    "scale-y",
+    "transform",
+    "transform-cpu",
+    "transform-gpu",
+    "transform-none",
+    "animate",
+    "cursor",
+    "touch",
+    "select",
+    "resize",
+    "snap",
+    "scroll-m",
+    "scroll-p",
+    "list",
+    "appearance",
+    "columns",
+    "break-before",
+    "break-inside",
+    "break-after",
+    "auto-cols",
+    "grid-flow",
+    "auto-rows",
+    "grid-cols",
+    "grid-rows",
+    "flex-row",
+    "flex-row-reverse",
+    "flex-col",
+    "flex-col-reverse",
+    "flex-wrap",
+    "flex-wrap-reverse",
+    "flex-nowrap",
+    "place-content",
+    "place-items",
+    "content",
+    "items",
+    "justify",
+    "justify-items",
+    "gap",
+    "gap-x",
+    "gap-y",
+    "space-x",
+    "space-y",
+    "divide-x",
+    "divide-y",
+    "divide",
+    "place-self",
+    "self",
+    "justify-self",
+    "overflow",
+    "overscroll",
+    "scroll",
+    "truncate",
+    "text-ellipsis",
+    "text-clip",
+    "hyphens",
+    "whitespace",
+    "text-wrap",
+    "text-nowrap",
+    "text-balance",
+    "text-pretty",
+    "break",
+    "rounded",
+    "border",
+    "border-x",
+    "border-y",
+    "border-s",
+    "border-e",
+    "border-t",
+    "border-r",
+    "border-b",
+    "border-l",
+    "border-solid",
+    "border-dashed",
+    "border-dotted",
+    "border-double",
+    "border-hidden",
+    "border-none",
+    "bg",
+    "bg-fixed",
+    "bg-local",
+    "bg-scroll",
+    "bg-clip",
+    "bg-origin",
+    "bg-none",
+    "bg-gradient",
+    "from",
+    "via",
+    "to",
+    "decoration-slice",
+    "decoration-clone",
+    "fill",
+    "stroke",
+    "object",
+    "p",
+    "px",
+    "py",
+    "ps",
+    "pe",
+    "pt",
+    "pr",
+    "pb",
+    "pl",
+    "text-left",
+    "text-center",
+    "text-right",
+    "text-justify",
+    "text-start",
+    "text-end",
+    "indent",
+    "align",
+    "font",
+    "text-xs",
+    "text-sm",
+    "text-base",
+    "text-lg",
+    "text-xl",
+    "text-2xl",
+    "text-3xl",
+    "text-4xl",
+    "text-5xl",
+    "text-6xl",
+    "text-7xl",
+    "text-8xl",
+    "text-9xl",
+    "uppercase",
+    "lowercase",
+    "capitalize",
+    "normal-case",
+    "italic",
+    "not-italic",
+    "normal-nums",
+    "ordinal",
+    "slashed-zero",
+    "lining-nums",
+    "oldstyle-nums",
+    "proportional-nums",
+    "tabular-nums",
+    "diagonal-fractions",
+    "stacked-fractions",
+    "leading",
+    "tracking",
+    "text",
+    "underline",
+    "overline",
+    "line-through",
+    "no-underline",
+    "decoration",
+    "underline-offset",
+    "antialiased",
+    "subpixel-antialiased",
+    "placeholder",
+    "caret",
+    "accent",
+    "opacity",
+    "bg-blend",
+    "mix-blend",
+    "shadow",
+    "outline",
+    "outline-none",
+    "outline-offset",
+    "ring",
+    "ring-inset",
+    "ring-offset",
+    "blur",
+    "brightness",
+    "contrast",
+    "drop-shadow",
+    "grayscale",
+    "hue-rotate",
+    "invert",
+    "saturate",
+    "sepia",
+    "filter",
+    "backdrop-blur",
+    "backdrop-brightness",
+    "backdrop-contrast",
+    "backdrop-grayscale",
+    "backdrop-hue-rotate",
+    "backdrop-invert",
+    "backdrop-opacity",
+    "backdrop-saturate",
+    "backdrop-sepia",
+    "backdrop-filter",
+    "transition",
+    "delay",
+    "duration",
+    "ease",
+    "will-change",
+    "forced-color-adjust",
+];
+
+/// Formats an attribute like `key="value"`, using the other quote character if the value contains
+/// the preferred one. A value with both keeps its original quote, along with the quotes of any
+/// template strings in it, as changing those could put the attribute's quote within the value.
+pub fn format_attribute(
+    key: &str,
+    value: Option<&AttributeValue>,
+    options: &FormatOptions,
+) -> String {
+    match value {
+        Some(value) => {
+            let quote_char = value.quote;
+            let mut formatted = value.formatted(0, options);
+            if formatted.contains('"') && formatted.contains('\'') {
+                let options = FormatOptions {
+                    template_quote_style: QuoteStyle::Preserve,
+                    ..options.clone()
+                };
+                formatted = value.formatted(0, &options);
+            }
+            let value = formatted;
+            let quote = options.quote_style.quote_for(&value, quote_char);
+            format!("{key}={quote}{value}{quote}")
         }
-        None
+        None => key.to_string(),
     }
 }
 
@@ -110,12 +912,13 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case("width", "width")]
     #[case("my-class", "my-class")]
     fn test_key(#[case] input: &str, #[case] expected: &str) {
-        let actual = parse_key.parse(input).unwrap();
+        let actual = parse_key.parse(new_input(input)).unwrap();
         assert_eq!(actual, expected)
     }
 
@@ -124,8 +927,8 @@ mod tests {
     #[case("'40'", "40")]
     #[case("'hello world'", "hello world")]
     fn test_parsing_single_quoted_val(#[case] input: &str, #[case] expected: &str) {
-        let actual = parse_single_quoted_val.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = parse_single_quoted_val.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &AttributeValue::from(expected))
     }
 
     #[rstest]
@@ -133,59 +936,216 @@ mod tests {
     #[case("\"40\"", "40")]
     #[case("\"hello world\"", "hello world")]
     fn test_parsing_double_quoted_val(#[case] input: &str, #[case] expected: &str) {
-        let actual = parse_double_quoted_val.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = parse_double_quoted_val.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &AttributeValue::from(expected))
+    }
+
+    #[rstest]
+    #[case("\"{% url \"home\" %}\"", "{% url \"home\" %}", "{% url \"home\" %}")]
+    #[case(
+        "'{%static    \"a.css\"%}'",
+        "{%static    \"a.css\"%}",
+        "{% static \"a.css\" %}"
+    )]
+    #[case("\"btn {{ kind }}\"", "btn {{ kind }}", "btn {{ kind }}")]
+    #[case(
+        "\"btn {%if active%}active{% else %}{{ other }}{% endif %}\"",
+        "btn {%if active%}active{% else %}{{ other }}{% endif %}",
+        "btn {% if active %}active{% else %}{{ other }}{% endif %}"
+    )]
+    fn test_parsing_val_with_template_syntax(
+        #[case] input: &str,
+        #[case] expected_raw: &str,
+        #[case] expected_formatted: &str,
+    ) {
+        let actual = alt((parse_double_quoted_val, parse_single_quoted_val))
+            .parse(new_input(input))
+            .unwrap();
+        assert_eq!(actual.raw, expected_raw);
+        assert_eq!(
+            actual.formatted(0, &FormatOptions::default()),
+            expected_formatted
+        );
+    }
+
+    #[rstest]
+    #[case("width", None, "width")]
+    #[case("width", Some("40"), "width=\"40\"")]
+    #[case(
+        "href",
+        Some("{% static \"a.css\" %}"),
+        "href='{% static \"a.css\" %}'"
+    )]
+    fn test_formatting_attribute(
+        #[case] key: &str,
+        #[case] value: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        let value = value.map(AttributeValue::from);
+        assert_eq!(
+            format_attribute(key, value.as_ref(), &FormatOptions::default()),
+            expected
+        );
     }
 
     #[rstest]
-    #[case("width", Attributes {
-        kvs: [("width", None)].into_iter().collect(),
-    })]
-    #[case("-1width", Attributes {
-        kvs: [("-1width", None)].into_iter().collect(),
-    })]
-    #[case("1width", Attributes {
-        kvs: [("1width", None)].into_iter().collect(),
-    })]
-    #[case("1-width", Attributes {
-        kvs: [("1-width", None)].into_iter().collect(),
-    })]
-    #[case("width=\"40\"", Attributes {
-        kvs: [("width", Some("40"))].into_iter().collect(),
-    })]
-    #[case("width   =    \"40\"", Attributes {
-        kvs: [("width", Some("40"))].into_iter().collect(),
-    })]
-    #[case("value=yes", Attributes {
-        kvs: [("value", Some("yes"))].into_iter().collect(),
-    })]
-    #[case("width=\"40\"", Attributes {
-        kvs: [("width", Some("40"))].into_iter().collect(),
-    })]
-    #[case("width=\"40\" height=\"30\"", Attributes {
-        kvs: [("width", Some("40")), ("height", Some("30"))]
-            .into_iter()
-            .collect(),
-    })]
-    #[case("width=\"40\" height=\"30\" class=\"my-class\"", Attributes {
-        kvs: [("width", Some("40")), ("height", Some("30")), ("class", Some("my-class"))]
-            .into_iter()
-            .collect(),
-    })]
-    #[case("key  =    value key-here1  =    value123   width=\"40\" length='40' height=\"30\" class=\"my-class\"", Attributes {
-        kvs: [
-            ("key", Some("value")),
-            ("key-here1", Some("value123")),
-            ("width", Some("40")),
-            ("length", Some("40")),
-            ("height", Some("30")),
-            ("class", Some("my-class")),
-        ]
-            .into_iter()
-            .collect(),
-    })]
+    #[case::preserve_double("\"40\"", QuoteStyle::Preserve, "width=\"40\"")]
+    #[case::preserve_single("'40'", QuoteStyle::Preserve, "width='40'")]
+    #[case::preserve_unquoted("40", QuoteStyle::Preserve, "width=\"40\"")]
+    #[case::prefer_double("'40'", QuoteStyle::Double, "width=\"40\"")]
+    #[case::prefer_single("\"40\"", QuoteStyle::Single, "width='40'")]
+    #[case::avoid_inner_quote("'{% url \"a\" %}'", QuoteStyle::Double, "width='{% url \"a\" %}'")]
+    #[case::both_quotes_in_template_syntax(
+        "'a\"b{% url \"q\" %}'",
+        QuoteStyle::Single,
+        "width='a\"b{% url \"q\" %}'"
+    )]
+    fn test_formatting_attribute_quotes(
+        #[case] input: &str,
+        #[case] quote_style: QuoteStyle,
+        #[case] expected: &str,
+    ) {
+        let value = alt((
+            parse_double_quoted_val,
+            parse_single_quoted_val,
+            parse_unquoted_val,
+        ))
+        .parse(new_input(input))
+        .unwrap();
+        // Template strings prefer the same quote, which can leave a value with both
+        let options = FormatOptions {
+            quote_style,
+            template_quote_style: quote_style,
+            ..FormatOptions::default()
+        };
+        assert_eq!(format_attribute("width", Some(&value), &options), expected);
+    }
+
+    #[rstest]
+    #[case("width", [("width", None)].into_iter().collect())]
+    #[case("-1width", [("-1width", None)].into_iter().collect())]
+    #[case("1width", [("1width", None)].into_iter().collect())]
+    #[case("1-width", [("1-width", None)].into_iter().collect())]
+    #[case("width=\"40\"", [("width", Some("40".into()))].into_iter().collect())]
+    #[case("width   =    \"40\"", [("width", Some("40".into()))].into_iter().collect())]
+    #[case("value=yes", [("value", Some("yes".into()))].into_iter().collect())]
+    #[case("width=\"40\"", [("width", Some("40".into()))].into_iter().collect())]
+    #[case("width=\"40\" height=\"30\"", [("width", Some("40".into())), ("height", Some("30".into()))].into_iter().collect())]
+    #[case("width=\"40\" height=\"30\" class=\"my-class\"", [("width", Some("40".into())), ("height", Some("30".into())), ("class", Some("my-class".into()))].into_iter().collect())]
+    #[case("key  =    value key-here1  =    value123   width=\"40\" length='40' height=\"30\" class=\"my-class\"", [
+            ("key", Some("value".into())),
+            ("key-here1", Some("value123".into())),
+            ("width", Some("40".into())),
+            ("length", Some("40".into())),
+            ("height", Some("30".into())),
+            ("class", Some("my-class".into())),
+        ].into_iter().collect())]
     fn test_attributes(#[case] input: &str, #[case] expected: Attributes) {
-        let actual = Attributes::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = Attributes::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
+    }
+
+    #[rstest]
+    #[case::source(
+        AttributeOrder::Source,
+        "type href id rel data-b class data-a aria-label name"
+    )]
+    #[case::conventional(
+        AttributeOrder::conventional(),
+        "id class name data-a data-b aria-label href rel type"
+    )]
+    #[case::custom(
+        AttributeOrder::Groups(vec!["rel".to_string(), "data-*".to_string()]),
+        "rel data-a data-b aria-label class href id name type"
+    )]
+    fn test_sorting_attributes(#[case] order: AttributeOrder, #[case] expected: &str) {
+        let mut attributes = Attributes::parse
+            .parse(new_input(
+                "type href id rel data-b class data-a aria-label name",
+            ))
+            .unwrap();
+        attributes.sort(&order);
+        let actual = attributes.iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(actual.join(" "), expected)
+    }
+
+    #[rstest]
+    #[case("a b c", vec![], "a b c")]
+    #[case("value=\"a\" value=\"b\"", vec!["value"], "value")]
+    #[case("a b a c b a", vec!["a", "b", "a"], "a b c")]
+    fn test_duplicate_attributes(
+        #[case] input: &str,
+        #[case] duplicates: Vec<&str>,
+        #[case] deduped: &str,
+    ) {
+        let mut attributes = Attributes::parse.parse(new_input(input)).unwrap();
+        let actual = attributes
+            .duplicates()
+            .iter()
+            .map(|attribute| attribute.key)
+            .collect::<Vec<_>>();
+        assert_eq!(actual, duplicates);
+
+        attributes.dedup();
+        let actual = attributes.iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(actual.join(" "), deduped)
+    }
+
+    #[rstest]
+    fn test_dedup_keeps_first_value() {
+        let mut attributes = Attributes::parse
+            .parse(new_input("value=\"a\" value=\"b\""))
+            .unwrap();
+        attributes.dedup();
+        assert_equivalent(attributes.get("value").unwrap(), &"a".into())
+    }
+
+    #[rstest]
+    #[case::whitespace("\"  b\n\t\ta   c \"", ClassOrder::Source, "b a c")]
+    #[case::duplicates("\"b a b c a\"", ClassOrder::Source, "b a c")]
+    #[case::empty("\"  \"", ClassOrder::Source, "")]
+    #[case::alphabetical("\"c a b\"", ClassOrder::Alphabetical, "a b c")]
+    #[case::template_syntax(
+        "\"btn  {% if active %}active{% endif %}\n card\"",
+        ClassOrder::Source,
+        "btn {% if active %}active{% endif %} card"
+    )]
+    #[case::glued_template_syntax(
+        "\"btn-{{ size }} btn-{{ size }}  x{%if a%}-a{%endif%}\"",
+        ClassOrder::Source,
+        "btn-{{ size }} x{% if a %}-a{% endif %}"
+    )]
+    #[case::sorted_around_template_syntax(
+        "\"d c {{ a }} b a {% if x %}x{% endif %} f e\"",
+        ClassOrder::Alphabetical,
+        "c d {{ a }} a b {% if x %}x{% endif %} e f"
+    )]
+    #[case::tailwind(
+        "\"hover:bg-red-500 p-4 text-white bg-blue-500 md:flex flex my-card text-sm\"",
+        ClassOrder::tailwind(),
+        "my-card flex bg-blue-500 p-4 text-sm text-white hover:bg-red-500 md:flex"
+    )]
+    #[case::tailwind_custom_list(
+        "\"p-2 focus:p-1 bg-white hover:p-1 flex\"",
+        ClassOrder::Tailwind { variants: vec!["focus".to_string(), "hover".to_string()], utilities: vec!["p".to_string(), "bg".to_string()] },
+        "flex p-2 bg-white focus:p-1 hover:p-1"
+    )]
+    fn test_normalizing_classes(
+        #[case] input: &str,
+        #[case] order: ClassOrder,
+        #[case] expected: &str,
+    ) {
+        let mut value = parse_double_quoted_val.parse(new_input(input)).unwrap();
+        value.normalize_classes(&order);
+        assert_eq!(value.formatted(0, &FormatOptions::default()), expected);
+    }
+
+    #[rstest]
+    fn test_value_equivalence_ignores_how_text_is_split() {
+        let mut value = AttributeValue::from("a  b");
+        value.normalize_classes(&ClassOrder::Source);
+        assert_eq!(value.nodes.len(), 3);
+        assert!(value.is_equivalent(&AttributeValue::from("a b")));
+        assert!(!value.is_equivalent(&AttributeValue::from("a  b")));
     }
 }
@@ -1,18 +1,32 @@
+use crate::span::Input;
 use winnow::{combinator::delimited, token::take_until, PResult, Parser};
 
-pub fn parse_comment<'i>(input: &mut &'i str) -> PResult<&'i str> {
+pub fn parse_comment<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
     let mut comment = delimited("<!--", take_until(0.., "-->"), "-->");
 
     let content = comment.parse_next(input)?;
     Ok(content.trim())
 }
 
+/// Parses a processing instruction like `<?xml version="1.0"?>`, which is kept as it was.
+pub fn parse_processing_instruction<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    ("<?", take_until(0.., "?>"), "?>").take().parse_next(input)
+}
+
+/// Parses a CDATA section like `<![CDATA[a < b]]>`, which is kept as it was.
+pub fn parse_cdata<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    ("<![CDATA[", take_until(0.., "]]>"), "]]>")
+        .take()
+        .parse_next(input)
+}
+
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
     use rstest::rstest;
 
     use super::*;
+    use crate::span::new_input;
 
     #[rstest]
     #[case("<!-- -->", "")]
@@ -23,7 +37,29 @@ mod tests {
     #[case("<!--my-comment-->", "my-comment")]
     #[case("<!--     my-comment       -->", "my-comment")]
     fn test_comment_parses_successfully(#[case] input: &str, #[case] expected: &str) {
-        let actual = parse_comment.parse(input).unwrap();
+        let actual = parse_comment.parse(new_input(input)).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[rstest]
+    #[case("<?xml version=\"1.0\"?>", "<?xml version=\"1.0\"?>", "")]
+    #[case("<?php echo 1 > 0; ?><p>", "<?php echo 1 > 0; ?>", "<p>")]
+    fn test_parsing_processing_instruction(
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] expected_remaining: &str,
+    ) {
+        let mut input = new_input(input);
+        let actual = parse_processing_instruction.parse_next(&mut input).unwrap();
+        assert_eq!(actual, expected);
+        assert_eq!(**input, expected_remaining);
+    }
+
+    #[rstest]
+    #[case("<![CDATA[]]>", "<![CDATA[]]>")]
+    #[case("<![CDATA[ a < b && c ]]>", "<![CDATA[ a < b && c ]]>")]
+    fn test_parsing_cdata(#[case] input: &str, #[case] expected: &str) {
+        let actual = parse_cdata.parse(new_input(input)).unwrap();
         assert_eq!(actual, expected);
     }
 }
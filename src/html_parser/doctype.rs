@@ -0,0 +1,106 @@
+use crate::doc::Doc;
+use crate::formatting::{DoctypeCase, FormatOptions, Formatable};
+use crate::span::Input;
+use winnow::{
+    ascii::{multispace0, Caseless},
+    combinator::{delimited, preceded},
+    token::{take_till, take_until},
+    PResult, Parser,
+};
+
+/// A document type declaration, like `<!DOCTYPE html>`.
+#[derive(Debug)]
+pub struct Doctype<'i> {
+    /// The name of the root element, like `html`
+    pub name: &'i str,
+    /// Anything after the name, like the public identifier of a legacy doctype
+    pub rest: &'i str,
+}
+
+/// The keyword and name are case insensitive, so `<!doctype HTML>` is the same as
+/// `<!DOCTYPE html>`.
+impl PartialEq for Doctype<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.eq_ignore_ascii_case(other.name) && self.rest == other.rest
+    }
+}
+
+impl<'i> Doctype<'i> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let (name, rest) = delimited(
+            ("<!", Caseless("doctype"), multispace0),
+            (
+                take_till(0.., |c: char| c.is_whitespace() || c == '>'),
+                preceded(multispace0, take_until(0.., '>')),
+            ),
+            '>',
+        )
+        .parse_next(input)?;
+
+        Ok(Self {
+            name,
+            rest: rest.trim_end(),
+        })
+    }
+}
+
+impl Formatable for Doctype<'_> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let keyword = match options.doctype_case {
+            DoctypeCase::Upper => "DOCTYPE",
+            DoctypeCase::Lower => "doctype",
+        };
+        let mut formatted = format!("<!{keyword} {}", self.name.to_ascii_lowercase());
+        if !self.rest.is_empty() {
+            formatted.push(' ');
+            formatted.push_str(self.rest);
+        }
+        formatted.push('>');
+        Doc::text(formatted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::span::new_input;
+
+    #[rstest]
+    #[case("<!DOCTYPE html>", "html", "")]
+    #[case("<!doctype html>", "html", "")]
+    #[case("<!DocType   HTML  >", "HTML", "")]
+    #[case(
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\">",
+        "html",
+        "PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\""
+    )]
+    fn test_parsing_doctype(#[case] input: &str, #[case] name: &str, #[case] rest: &str) {
+        let actual = Doctype::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.name, name);
+        assert_eq!(actual.rest, rest);
+    }
+
+    #[rstest]
+    #[case("<!doctype  HTML>", DoctypeCase::Upper, "<!DOCTYPE html>")]
+    #[case("<!DOCTYPE html>", DoctypeCase::Lower, "<!doctype html>")]
+    #[case(
+        "<!doctype html SYSTEM \"about:legacy-compat\">",
+        DoctypeCase::Upper,
+        "<!DOCTYPE html SYSTEM \"about:legacy-compat\">"
+    )]
+    fn test_formatting_doctype(
+        #[case] input: &str,
+        #[case] doctype_case: DoctypeCase,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            doctype_case,
+            ..FormatOptions::default()
+        };
+        let actual = Doctype::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &options), expected);
+    }
+}
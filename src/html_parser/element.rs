@@ -1,152 +1,437 @@
 use super::{
     attribute::Attributes,
-    node::{parse_child_nodes, Node},
-    tag::Tag,
+    node::{child_nodes_doc, is_blank, is_in_text, parse_child_nodes_in, Node, Open},
+    tag::{is_void_element, opening_tag_doc, Tag},
+    text::text_doc,
+};
+use crate::{
+    doc::Doc,
+    formatting::{FormatOptions, Formatable, VoidStyle, WhitespaceSensitivity},
+    html_parser::tag::ClosingTag,
+    span::{Equivalent, Input, Span, Spanned},
 };
-use crate::{formatting::Formatable, html_parser::tag::ClosingTag};
 use winnow::{
-    error::{ErrMode, ErrorKind, ParserError},
+    error::{
+        AddContext, ContextError, ErrMode, ErrorKind, ParserError, StrContext, StrContextValue,
+    },
+    stream::{Location, Stream},
     PResult, Parser,
 };
 
+/// Elements whose content is never parsed as HTML, which the HTML spec calls raw text elements.
+/// Their content can be re-indented, as long as that doesn't change it.
+pub const RAW_TEXT_ELEMENT_NAMES: &[&str] = &["script", "style"];
+
+/// Elements whose whitespace is significant, so their content is kept exactly as written.
+pub const PREFORMATTED_ELEMENT_NAMES: &[&str] = &["pre", "textarea"];
+
+/// Elements that flow along with the text around them, rather than going on their own line,
+/// following the elements that CSS displays inline by default.
+pub const INLINE_ELEMENT_NAMES: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "button", "cite", "code", "data", "del", "dfn", "em",
+    "i", "img", "input", "ins", "kbd", "label", "mark", "meter", "output", "progress", "q", "s",
+    "samp", "select", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+/// The opening tags that close a `<p>`, from the HTML spec.
+const P_CLOSING: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+const CELL_CLOSING: &[&str] = &["td", "th", "tr", "thead", "tbody", "tfoot"];
+
+/// Elements whose closing tag can be left out, along with the opening tags that imply it, from
+/// the HTML spec. The end of their parent implies it too.
+const OPTIONAL_CLOSING_TAGS: &[(&str, &[&str])] = &[
+    ("li", &["li"]),
+    ("dt", &["dt", "dd"]),
+    ("dd", &["dt", "dd"]),
+    ("p", P_CLOSING),
+    ("tr", &["tr", "thead", "tbody", "tfoot"]),
+    ("td", CELL_CLOSING),
+    ("th", CELL_CLOSING),
+    ("option", &["option", "optgroup"]),
+    ("thead", &["tbody", "tfoot"]),
+    ("tbody", &["tbody", "tfoot"]),
+];
+
 #[derive(Debug, PartialEq)]
 pub enum ElementVariant {
     Normal,
     Void,
+    /// Like `<script>` or `<pre>`, where the only child is the content exactly as it was written
+    RawText,
+    /// Like `<li>` without its `</li>`, which was implied by whatever came after it
+    ImpliedEnd,
+    /// An element that's never closed, which is where parsing failed
+    Unclosed {
+        error_offset: usize,
+        error: ContextError,
+    },
+}
+
+impl ElementVariant {
+    /// Whether elements are the same either way, which is when the only difference is whether the
+    /// closing tag was written out.
+    pub fn is_equivalent(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ElementVariant::Normal, ElementVariant::ImpliedEnd)
+            | (ElementVariant::ImpliedEnd, ElementVariant::Normal)
+            | (ElementVariant::Unclosed { .. }, ElementVariant::Unclosed { .. }) => true,
+            _ => self == other,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Element<'i> {
-    pub id: Option<&'i str>,
     pub name: &'i str,
     pub variant: ElementVariant,
     pub attributes: Attributes<'i>,
-    pub classes: Vec<&'i str>,
     pub children: Vec<Node<'i>>,
+    /// From the start of the opening tag to the end of the closing tag
+    pub span: Span,
 }
 
-impl<'i> Element<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let mut opening_tag = Tag::parse.parse_next(input)?;
+impl Equivalent for Element<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.variant == other.variant
+            && self.attributes.is_equivalent(&other.attributes)
+            && self.children.is_equivalent(&other.children)
+    }
+}
 
-        let id = opening_tag.attributes.pop("id");
-        let classes_attr = opening_tag.attributes.pop("class");
+impl<'i> Element<'i> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        Self::parse_in(&[], input)
+    }
 
-        let mut classes = vec![];
-        if let Some(classes_attr) = classes_attr {
-            classes.extend(classes_attr.split(' '));
-        }
+    /// Parses an element within the `open` elements and blocks, innermost last. An element whose
+    /// closing tag never comes keeps the nodes up until where one of those ends.
+    pub fn parse_in(open: &[Open<'i>], input: &mut Input<'i>) -> PResult<Self> {
+        let opening_tag = Tag::parse.parse_next(input)?;
 
         if opening_tag.variant == ElementVariant::Void {
             return Ok(Self {
-                id,
                 name: opening_tag.name,
                 variant: ElementVariant::Void,
                 attributes: opening_tag.attributes,
-                classes,
                 children: vec![],
+                span: opening_tag.span,
             });
         }
 
-        let closing_tag_peek = ClosingTag::parse.parse_peek(input);
+        if is_raw_text_element(opening_tag.name) {
+            let (children, closing_tag) = parse_raw_text(opening_tag.name, input)?;
+            return Ok(Self {
+                name: opening_tag.name,
+                variant: ElementVariant::RawText,
+                attributes: opening_tag.attributes,
+                children,
+                span: (opening_tag.span.start..closing_tag.span.end).into(),
+            });
+        }
+
+        let mut open = open.to_vec();
+        open.push(Open::Element(opening_tag.name));
 
-        if closing_tag_peek.is_ok() {
-            let closing_tag = ClosingTag::parse.parse_next(input)?;
+        let optional_closing = OPTIONAL_CLOSING_TAGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(opening_tag.name));
+        let closing = optional_closing.map_or(&[][..], |(_, closing)| closing);
+        let children = parse_child_nodes_in(&open, closing, input)?;
 
-            if opening_tag.name != closing_tag.name {
-                return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
-            } else if opening_tag.name == closing_tag.name {
+        let start = input.checkpoint();
+        if let Ok(closing_tag) = ClosingTag::parse.parse_next(input) {
+            if closing_tag.name.eq_ignore_ascii_case(opening_tag.name) {
                 return Ok(Self {
-                    id,
                     name: opening_tag.name,
                     variant: ElementVariant::Normal,
                     attributes: opening_tag.attributes,
-                    classes,
-                    children: vec![],
+                    children,
+                    span: (opening_tag.span.start..closing_tag.span.end).into(),
                 });
             }
         }
-
-        let children = parse_child_nodes.parse_next(input)?;
-
-        let closing_tag = ClosingTag::parse.parse_next(input)?;
-
-        if opening_tag.name != closing_tag.name {
-            return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
-        }
-
+        input.reset(&start);
+
+        let variant = match optional_closing {
+            Some(_) => ElementVariant::ImpliedEnd,
+            None => ElementVariant::Unclosed {
+                error_offset: input.location(),
+                error: unclosed_element_error(input, &start),
+            },
+        };
+        let end = children
+            .last()
+            .map_or(opening_tag.span.end, |child| child.span().end);
         Ok(Self {
-            id,
             name: opening_tag.name,
-            variant: ElementVariant::Normal,
+            variant,
             attributes: opening_tag.attributes,
-            classes,
             children,
+            span: (opening_tag.span.start..end).into(),
         })
     }
-}
 
-impl<'i> Formatable for Element<'i> {
-    fn formatted(&self, indent_level: usize) -> String {
-        let mut html = String::new();
-
-        // Create the indent string for the current level
-        let mut indent = "\t".repeat(indent_level);
-
-        // Add the opening tag with the current indentation
-        html.push_str(&format!("{}<{}", indent, self.name));
+    /// The element within the text around it, if it's inline. It's on a single line if everything
+    /// in it is inline too, and otherwise hugs the text on each side with its children on lines of
+    /// their own, unless whitespace doesn't matter. The whitespace around it is left as it was.
+    pub fn inline_doc<'a>(
+        &'a self,
+        indent_level: usize,
+        options: &'a FormatOptions,
+    ) -> Option<Doc<'a>> {
+        let is_inline = match options.whitespace_sensitivity {
+            WhitespaceSensitivity::Strict => true,
+            WhitespaceSensitivity::Css | WhitespaceSensitivity::Ignore => self.is_inline_level(),
+        };
+        if !is_inline
+            || matches!(
+                self.variant,
+                ElementVariant::RawText
+                    | ElementVariant::ImpliedEnd
+                    | ElementVariant::Unclosed { .. }
+            )
+        {
+            return None;
+        }
 
-        // Add the id attribute if it exists
-        if let Some(id) = self.id {
-            html.push_str(&format!(" id=\"{}\"", id));
+        let mut docs = vec![self.opening_tag_doc(options)];
+        if self.variant == ElementVariant::Normal {
+            let children = self
+                .children
+                .iter()
+                .map(|child| child.inline_doc(indent_level, options))
+                .collect::<Option<Vec<_>>>();
+            match children {
+                Some(children) => docs.extend(children),
+                None if options.whitespace_sensitivity == WhitespaceSensitivity::Ignore => {
+                    return None;
+                }
+                // The children go on lines of their own, which only adds whitespace where it
+                // doesn't matter, next to the ones that can't flow along with the text
+                None => return Some(Doc::group(self.hugging_doc(options))),
+            }
+            docs.push(Doc::text(format!("</{}>", self.name)));
+        }
+        let doc = Doc::concat(docs);
+
+        // Elements that don't fit can go on their own line when whitespace doesn't matter
+        if options.whitespace_sensitivity == WhitespaceSensitivity::Ignore {
+            let html = doc.print_flat(options);
+            let width = options.width(&options.indent(indent_level)) + options.width(&html);
+            if html.contains('\n') || width > options.line_width {
+                return None;
+            }
         }
+        Some(doc)
+    }
 
-        // Add the classes if they exist
-        if !self.classes.is_empty() {
-            html.push_str(" class=\"");
-            html.push_str(&self.classes.join(" "));
-            html.push('"');
+    /// The element with its children on lines of their own, apart from the first and last when
+    /// they're part of the text, which hug the tags.
+    fn hugging_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let is_content = |child: &Node| !is_blank(std::slice::from_ref(child));
+        let hugs = |index: Option<usize>| {
+            index.is_some_and(|index| is_in_text(&self.children, index, true, options))
+        };
+        let mut children = vec![];
+        if !hugs(self.children.iter().position(is_content)) {
+            children.push(Doc::HardLine);
         }
+        children.push(child_nodes_doc(&self.children, true, options));
+
+        let mut docs = vec![
+            self.opening_tag_doc(options),
+            Doc::indent(Doc::concat(children)),
+        ];
+        if !hugs(self.children.iter().rposition(is_content)) {
+            docs.push(Doc::HardLine);
+        }
+        docs.push(Doc::text(format!("</{}>", self.name)));
+        Doc::concat(docs)
+    }
+
+    /// Whether the element is shown within the text around it by default, like `<a>` and `<span>`.
+    pub fn is_inline_level(&self) -> bool {
+        INLINE_ELEMENT_NAMES
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(self.name))
+    }
+
+    /// The opening tag, like `<a href="/">`, or the whole element if it's void.
+    fn opening_tag_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let self_closing = self.is_self_closing(options);
+        opening_tag_doc(self.name, &self.attributes, self_closing, options)
+    }
 
-        // Add the attributes if they exist
-        for (key, val) in self.attributes.iter() {
-            html.push(' ');
-            html.push_str(key);
-            if let Some(val) = val {
-                html.push_str("=\"");
-                html.push_str(val);
-                html.push('"');
+    /// Whether the opening tag ends in `/>`, which is for void elements unless they're bare.
+    fn is_self_closing(&self, options: &FormatOptions) -> bool {
+        let is_bare = options.void_style == VoidStyle::Bare && is_void_element(self.name);
+        self.variant == ElementVariant::Void && !is_bare
+    }
+
+    /// Whether the element's content is written exactly as it was, rather than re-indented.
+    pub fn is_verbatim(&self) -> bool {
+        self.variant == ElementVariant::RawText && self.reindentable_text().is_none()
+    }
+
+    /// The content of a raw text element, if it's safe to re-indent. That's when it starts on its
+    /// own line, and has no strings that can span lines, like JavaScript's template literals.
+    fn reindentable_text(&self) -> Option<&'i str> {
+        let is_reindentable = RAW_TEXT_ELEMENT_NAMES
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(self.name));
+        let text = match self.children.as_slice() {
+            [Node::Text(text)] if is_reindentable => text.value,
+            _ => return None,
+        };
+        let first_line = text.split('\n').next().unwrap_or_default();
+        let safe = text.contains('\n')
+            && first_line.trim().is_empty()
+            && !text.contains('`')
+            && !text.lines().any(|line| line.trim_end().ends_with('\\'));
+        safe.then_some(text)
+    }
+}
+
+/// The error for an element whose closing tag never comes.
+fn unclosed_element_error<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+) -> ContextError {
+    ContextError::new()
+        .add_context(input, start, StrContext::Label("element"))
+        .add_context(
+            input,
+            start,
+            StrContext::Expected(StrContextValue::Description("a closing tag")),
+        )
+}
+
+fn is_raw_text_element(name: &str) -> bool {
+    RAW_TEXT_ELEMENT_NAMES
+        .iter()
+        .chain(PREFORMATTED_ELEMENT_NAMES)
+        .any(|raw_name| raw_name.eq_ignore_ascii_case(name))
+}
+
+/// Takes everything up to the element's closing tag as text, like browsers do for `<script>`.
+/// The closing tag's name is matched regardless of case.
+fn parse_raw_text<'i>(
+    name: &str,
+    input: &mut Input<'i>,
+) -> PResult<(Vec<Node<'i>>, ClosingTag<'i>)> {
+    let mut length = 0;
+    loop {
+        let Some(offset) = input[length..].find("</") else {
+            return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
+        };
+        length += offset;
+
+        let mut rest = *input;
+        rest.next_slice(length);
+        if let Ok(closing_tag) = ClosingTag::parse.parse_next(&mut rest) {
+            if closing_tag.name.eq_ignore_ascii_case(name) {
+                let start = input.location();
+                let value = input.next_slice(length);
+                let children = if value.is_empty() {
+                    vec![]
+                } else {
+                    vec![Node::Text(Spanned {
+                        value,
+                        span: (start..input.location()).into(),
+                    })]
+                };
+                *input = rest;
+                return Ok((children, closing_tag));
             }
         }
+        length += "</".len();
+    }
+}
+
+impl<'i> Formatable for Element<'i> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let mut docs = vec![self.opening_tag_doc(options)];
+        let closing_tag = Doc::text(format!("</{}>", self.name));
 
-        // Add the closing tag
         match self.variant {
-            ElementVariant::Normal => {
-                html.push('>');
-            }
-            ElementVariant::Void => {
-                html.push_str(" />");
-                return html;
+            ElementVariant::Normal
+            | ElementVariant::ImpliedEnd
+            | ElementVariant::Unclosed { .. } => {}
+            ElementVariant::RawText => {
+                if let Some(text) = self.reindentable_text() {
+                    if let Some(text) = text_doc(text, options.max_blank_lines) {
+                        docs.push(Doc::indent(Doc::concat([Doc::HardLine, text])));
+                        docs.push(Doc::HardLine);
+                    }
+                } else {
+                    for child in &self.children {
+                        if let Node::Text(text) = child {
+                            docs.push(Doc::text(text.value));
+                        }
+                    }
+                }
+                docs.push(closing_tag);
+                return Doc::concat(docs);
             }
+            ElementVariant::Void => return Doc::concat(docs),
         }
 
-        if !self.children.is_empty() {
-            html.push('\n');
+        // Each child goes on its own line, one level deeper
+        if !is_blank(&self.children) {
+            docs.push(Doc::indent(Doc::concat([
+                Doc::HardLine,
+                child_nodes_doc(&self.children, false, options),
+            ])));
         }
 
-        // Add each child, increasing the indentation for each child
-        for child in &self.children {
-            html.push_str(&child.formatted(indent_level + 1)); // Recursively increase the indentation
+        let implied_end =
+            self.variant == ElementVariant::ImpliedEnd && !options.insert_implied_end_tags;
+        if implied_end || matches!(self.variant, ElementVariant::Unclosed { .. }) {
+            return Doc::concat(docs);
         }
 
-        if self.children.is_empty() {
-            indent = "".to_string();
+        if !self.children.is_empty() {
+            docs.push(Doc::HardLine);
         }
-
-        // Add the closing tag with the current indentation
-        html.push_str(&format!("{}</{}>", indent, self.name));
-
-        html
+        docs.push(closing_tag);
+        Doc::concat(docs)
     }
 }
 
@@ -156,167 +441,280 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::formatting::BracketPlacement;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case("<div></div>", Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     })]
     #[case("<div id=\"my-id\"></div>", Element {
-        id: Some("my-id"),
         name: "div",
         variant: ElementVariant::Normal,
-        attributes: Attributes::default(),
-        classes: vec![],
+        attributes: [("id", Some("my-id".into()))].into_iter().collect(),
         children: vec![],
+        span: Span::default(),
     })]
     fn test_element_parses_successfully(#[case] input: &str, #[case] expected: Element) {
-        let actual = Element::parse.parse(input).unwrap();
-        assert_eq!(actual, expected);
+        let actual = Element::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
     #[case(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }, "<div></div>")]
     #[case(Element {
-        id: Some("my-id"),
         name: "div",
         variant: ElementVariant::Normal,
-        attributes: Attributes::default(),
-        classes: vec![],
+        attributes: [("id", Some("my-id".into()))].into_iter().collect(),
         children: vec![],
+        span: Span::default(),
     }, "<div id=\"my-id\"></div>")]
     #[case(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
-        attributes: Attributes::default(),
-        classes: vec!["my-class"],
+        attributes: [("class", Some("my-class".into()))].into_iter().collect(),
         children: vec![],
+        span: Span::default(),
     }, "<div class=\"my-class\"></div>")]
     #[case(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
-        attributes: Attributes::default(),
-        classes: vec!["my-class", "my-other-class"],
+        attributes: [("class", Some("my-class my-other-class".into()))].into_iter().collect(),
         children: vec![],
+        span: Span::default(),
     }, "<div class=\"my-class my-other-class\"></div>")]
     #[case(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
-        children: vec![Node::Text("hello there")],
+        children: vec![Node::Text("hello there".into())],
+        span: Span::default(),
     }, "<div>\n\thello there\n</div>")]
     #[case(Element {
-        id: Some("my-id"),
         name: "div",
         variant: ElementVariant::Normal,
-        attributes: Attributes::default(),
-        classes: vec!["my-class"],
+        attributes: [("id", Some("my-id".into())), ("class", Some("my-class".into()))].into_iter().collect(),
         children: vec![Node::Element(Element {
-            id: None,
             name: "div",
             variant: ElementVariant::Normal,
             attributes: Attributes::default(),
-            classes: vec![],
             children: vec![],
+            span: Span::default(),
         })],
+        span: Span::default(),
     }, "<div id=\"my-id\" class=\"my-class\">\n\t<div></div>\n</div>")]
     #[case(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![Node::Element(Element {
-            id: None,
             name: "meta",
             variant: ElementVariant::Void,
             attributes: Attributes::default(),
-            classes: vec![],
             children: vec![],
+            span: Span::default(),
         }),
         Node::Element(Element {
-            id: None,
             name: "title",
             variant: ElementVariant::Normal,
             attributes: Attributes::default(),
-            classes: vec![],
             children: vec![],
+            span: Span::default(),
         })
         ],
+        span: Span::default(),
     }, "<div>\n\t<meta />\n\t<title></title>\n</div>")]
     fn test_element_format(#[case] input: Element, #[case] expected: &str) {
-        let actual = input.formatted(0);
+        let actual = input.formatted(0, &FormatOptions::default());
         assert_eq!(actual, expected);
     }
 
+    #[rstest]
+    #[case::closed("<li>a</li><li>", ElementVariant::Normal, "<li>")]
+    #[case::next_item("<li>a<li>b", ElementVariant::ImpliedEnd, "<li>b")]
+    #[case::parent_end("<li>a</ul>", ElementVariant::ImpliedEnd, "</ul>")]
+    #[case::end_of_input("<p>a", ElementVariant::ImpliedEnd, "")]
+    #[case::block_after_paragraph("<p>a<div>b</div>", ElementVariant::ImpliedEnd, "<div>b</div>")]
+    #[case::uppercase("<LI>a<li>", ElementVariant::ImpliedEnd, "<li>")]
+    fn test_parsing_implied_end(
+        #[case] input: &str,
+        #[case] variant: ElementVariant,
+        #[case] remaining: &str,
+    ) {
+        let mut input = new_input(input);
+        let actual = Element::parse_in(&[Open::Element("ul")], &mut input).unwrap();
+        assert_eq!(actual.variant, variant);
+        assert_equivalent(&actual.children, &vec![Node::Text("a".into())]);
+        assert_eq!(**input, remaining);
+    }
+
+    #[rstest]
+    #[case::script("<script>if (a < b && c > d) {}</script>", "if (a < b && c > d) {}")]
+    #[case::style("<style>a > b { color: red; }</style>", "a > b { color: red; }")]
+    #[case::closing_tag_in_string("<script>x = '</div>';</script>", "x = '</div>';")]
+    #[case::uppercase("<SCRIPT>a</Script>", "a")]
+    #[case::template_syntax("<pre>{{ a }}\n  <b>b</b></pre>", "{{ a }}\n  <b>b</b>")]
+    #[case::textarea("<textarea>  <p>\n</textarea>", "  <p>\n")]
+    fn test_parsing_raw_text_element(#[case] input: &str, #[case] expected: &str) {
+        let actual = Element::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.variant, ElementVariant::RawText);
+        assert_equivalent(&actual.children, &vec![Node::Text(expected.into())]);
+    }
+
+    #[rstest]
+    #[case::empty("<script></script>", "<script></script>")]
+    #[case::blank("<style>\n  \n</style>", "<style></style>")]
+    #[case::same_line("<script>  a  </script>", "<script>  a  </script>")]
+    #[case::reindented(
+        "<script>\n        if (a) {\n            b();\n        }\n    </script>",
+        "<script>\n\tif (a) {\n\t    b();\n\t}\n</script>"
+    )]
+    #[case::template_literal(
+        "<script>\n  x = `a\n    b`;\n</script>",
+        "<script>\n  x = `a\n    b`;\n</script>"
+    )]
+    #[case::line_continuation(
+        "<script>\n  x = 'a\\\n    b';\n</script>",
+        "<script>\n  x = 'a\\\n    b';\n</script>"
+    )]
+    #[case::pre("<pre>\n  a\n    b\n</pre>", "<pre>\n  a\n    b\n</pre>")]
+    #[case::textarea(
+        "<textarea name=\"a\">\n  a  </textarea>",
+        "<textarea name=\"a\">\n  a  </textarea>"
+    )]
+    fn test_formatting_raw_text_element(#[case] input: &str, #[case] expected: &str) {
+        let actual = Element::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &FormatOptions::default()), expected);
+    }
+
+    #[rstest]
+    #[case::fits(
+        40,
+        BracketPlacement::NewLine,
+        "<a href=\"/\" title=\"Home\">\n\thome\n</a>"
+    )]
+    #[case::new_line(
+        20,
+        BracketPlacement::NewLine,
+        "<a\n\thref=\"/\"\n\ttitle=\"Home\"\n>\n\thome\n</a>"
+    )]
+    #[case::same_line(
+        20,
+        BracketPlacement::SameLine,
+        "<a\n\thref=\"/\"\n\ttitle=\"Home\">\n\thome\n</a>"
+    )]
+    fn test_formatting_long_opening_tag(
+        #[case] line_width: usize,
+        #[case] bracket_placement: BracketPlacement,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            line_width,
+            bracket_placement,
+            ..FormatOptions::default()
+        };
+        let element = Element::parse
+            .parse(new_input("<a href=\"/\" title=\"Home\">home</a>"))
+            .unwrap();
+        assert_eq!(element.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::self_closing(
+        VoidStyle::SelfClosing,
+        "<img\n\tsrc=\"a.png\"\n\talt=\"An image\"\n/>"
+    )]
+    #[case::bare(VoidStyle::Bare, "<img\n\tsrc=\"a.png\"\n\talt=\"An image\"\n>")]
+    fn test_formatting_long_void_element(#[case] void_style: VoidStyle, #[case] expected: &str) {
+        let options = FormatOptions {
+            line_width: 20,
+            void_style,
+            ..FormatOptions::default()
+        };
+        let element = Element::parse
+            .parse(new_input("<img src=\"a.png\" alt=\"An image\">"))
+            .unwrap();
+        assert_eq!(element.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::self_closing(VoidStyle::SelfClosing, "<BR />")]
+    #[case::bare(VoidStyle::Bare, "<BR>")]
+    fn test_formatting_void_element_in_upper_case(
+        #[case] void_style: VoidStyle,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            void_style,
+            ..FormatOptions::default()
+        };
+        let mut input = new_input("<BR>a");
+        let element = Element::parse.parse_next(&mut input).unwrap();
+        assert_eq!(element.variant, ElementVariant::Void);
+        assert_eq!(**input, "a");
+        assert_eq!(element.formatted(0, &options), expected);
+    }
+
     #[rstest]
     fn test_element_format_kitchen_sink() {
         let mut attributes = Attributes::default();
-        attributes.insert("width", Some("40"));
+        attributes.insert("id", Some("my-id".into()));
+        attributes.insert("class", Some("my-class".into()));
+        attributes.insert("width", Some("40".into()));
 
         let element = Element {
-            id: Some("my-id"),
             name: "div",
             variant: ElementVariant::Normal,
             attributes,
-            classes: vec!["my-class"],
             children: vec![
                 Node::Element(Element {
-                    id: None,
                     name: "div",
                     variant: ElementVariant::Normal,
                     attributes: Attributes::default(),
-                    classes: vec![],
                     children: vec![],
+                    span: Span::default(),
                 }),
-                Node::Comment("my comment"),
+                Node::Comment("my comment".into()),
             ],
+            span: Span::default(),
         };
         let expected =
             "<div id=\"my-id\" class=\"my-class\" width=\"40\">\n\t<div></div>\n\t<!-- my comment -->\n</div>";
-        let actual = element.formatted(0);
+        let actual = element.formatted(0, &FormatOptions::default());
         assert_eq!(actual, expected);
     }
 
     #[rstest]
     #[case("<div></div>", Ok(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }), "")]
     #[case("<meta />", Ok(Element {
-        id: None,
         name: "meta",
         variant: ElementVariant::Void,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }), "")]
     #[case("<meta>", Ok(Element {
-        id: None,
         name: "meta",
         variant: ElementVariant::Void,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }), "")]
     #[case("</div>", Err(ErrMode::from_error_kind(&"", ErrorKind::Verify)), "</div>")]
     fn test_element_doesnt_consume_input_after_closing_tag(
@@ -324,11 +722,14 @@ mod tests {
         #[case] expected_extracted: PResult<Element>,
         #[case] expected_remaining: &str,
     ) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         let actual = Element::parse.parse_next(&mut input);
 
-        assert_eq!(actual, expected_extracted);
-        assert_eq!(input, expected_remaining);
+        match (&actual, &expected_extracted) {
+            (Ok(actual), Ok(expected)) => assert_equivalent(actual, expected),
+            _ => assert_eq!(actual, expected_extracted),
+        }
+        assert_eq!(**input, expected_remaining);
     }
 }
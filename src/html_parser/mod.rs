@@ -1,31 +1,34 @@
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input};
 use tag::{ClosingTag, Tag};
 use winnow::{combinator::repeat, PResult, Parser};
 
-mod attribute;
+pub mod attribute;
 mod comment;
+pub mod doctype;
 mod element;
 pub mod node;
 mod tag;
 mod text;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Element<'i> {
     opening_tag: Tag<'i>,
     closing_tag: ClosingTag<'i>,
     children: Vec<Element<'i>>,
 }
 
-impl<'i> PartialEq for Element<'i> {
-    fn eq(&self, other: &Self) -> bool {
-        self.opening_tag == other.opening_tag
-            && self.closing_tag == other.closing_tag
-            && self.children == other.children
+impl Equivalent for Element<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.opening_tag.is_equivalent(&other.opening_tag)
+            && self.closing_tag.is_equivalent(&other.closing_tag)
+            && self.children.is_equivalent(&other.children)
     }
 }
 
 impl<'i> Element<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
         let (opening_tag, children, closing_tag) =
             (Tag::parse, repeat(0.., Element::parse), ClosingTag::parse).parse_next(input)?;
 
@@ -38,33 +41,21 @@ impl<'i> Element<'i> {
 }
 
 impl<'i> Formatable for Element<'i> {
-    fn formatted(&self, indent_level: usize) -> String {
-        let mut html = String::new();
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let mut docs = vec![self.opening_tag.to_doc(options)];
 
-        // Create the indent string for the current level
-        let mut indent = "\t".repeat(indent_level);
-
-        // Add the opening tag with the current indentation
-        html.push_str(&format!("{}{}", indent, self.opening_tag.formatted(0)));
+        // Each child goes on its own line, one level deeper
+        let children = self
+            .children
+            .iter()
+            .map(|child| Doc::concat([Doc::HardLine, child.to_doc(options)]));
+        docs.push(Doc::indent(Doc::concat(children)));
 
         if !self.children.is_empty() {
-            html.push('\n');
-        }
-
-        // Add each child, increasing the indentation for each child
-        for child in &self.children {
-            html.push_str(&child.formatted(indent_level + 1)); // Recursively increase the indentation
-            html.push('\n');
+            docs.push(Doc::HardLine);
         }
-
-        if self.children.is_empty() {
-            indent = "".to_string();
-        }
-
-        // Add the closing tag with the current indentation
-        html.push_str(&format!("{}{}", indent, self.closing_tag.formatted(0)));
-
-        html
+        docs.push(self.closing_tag.to_doc(options));
+        Doc::concat(docs)
     }
 }
 
@@ -76,6 +67,7 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input, Span};
 
     #[rstest]
     fn test_simple_element() {
@@ -85,12 +77,16 @@ mod tests {
                 name: "div",
                 variant: ElementVariant::Normal,
                 attributes: Attributes::default(),
+                span: Span::default(),
+            },
+            closing_tag: ClosingTag {
+                name: "div",
+                span: Span::default(),
             },
-            closing_tag: ClosingTag { name: "div" },
             children: vec![],
         };
-        let actual = Element::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Element::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
@@ -101,22 +97,28 @@ mod tests {
                 name: "div",
                 variant: ElementVariant::Normal,
                 attributes: Attributes::default(),
+                span: Span::default(),
+            },
+            closing_tag: ClosingTag {
+                name: "div",
+                span: Span::default(),
             },
-            closing_tag: ClosingTag { name: "div" },
             children: vec![Element {
                 opening_tag: Tag {
                     name: "div",
                     variant: ElementVariant::Normal,
-                    attributes: Attributes {
-                        kvs: [("height", Some("30"))].into_iter().collect(),
-                    },
+                    attributes: [("height", Some("30".into()))].into_iter().collect(),
+                    span: Span::default(),
+                },
+                closing_tag: ClosingTag {
+                    name: "div",
+                    span: Span::default(),
                 },
-                closing_tag: ClosingTag { name: "div" },
                 children: vec![],
             }],
         };
-        let actual = Element::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Element::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
@@ -126,29 +128,37 @@ mod tests {
                 name: "div",
                 variant: ElementVariant::Normal,
                 attributes: Attributes::default(),
+                span: Span::default(),
+            },
+            closing_tag: ClosingTag {
+                name: "div",
+                span: Span::default(),
             },
-            closing_tag: ClosingTag { name: "div" },
             children: vec![
                 Element {
                     opening_tag: Tag {
                         name: "div",
                         variant: ElementVariant::Normal,
-                        attributes: Attributes {
-                            kvs: [("height", Some("30"))].into_iter().collect(),
-                        },
+                        attributes: [("height", Some("30".into()))].into_iter().collect(),
+                        span: Span::default(),
+                    },
+                    closing_tag: ClosingTag {
+                        name: "div",
+                        span: Span::default(),
                     },
-                    closing_tag: ClosingTag { name: "div" },
                     children: vec![],
                 },
                 Element {
                     opening_tag: Tag {
                         name: "div",
                         variant: ElementVariant::Normal,
-                        attributes: Attributes {
-                            kvs: [("height", Some("30"))].into_iter().collect(),
-                        },
+                        attributes: [("height", Some("30".into()))].into_iter().collect(),
+                        span: Span::default(),
+                    },
+                    closing_tag: ClosingTag {
+                        name: "div",
+                        span: Span::default(),
                     },
-                    closing_tag: ClosingTag { name: "div" },
                     children: vec![],
                 },
             ],
@@ -156,7 +166,7 @@ mod tests {
 
         let expected = "<div>\n\t<div height=\"30\"></div>\n\t<div height=\"30\"></div>\n</div>";
 
-        let actual = input.formatted(0);
+        let actual = input.formatted(0, &FormatOptions::default());
         assert_eq!(expected, actual);
     }
 }
@@ -1,72 +1,845 @@
-use super::element::Element;
-use super::{comment::parse_comment, text::parse_text};
-use crate::formatting::Formatable;
-use winnow::combinator::peek;
+use super::element::{Element, ElementVariant};
+use super::{
+    comment::{parse_cdata, parse_comment, parse_processing_instruction},
+    doctype::Doctype,
+    tag::{parse_tag_name, ClosingTag},
+    text::{inline_run_doc, parse_text, text_doc},
+};
+use crate::doc::Doc;
+use crate::error::token_at;
+use crate::formatting::{FormatOptions, Formatable, WhitespaceSensitivity};
+use crate::span::{Equivalent, Input, Span, Spanned};
+use crate::template_parser::{
+    block::{unexpected_boundary_error, Block},
+    comment::{Comment, CommentKind},
+    filter::Filter,
+    registry::BlockDefinition,
+    tag::{parse_tag_type, Tag},
+    variable::VariableTag,
+};
 use winnow::{
-    error::{ErrMode, ErrorKind, ParserError},
+    combinator::preceded,
+    error::{
+        AddContext, ContextError, ErrMode, ErrorKind, ParserError, StrContext, StrContextValue,
+    },
+    stream::{Location, Stream},
     PResult, Parser,
 };
 
+/// A region of the source that couldn't be parsed, which is printed exactly as it was written.
+#[derive(Debug, PartialEq)]
+pub struct Raw<'i> {
+    pub text: &'i str,
+    pub span: Span,
+    /// Where parsing failed, which is after the raw text for blocks that are never closed
+    pub error_offset: usize,
+    pub error: ContextError,
+}
+
+/// Where parsing failed doesn't matter as long as the text is kept as it was.
+impl Equivalent for Raw<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl<'i> Raw<'i> {
+    /// Takes the tag or word at the start of the input as raw text, so that parsing can carry on
+    /// after something it doesn't understand.
+    pub fn parse_token(input: &mut Input<'i>, error_offset: usize, error: ContextError) -> Self {
+        let start = input.location();
+        let length = token_at(input)
+            .len()
+            .max(input.chars().next().map_or(0, char::len_utf8));
+        let text = input.next_slice(length);
+        Self {
+            text,
+            span: (start..input.location()).into(),
+            error_offset,
+            error,
+        }
+    }
+}
+
+/// An element or block that the nodes being parsed are within. Closing tags only end the nodes
+/// when they belong to one of these, and are kept as they were written otherwise.
+#[derive(Debug, Clone, Copy)]
+pub enum Open<'i> {
+    Element(&'i str),
+    Block(&'i BlockDefinition),
+}
+
+impl Open<'_> {
+    /// Whether `</name>` closes this element.
+    fn is_closed_by(&self, name: &str) -> bool {
+        matches!(self, Open::Element(element) if element.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether this block ends or is split by the `tag_type` tag.
+    fn has_boundary(&self, tag_type: &str) -> bool {
+        matches!(self, Open::Block(definition) if definition.is_boundary(tag_type))
+    }
+}
+
+impl<'i> From<Spanned<&'i str>> for Node<'i> {
+    fn from(text: Spanned<&'i str>) -> Self {
+        Node::Text(text)
+    }
+}
+
+/// A node in a template, which can be HTML or template syntax. Block-level nodes like elements
+/// and template blocks contain their own child nodes.
 #[derive(Debug, PartialEq)]
 pub enum Node<'i> {
-    Text(&'i str),
+    Text(Spanned<&'i str>),
     Element(Element<'i>),
-    Comment(&'i str),
+    Comment(Spanned<&'i str>),
+    Doctype(Spanned<Doctype<'i>>),
+    /// Like `<?xml version="1.0"?>`, exactly as it was written
+    ProcessingInstruction(Spanned<&'i str>),
+    /// Like `<![CDATA[…]]>`, exactly as it was written
+    CData(Spanned<&'i str>),
+    Variable(VariableTag<'i>),
+    Tag(Tag<'i>),
+    Block(Block<'i, Node<'i>>),
+    TemplateComment(Spanned<Comment<'i>>),
+    Raw(Raw<'i>),
+}
+
+impl Equivalent for Node<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Node::Text(text), Node::Text(other))
+            | (Node::Comment(text), Node::Comment(other))
+            | (Node::ProcessingInstruction(text), Node::ProcessingInstruction(other))
+            | (Node::CData(text), Node::CData(other)) => text.is_equivalent(other),
+            (Node::Element(element), Node::Element(other)) => element.is_equivalent(other),
+            (Node::Doctype(doctype), Node::Doctype(other)) => doctype.is_equivalent(other),
+            (Node::Variable(variable), Node::Variable(other)) => variable.is_equivalent(other),
+            (Node::Tag(tag), Node::Tag(other)) => tag.is_equivalent(other),
+            (Node::Block(block), Node::Block(other)) => block.is_equivalent(other),
+            (Node::TemplateComment(comment), Node::TemplateComment(other)) => {
+                comment.is_equivalent(other)
+            }
+            (Node::Raw(raw), Node::Raw(other)) => raw.is_equivalent(other),
+            _ => false,
+        }
+    }
 }
 
 impl<'i> Node<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        if let Ok(comment) = parse_comment.parse_next(input) {
-            return Ok(Self::Comment(comment));
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        Self::parse_in(&[], input)
+    }
+
+    /// Parses a node within the `open` elements and blocks, innermost last.
+    pub fn parse_in(open: &[Open<'i>], input: &mut Input<'i>) -> PResult<Self> {
+        let start = input.checkpoint();
+
+        if let Ok((value, span)) = parse_comment.with_span().parse_next(input) {
+            return Ok(Self::Comment(Spanned {
+                value,
+                span: span.into(),
+            }));
+        }
+        input.reset(&start);
+
+        if let Ok((value, span)) = Doctype::parse.with_span().parse_next(input) {
+            return Ok(Self::Doctype(Spanned {
+                value,
+                span: span.into(),
+            }));
+        }
+        input.reset(&start);
+
+        if let Ok((value, span)) = parse_processing_instruction.with_span().parse_next(input) {
+            return Ok(Self::ProcessingInstruction(Spanned {
+                value,
+                span: span.into(),
+            }));
         }
+        input.reset(&start);
 
-        if let Ok(element) = Element::parse.parse_next(input) {
-            return Ok(Self::Element(element));
+        if let Ok((value, span)) = parse_cdata.with_span().parse_next(input) {
+            return Ok(Self::CData(Spanned {
+                value,
+                span: span.into(),
+            }));
         }
+        input.reset(&start);
 
-        if let Ok(text) = parse_text.parse_next(input) {
-            if text.is_empty() {
+        if let Ok((value, span)) = Comment::parse.with_span().parse_next(input) {
+            return Ok(Self::TemplateComment(Spanned {
+                value,
+                span: span.into(),
+            }));
+        }
+        input.reset(&start);
+
+        match VariableTag::parse.parse_next(input) {
+            Ok(variable) => return Ok(Self::Variable(variable)),
+            Err(ErrMode::Cut(error)) => {
+                let error_offset = input.location();
+                input.reset(&start);
+                return Ok(Self::Raw(Raw::parse_token(input, error_offset, error)));
+            }
+            Err(_) => input.reset(&start),
+        }
+
+        let tag = match Tag::parse.with_taken().parse_next(input) {
+            Ok(tag) => Some(tag),
+            // Like an `{% if %}` with an invalid condition, or arguments that don't reach the `%}`
+            Err(ErrMode::Cut(error)) => {
+                let error_offset = input.location();
+                input.reset(&start);
+                return Ok(Self::Raw(Raw::parse_token(input, error_offset, error)));
+            }
+            Err(_) => None,
+        };
+        if let Some((tag, text)) = tag {
+            // Boundary tags are left for the block they belong to
+            let tags = input.state;
+            if tags.is_boundary(tag.tag_type) {
+                input.reset(&start);
                 return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
             }
-            return Ok(Self::Text(text));
+            let Some(definition) = tags.find(tag.tag_type) else {
+                return Ok(Self::Tag(tag));
+            };
+
+            // A block that can't be parsed leaves its opening tag as is, and everything after it
+            // is parsed as if the block wasn't there
+            let span = tag.span;
+            let after_tag = input.checkpoint();
+            let mut open = open.to_vec();
+            open.push(Open::Block(definition));
+            let parse_children = |input: &mut Input<'i>| parse_child_nodes_in(&open, &[], input);
+            return match Block::parse_rest(tag, definition, parse_children, input) {
+                Ok(block) => Ok(Self::Block(block)),
+                Err(ErrMode::Cut(error)) => {
+                    let error_offset = input.location();
+                    input.reset(&after_tag);
+                    Ok(Self::Raw(Raw {
+                        text,
+                        span,
+                        error_offset,
+                        error,
+                    }))
+                }
+                Err(error) => Err(error),
+            };
         }
+        input.reset(&start);
 
-        Err(ErrMode::from_error_kind(input, ErrorKind::Verify))
+        match Element::parse_in(open, input) {
+            Ok(element) => return Ok(Self::Element(element)),
+            Err(ErrMode::Cut(error)) => {
+                let error_offset = input.location();
+                input.reset(&start);
+                return Ok(Self::Raw(Raw::parse_token(input, error_offset, error)));
+            }
+            Err(_) => input.reset(&start),
+        }
+
+        let (value, span) = parse_text.with_span().parse_next(input)?;
+        if value.is_empty() {
+            return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
+        }
+        Ok(Self::Text(Spanned {
+            value,
+            span: span.into(),
+        }))
+    }
+
+    /// Where the node is in the source.
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Text(text)
+            | Node::Comment(text)
+            | Node::ProcessingInstruction(text)
+            | Node::CData(text) => text.span,
+            Node::Doctype(doctype) => doctype.span,
+            Node::Element(element) => element.span,
+            Node::Variable(variable) => variable.span,
+            Node::Tag(tag) => tag.span,
+            Node::Block(block) => block.span(),
+            Node::TemplateComment(comment) => comment.span,
+            Node::Raw(raw) => raw.span,
+        }
+    }
+
+    /// Where parsing failed and why, if the node is what was left of something that couldn't be
+    /// parsed.
+    pub fn parse_error(&self) -> Option<(usize, &ContextError)> {
+        match self {
+            Node::Raw(raw) => Some((raw.error_offset, &raw.error)),
+            Node::Element(Element {
+                variant:
+                    ElementVariant::Unclosed {
+                        error_offset,
+                        error,
+                    },
+                ..
+            }) => Some((*error_offset, error)),
+            _ => None,
+        }
+    }
+
+    /// The node as part of the text around it, if it flows along with it rather than going on its
+    /// own line. Blocks only do within text, which is for the caller to decide with
+    /// [`is_in_text`].
+    pub fn inline_doc<'a>(
+        &'a self,
+        indent_level: usize,
+        options: &'a FormatOptions,
+    ) -> Option<Doc<'a>> {
+        let doc = match self {
+            Node::Text(text) => return Some(Doc::text(text.value)),
+            Node::Element(element) => return element.inline_doc(indent_level, options),
+            Node::Block(block) => return block.inline_doc(indent_level, options),
+            Node::Variable(variable) => variable.to_doc(options),
+            Node::Tag(tag) => tag.to_doc(options),
+            Node::TemplateComment(comment) if !comment.value.is_block(indent_level, options) => {
+                Doc::text(comment.value.formatted(0, options))
+            }
+            Node::Comment(_) if options.whitespace_sensitivity != WhitespaceSensitivity::Ignore => {
+                self.content_doc(options)
+            }
+            // Like a stray closing tag, which mustn't add whitespace where there wasn't any
+            Node::Raw(raw) if !raw.text.contains('\n') => Doc::text(raw.text),
+            _ => return None,
+        };
+        // A group keeps it whole, rather than wrapped along with the text around it
+        Some(Doc::group(doc))
+    }
+
+    /// Whether the node flows along with the text around it, rather than going on its own line,
+    /// so that the whitespace next to it matters.
+    fn is_inline(&self, options: &FormatOptions) -> bool {
+        let whitespace_matters = options.whitespace_sensitivity != WhitespaceSensitivity::Ignore;
+        match self {
+            Node::TemplateComment(comment) => comment.value.kind == CommentKind::SingleLine,
+            Node::Raw(raw) => !raw.text.contains('\n'),
+            Node::Element(element) => whitespace_matters && element.is_inline_level(),
+            Node::Comment(_) => whitespace_matters,
+            _ => matches!(self, Node::Text(_) | Node::Variable(_) | Node::Tag(_)),
+        }
+    }
+
+    /// Whether the node and everything within it can flow along with text, which for a block is
+    /// when each of its branches holds nothing else.
+    fn has_inline_content(&self, options: &FormatOptions) -> bool {
+        match self {
+            Node::Block(block) => block
+                .children
+                .iter()
+                .chain(block.branches.iter().flat_map(|branch| &branch.children))
+                .all(|child| child.has_inline_content(options)),
+            _ => self.is_inline(options),
+        }
     }
 }
 
+/// Whether the node at `index` flows along with the text around it. A block does when it only
+/// holds inline nodes and is within an inline element, as `in_inline` says, or has text or an
+/// inline node next to it, whatever whitespace is between them.
+pub fn is_in_text(nodes: &[Node], index: usize, in_inline: bool, options: &FormatOptions) -> bool {
+    let node = &nodes[index];
+    if !matches!(node, Node::Block(_)) {
+        return node.is_inline(options);
+    }
+    if !node.has_inline_content(options) {
+        return false;
+    }
+    let is_content = |node: &&Node| !is_blank(std::slice::from_ref(*node));
+    let before = nodes[..index].iter().rev().find(is_content);
+    let after = nodes[index + 1..].iter().find(is_content);
+    in_inline
+        || [before, after]
+            .into_iter()
+            .flatten()
+            .any(|node| node.is_inline(options))
+}
+
+/// Each node goes on its own line, apart from text, which is left out when it's only whitespace.
 impl<'i> Formatable for Node<'i> {
-    fn formatted(&self, indent_level: usize) -> String {
-        let indent = "\t".repeat(indent_level);
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        if is_blank(std::slice::from_ref(self)) {
+            return Doc::Nil;
+        }
+        Doc::concat([self.content_doc(options), Doc::HardLine])
+    }
+}
+
+impl<'i> Node<'i> {
+    /// The node without the line break after it.
+    pub fn content_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
         match self {
-            Node::Text(text) => {
-                // Don't include empty text nodes
-                let trimmed = text.trim();
-                if trimmed.is_empty() {
-                    return String::new();
+            Node::Text(text) => text_doc(text.value, options.max_blank_lines).unwrap_or(Doc::Nil),
+            Node::Element(element) => element.to_doc(options),
+            Node::Comment(comment) => Doc::text(format!("<!-- {} -->", comment.value)),
+            Node::Doctype(doctype) => doctype.value.to_doc(options),
+            Node::ProcessingInstruction(text) | Node::CData(text) => Doc::text(text.value),
+            Node::Variable(variable) => variable.to_doc(options),
+            Node::Tag(tag) => tag.to_doc(options),
+            Node::Block(block) => block.to_doc(options),
+            Node::TemplateComment(comment) => comment.value.to_doc(options),
+            Node::Raw(raw) => Doc::text(raw.text),
+        }
+    }
+}
+
+/// Lays out a list of sibling nodes one per line, keeping runs of inline nodes like text and
+/// variables together, along with the blocks within them. The nodes are `in_inline` content when
+/// they're within an inline element.
+pub fn child_nodes_doc<'a>(
+    nodes: &'a [Node],
+    in_inline: bool,
+    options: &'a FormatOptions,
+) -> Doc<'a> {
+    // Whether a node fits within the text around it depends on how deeply it's nested
+    Doc::nesting(move |indent_level| {
+        let mut docs = vec![];
+        let mut inline_run = vec![];
+
+        for (i, node) in nodes.iter().enumerate() {
+            let in_text =
+                !matches!(node, Node::Block(_)) || is_in_text(nodes, i, in_inline, options);
+            if let Some(inline) = in_text
+                .then(|| node.inline_doc(indent_level, options))
+                .flatten()
+            {
+                inline_run.push(inline);
+                continue;
+            }
+
+            docs.extend(inline_run_doc(std::mem::take(&mut inline_run), options));
+            docs.push(node.content_doc(options));
+        }
+        docs.extend(inline_run_doc(inline_run, options));
+
+        Doc::join(Doc::HardLine, docs)
+    })
+}
+
+/// Formats a list of sibling nodes, with each line ending in a line break.
+pub fn format_child_nodes(nodes: &[Node], indent_level: usize, options: &FormatOptions) -> String {
+    if is_blank(nodes) {
+        return String::new();
+    }
+    Doc::concat([child_nodes_doc(nodes, false, options), Doc::HardLine])
+        .print(indent_level, options)
+}
+
+/// Whether the nodes are nothing but whitespace, which leaves nothing to format.
+pub fn is_blank(nodes: &[Node]) -> bool {
+    nodes
+        .iter()
+        .all(|node| matches!(node, Node::Text(text) if text.value.trim().is_empty()))
+}
+
+/// Calls `f` on every node in `nodes`, including the ones nested within elements and blocks.
+pub fn for_each_node<'i>(nodes: &[Node<'i>], f: &mut impl FnMut(&Node<'i>)) {
+    for node in nodes {
+        f(node);
+        match node {
+            Node::Element(element) => for_each_node(&element.children, f),
+            Node::Block(block) => {
+                for_each_node(&block.children, f);
+                for branch in &block.branches {
+                    for_each_node(&branch.children, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` on every element in `nodes`, including the ones nested within other elements and
+/// blocks.
+pub fn for_each_element<'i>(nodes: &[Node<'i>], f: &mut impl FnMut(&Element<'i>)) {
+    for node in nodes {
+        match node {
+            Node::Element(element) => {
+                f(element);
+                for_each_element(&element.children, f);
+            }
+            Node::Block(block) => {
+                for_each_element(&block.children, f);
+                for branch in &block.branches {
+                    for_each_element(&branch.children, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Like [`for_each_element`], but with mutable access to each element.
+pub fn for_each_element_mut<'i>(nodes: &mut [Node<'i>], f: &mut impl FnMut(&mut Element<'i>)) {
+    for node in nodes {
+        match node {
+            Node::Element(element) => {
+                f(element);
+                for_each_element_mut(&mut element.children, f);
+            }
+            Node::Block(block) => {
+                for_each_element_mut(&mut block.children, f);
+                for branch in &mut block.branches {
+                    for_each_element_mut(&mut branch.children, f);
                 }
-                format!("{indent}{}\n", trimmed)
             }
-            Node::Element(element) => format!("{}\n", element.formatted(indent_level)),
-            Node::Comment(comment) => format!("{indent}<!-- {comment} -->\n"),
+            _ => {}
         }
     }
 }
 
-pub fn parse_child_nodes<'i>(input: &mut &'i str) -> PResult<Vec<Node<'i>>> {
+/// Calls `f` on every filter in `nodes`, whether it's on a variable, a tag argument, or within
+/// an attribute value.
+pub fn for_each_filter<'i>(nodes: &[Node<'i>], f: &mut impl FnMut(&Filter<'i>)) {
+    for_each_node(nodes, &mut |node| match node {
+        Node::Variable(variable) => variable.filters.iter().for_each(&mut *f),
+        Node::Tag(tag) => for_each_tag_filter(tag, f),
+        Node::Block(block) => {
+            for_each_tag_filter(&block.opening_tag, f);
+            for branch in &block.branches {
+                for_each_tag_filter(&branch.tag, f);
+            }
+            for_each_tag_filter(&block.closing_tag, f);
+        }
+        Node::Element(element) => {
+            for attribute in &element.attributes.kvs {
+                if let Some(value) = &attribute.value {
+                    for_each_filter(&value.nodes, f);
+                }
+            }
+        }
+        _ => {}
+    });
+}
+
+/// Calls `f` with the span of every template tag and variable in `nodes`, including the tags of
+/// blocks and the ones within attribute values.
+pub fn for_each_template_syntax(nodes: &[Node], f: &mut impl FnMut(Span)) {
+    for_each_node(nodes, &mut |node| match node {
+        Node::Variable(variable) => f(variable.span),
+        Node::Tag(tag) => f(tag.span),
+        Node::Block(block) => {
+            f(block.opening_tag.span);
+            for branch in &block.branches {
+                f(branch.tag.span);
+            }
+            f(block.closing_tag.span);
+        }
+        Node::Element(element) => {
+            for attribute in &element.attributes.kvs {
+                if let Some(value) = &attribute.value {
+                    for_each_template_syntax(&value.nodes, f);
+                }
+            }
+        }
+        _ => {}
+    });
+}
+
+fn for_each_tag_filter<'i>(tag: &Tag<'i>, f: &mut impl FnMut(&Filter<'i>)) {
+    let operands = tag
+        .condition
+        .iter()
+        .flat_map(|condition| condition.operands());
+    for argument in tag.arguments.iter().chain(operands) {
+        argument.filters.iter().for_each(&mut *f);
+    }
+}
+
+/// A node that matters when comparing templates, with runs of text merged together and their
+/// whitespace normalized.
+enum Significant<'a, 'i> {
+    Text(String, Span),
+    /// A node, and whether it flows along with the text around it
+    Node(&'a Node<'i>, bool),
+}
+
+impl Significant<'_, '_> {
+    fn span(&self) -> Span {
+        match self {
+            Significant::Text(_, span) => *span,
+            Significant::Node(node, _) => node.span(),
+        }
+    }
+}
+
+/// Merges runs of text and collapses their whitespace. Whitespace next to a node that goes on its
+/// own line is dropped as the formatter is free to change it, and so is whitespace at the start
+/// or end of the nodes, unless they're `in_inline` content like the body of an inline element.
+fn significant_nodes<'a, 'i>(
+    nodes: &'a [Node<'i>],
+    in_inline: bool,
+    options: &FormatOptions,
+) -> Vec<Significant<'a, 'i>> {
+    let mut significant = vec![];
+    let mut text = String::new();
+    let mut text_span: Option<Span> = None;
+    // Whether the text follows something that flows along with it
+    let mut after_inline = in_inline;
+
+    for (i, node) in nodes.iter().enumerate() {
+        if let Node::Text(value) = node {
+            text.push_str(value.value);
+            text_span = Some(match text_span {
+                Some(span) => (span.start..value.span.end).into(),
+                None => value.span,
+            });
+            continue;
+        }
+
+        let inline = is_in_text(nodes, i, in_inline, options);
+        if let Some(span) = text_span.take() {
+            let text = normalize_whitespace(&std::mem::take(&mut text), !after_inline, !inline);
+            if !text.is_empty() {
+                significant.push(Significant::Text(text, span));
+            }
+        }
+        after_inline = inline;
+        significant.push(Significant::Node(node, inline));
+    }
+    if let Some(span) = text_span {
+        let text = normalize_whitespace(&text, !after_inline, !in_inline);
+        if !text.is_empty() {
+            significant.push(Significant::Text(text, span));
+        }
+    }
+
+    significant
+}
+
+/// Collapses each run of whitespace into a single space, dropping it entirely from the start or end
+/// if asked to.
+fn normalize_whitespace(text: &str, trim_start: bool, trim_end: bool) -> String {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let leading = !trim_start && text.starts_with(char::is_whitespace);
+    let trailing = !trim_end && text.ends_with(char::is_whitespace);
+    if words.is_empty() {
+        return if leading && trailing { " " } else { "" }.to_string();
+    }
+
+    let mut normalized = words.join(" ");
+    if leading {
+        normalized.insert(0, ' ');
+    }
+    if trailing {
+        normalized.push(' ');
+    }
+    normalized
+}
+
+/// Finds the first place where `nodes` and `other` differ, ignoring whitespace that the formatter
+/// is free to change and anything that was written differently but means the same. Returns the
+/// span of the differing node in `nodes`, or `parent` if `other` has nodes that `nodes` doesn't.
+/// The nodes are `in_inline` content when they're within an inline element or block.
+pub fn find_difference(
+    nodes: &[Node],
+    other: &[Node],
+    parent: Span,
+    in_inline: bool,
+    options: &FormatOptions,
+) -> Option<Span> {
+    let significant = significant_nodes(nodes, in_inline, options);
+    let other = significant_nodes(other, in_inline, options);
+
+    for (i, node) in significant.iter().enumerate() {
+        let Some(other_node) = other.get(i) else {
+            return Some(node.span());
+        };
+        let difference = match (node, other_node) {
+            (Significant::Text(text, span), Significant::Text(other_text, _)) => {
+                (text != other_text).then_some(*span)
+            }
+            (Significant::Node(node, inline), Significant::Node(other_node, _)) => {
+                find_node_difference(node, other_node, *inline, options)
+            }
+            _ => Some(node.span()),
+        };
+        if difference.is_some() {
+            return difference;
+        }
+    }
+
+    (other.len() > significant.len()).then_some(parent)
+}
+
+/// Finds the first difference within `node`, which is `inline` when it flows along with the text
+/// around it.
+fn find_node_difference(
+    node: &Node,
+    other: &Node,
+    inline: bool,
+    options: &FormatOptions,
+) -> Option<Span> {
+    match (node, other) {
+        (Node::Element(element), Node::Element(other_element)) => {
+            if element.name != other_element.name
+                || !element.variant.is_equivalent(&other_element.variant)
+                || !element.attributes.is_equivalent(&other_element.attributes)
+            {
+                return Some(element.span);
+            }
+            if element.is_verbatim() {
+                return (!element.children.is_equivalent(&other_element.children))
+                    .then_some(element.span);
+            }
+            find_difference(
+                &element.children,
+                &other_element.children,
+                element.span,
+                inline,
+                options,
+            )
+        }
+        (Node::Block(block), Node::Block(other_block)) => {
+            if !block.opening_tag.is_equivalent(&other_block.opening_tag)
+                || !block.closing_tag.is_equivalent(&other_block.closing_tag)
+                || block.branches.len() != other_block.branches.len()
+            {
+                return Some(block.span());
+            }
+            // Whitespace is significant in the body of a raw block
+            if block.raw || other_block.raw {
+                return (!block.children.is_equivalent(&other_block.children))
+                    .then(|| block.span());
+            }
+            find_difference(
+                &block.children,
+                &other_block.children,
+                block.span(),
+                inline,
+                options,
+            )
+            .or_else(|| {
+                block.branches.iter().zip(&other_block.branches).find_map(
+                    |(branch, other_branch)| {
+                        if !branch.tag.is_equivalent(&other_branch.tag) {
+                            return Some(branch.tag.span);
+                        }
+                        find_difference(
+                            &branch.children,
+                            &other_branch.children,
+                            block.span(),
+                            inline,
+                            options,
+                        )
+                    },
+                )
+            })
+        }
+        _ => (!node.is_equivalent(other)).then(|| node.span()),
+    }
+}
+
+pub fn parse_child_nodes<'i>(input: &mut Input<'i>) -> PResult<Vec<Node<'i>>> {
+    parse_child_nodes_in(&[], &[], input)
+}
+
+/// Parses child nodes within the `open` elements and blocks, innermost last, up until a closing
+/// tag that belongs to one of them, or the opening tag of any of the `closing` elements, which
+/// implies the end of the parent, like `<li>` does for another `<li>`. Any other closing tag is
+/// kept as it was written, and parsing carries on after it.
+pub fn parse_child_nodes_in<'i>(
+    open: &[Open<'i>],
+    closing: &[&str],
+    input: &mut Input<'i>,
+) -> PResult<Vec<Node<'i>>> {
     let mut nodes = vec![];
+    // Blocks whose opening tag couldn't be parsed, whose other tags are then left as they are
+    // rather than reported too
+    let mut broken_blocks: Vec<&BlockDefinition> = vec![];
 
     while !input.is_empty() {
+        let start = input.checkpoint();
         let initial_len = input.len();
-        let peek_result = peek(Node::parse).parse_peek(input);
 
-        if peek_result.is_err() {
-            return Ok(nodes);
+        if let Ok(name) = preceded('<', parse_tag_name).parse_next(input) {
+            input.reset(&start);
+            if closing
+                .iter()
+                .any(|closing| closing.eq_ignore_ascii_case(name))
+            {
+                return Ok(nodes);
+            }
         }
-        let node = Node::parse.parse_next(input);
-        match node {
-            Ok(node) => nodes.push(node),
-            Err(_) => return Ok(nodes),
+        input.reset(&start);
+
+        let closing_tag = match input.starts_with("</") {
+            true => ClosingTag::parse.with_taken().parse_next(input).ok(),
+            false => None,
+        };
+        if let Some((closing_tag, text)) = closing_tag {
+            if open.iter().any(|open| open.is_closed_by(closing_tag.name)) {
+                input.reset(&start);
+                return Ok(nodes);
+            }
+            nodes.push(Node::Raw(Raw {
+                text,
+                span: closing_tag.span,
+                error_offset: closing_tag.span.start,
+                error: stray_closing_tag_error(input, &start),
+            }));
+            continue;
+        }
+        input.reset(&start);
+
+        let tags = input.state;
+        if let Ok((tag, text)) = Tag::parse.with_taken().parse_next(input) {
+            if tags.is_boundary(tag.tag_type) {
+                if open.iter().any(|open| open.has_boundary(tag.tag_type)) {
+                    input.reset(&start);
+                    return Ok(nodes);
+                }
+                if let Some(i) = broken_blocks
+                    .iter()
+                    .rposition(|definition| definition.is_boundary(tag.tag_type))
+                {
+                    if broken_blocks[i].end_tag == tag.tag_type {
+                        broken_blocks.truncate(i);
+                    }
+                    nodes.push(Node::Tag(tag));
+                    continue;
+                }
+                // The innermost block reports the tag as the wrong one to end it, unless a broken
+                // block has already been reported
+                if broken_blocks.is_empty() && matches!(open.last(), Some(Open::Block(_))) {
+                    input.reset(&start);
+                    return Ok(nodes);
+                }
+                nodes.push(Node::Raw(Raw {
+                    text,
+                    span: tag.span,
+                    error_offset: tag.span.start,
+                    error: unexpected_boundary_error(input, &start)
+                        .into_inner()
+                        .unwrap_or_default(),
+                }));
+                continue;
+            }
+        }
+        input.reset(&start);
+
+        let block = parse_tag_type
+            .parse_peek(*input)
+            .ok()
+            .and_then(|(_, tag_type)| tags.find(tag_type));
+        match Node::parse_in(open, input) {
+            Ok(node) => {
+                if let (Node::Raw(_), Some(definition)) = (&node, block) {
+                    broken_blocks.push(definition);
+                }
+                nodes.push(node);
+            }
+            Err(ErrMode::Cut(error)) => return Err(ErrMode::Cut(error)),
+            Err(_) => {
+                input.reset(&start);
+                return Ok(nodes);
+            }
         }
 
         // Check if the parser consumed any input
@@ -78,6 +851,20 @@ pub fn parse_child_nodes<'i>(input: &mut &'i str) -> PResult<Vec<Node<'i>>> {
     Ok(nodes)
 }
 
+/// The error for a closing tag like `</div>` with no element open for it to close.
+fn stray_closing_tag_error<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+) -> ContextError {
+    ContextError::new()
+        .add_context(input, start, StrContext::Label("element"))
+        .add_context(
+            input,
+            start,
+            StrContext::Expected(StrContextValue::Description("a matching opening tag")),
+        )
+}
+
 #[cfg(test)]
 mod tests {
     use crate::html_parser::{attribute::Attributes, element::ElementVariant};
@@ -85,106 +872,112 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
+    use crate::template_parser::argument::TagArgumentValue;
+    use crate::template_parser::registry::TagRegistry;
 
     #[rstest]
-    #[case("<!-- -->", Node::Comment(""), "")]
-    #[case("<!--     -->", Node::Comment(""), "")]
-    #[case("<!---->", Node::Comment(""), "")]
-    #[case("<!-- my comment -->", Node::Comment("my comment"), "")]
-    #[case("<!-- my-comment -->", Node::Comment("my-comment"), "")]
-    #[case("<!--my-comment-->", Node::Comment("my-comment"), "")]
-    #[case("<!--     my-comment       -->", Node::Comment("my-comment"), "")]
-    #[case("hello there", Node::Text("hello there"), "")]
+    #[case("<!-- -->", Node::Comment("".into()), "")]
+    #[case("<!--     -->", Node::Comment("".into()), "")]
+    #[case("<!---->", Node::Comment("".into()), "")]
+    #[case("<!-- my comment -->", Node::Comment("my comment".into()), "")]
+    #[case("<!-- my-comment -->", Node::Comment("my-comment".into()), "")]
+    #[case("<!--my-comment-->", Node::Comment("my-comment".into()), "")]
+    #[case("<!--     my-comment       -->", Node::Comment("my-comment".into()), "")]
+    #[case("hello there", Node::Text("hello there".into()), "")]
+    #[case("<!doctype html><p>", Node::Doctype(Doctype { name: "html", rest: "" }.into()), "<p>")]
+    #[case("<?xml version=\"1.0\"?>", Node::ProcessingInstruction("<?xml version=\"1.0\"?>".into()), "")]
+    #[case("<![CDATA[<p>]]>", Node::CData("<![CDATA[<p>]]>".into()), "")]
     #[case("<img />", Node::Element(Element {
-        id: None,
         name: "img",
         variant: ElementVariant::Void,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }), "")]
     #[case("<div></div>", Node::Element(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     }), "")]
     #[case("<div><img /></div>", Node::Element(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![
             Node::Element(Element {
-                id: None,
                 name: "img",
                 variant: ElementVariant::Void,
                 attributes: Attributes::default(),
-                classes: vec![],
                 children: vec![],
+                span: Span::default(),
             }),
         ],
+        span: Span::default(),
     }), "")]
     fn test_node_parses_successfully(
         #[case] input: &str,
         #[case] expected: Node,
         #[case] remaining: &str,
     ) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         let actual = Node::parse.parse_next(&mut input).unwrap();
-        assert_eq!(actual, expected);
-        assert_eq!(input, remaining);
+        assert_equivalent(&actual, &expected);
+        assert_eq!(**input, remaining);
     }
 
     #[rstest]
-    #[case("<!---->test<!---->", vec![Node::Comment(""), Node::Text("test"),Node::Comment("")], "")]
+    #[case("<!---->test<!---->", vec![Node::Comment("".into()), Node::Text("test".into()),Node::Comment("".into())], "")]
     #[case("<div><!---->test<!----></div>", vec![
         Node::Element(Element {
-            id: None,
             name: "div",
             variant: ElementVariant::Normal,
             attributes: Attributes::default(),
-            classes: vec![],
-            children: vec![Node::Comment(""), Node::Text("test"),Node::Comment("")],
+            children: vec![Node::Comment("".into()), Node::Text("test".into()),Node::Comment("".into())],
+            span: Span::default(),
         }),
     ], "")]
     #[case("<div/>", vec![Node::Element(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Void,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     })], "")]
-    #[case("</div>", vec![], "</div>")]
+    #[case("<div>{{ a }}</div>{# b #}", vec![
+        Node::Element(Element {
+            name: "div",
+            variant: ElementVariant::Normal,
+            attributes: Attributes::default(),
+            children: vec![Node::Variable(VariableTag { value: TagArgumentValue::Variable("a"), filters: vec![], span: Span::default() })],
+            span: Span::default(),
+        }),
+        Node::TemplateComment(Comment::single_line("b").into()),
+    ], "")]
     #[case("<div></div>", vec![Node::Element(Element {
-        id: None,
         name: "div",
         variant: ElementVariant::Normal,
         attributes: Attributes::default(),
-        classes: vec![],
         children: vec![],
+        span: Span::default(),
     })], "")]
     #[case("<div></div><div></div>", vec![
         Node::Element(Element {
-            id: None,
             name: "div",
             variant: ElementVariant::Normal,
             attributes: Attributes::default(),
-            classes: vec![],
             children: vec![],
+            span: Span::default(),
         }),
         Node::Element(Element {
-            id: None,
             name: "div",
             variant: ElementVariant::Normal,
             attributes: Attributes::default(),
-            classes: vec![],
             children: vec![],
+            span: Span::default(),
         }),
     ], "")]
     fn test_parse_child_nodes(
@@ -192,23 +985,88 @@ mod tests {
         #[case] expected: Vec<Node>,
         #[case] remaining: &str,
     ) {
-        let mut input = input;
+        let mut input = new_input(input);
         let actual = parse_child_nodes.parse_next(&mut input).unwrap();
-        assert_eq!(input, remaining);
-        assert_eq!(actual, expected);
+        assert_eq!(**input, remaining);
+        assert_equivalent(&actual, &expected);
+    }
+
+    #[rstest]
+    #[case::closing_tag("a</div>", Open::Element("div"), "</div>")]
+    #[case::boundary_tag("a{% endif %}", Open::Block(TagRegistry::builtin().find("if").unwrap()), "{% endif %}")]
+    fn test_parse_child_nodes_stops_at_open(
+        #[case] input: &str,
+        #[case] open: Open,
+        #[case] remaining: &str,
+    ) {
+        let mut input = new_input(input);
+        let actual = parse_child_nodes_in(&[open], &[], &mut input).unwrap();
+        assert_eq!(**input, remaining);
+        assert_equivalent(&actual, &vec![Node::Text("a".into())]);
+    }
+
+    #[rstest]
+    #[case::closing_tag("a</div>b", "</div>")]
+    #[case::boundary_tag("a{% endif %}b", "{% endif %}")]
+    fn test_parse_child_nodes_keeps_stray_tags(#[case] input: &str, #[case] stray: &str) {
+        let actual = parse_child_nodes.parse(new_input(input)).unwrap();
+        assert_eq!(actual.len(), 3);
+        assert!(matches!(&actual[1], Node::Raw(raw) if raw.text == stray));
+        assert_equivalent(&actual[2], &Node::Text("b".into()));
+    }
+
+    #[rstest]
+    fn test_node_spans() {
+        let input = "<div class=\"a\">\n\t{{ b|c }}{% if d %}e{% endif %}<!-- f -->\n</div>";
+        let Node::Element(element) = Node::parse.parse(new_input(input)).unwrap() else {
+            panic!("expected an element");
+        };
+        assert_eq!(element.span.range(), 0..65);
+        assert_eq!(element.attributes.kvs[0].span.range(), 5..14);
+
+        let spans = element
+            .children
+            .iter()
+            .map(|node| node.span().range())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![15..17, 17..26, 26..48, 48..58, 58..59]);
+
+        let (Node::Variable(variable), Node::Block(block)) =
+            (&element.children[1], &element.children[2])
+        else {
+            panic!("expected a variable and a block");
+        };
+        assert_eq!(variable.filters[0].span.range(), 22..23);
+        let condition = block.opening_tag.condition.as_ref().unwrap();
+        assert_eq!(condition.operands()[0].span.range(), 32..33);
+        assert_eq!(block.children[0].span().range(), 36..37);
+        assert_eq!(block.closing_tag.span.range(), 37..48);
+    }
+
+    #[rstest]
+    fn test_formatting_document() {
+        let input = "<?xml version=\"1.0\"?>\n<!doctype html>\n<html><svg><![CDATA[ a  <  b ]]></svg></html>";
+        let expected = "<?xml version=\"1.0\"?>\n<!DOCTYPE html>\n<html>\n\t<svg>\n\t\t<![CDATA[ a  <  b ]]>\n\t</svg>\n</html>\n";
+        let nodes = parse_child_nodes.parse(new_input(input)).unwrap();
+        assert_eq!(
+            format_child_nodes(&nodes, 0, &FormatOptions::default()),
+            expected
+        );
     }
 
     #[rstest]
     fn test_formatting_node_multiple_times_doesnt_change_output() {
         let expected = "<div>\n\ttext\n</div>\n";
-        let parsed = Node::parse.parse(expected).unwrap();
+        let parsed = Node::parse.parse_next(&mut new_input(expected)).unwrap();
 
-        let first_format = parsed.formatted(0);
+        let first_format = parsed.formatted(0, &FormatOptions::default());
         assert_eq!(expected, first_format);
 
-        let second_parse = Node::parse.parse(first_format.as_str()).unwrap();
+        let second_parse = Node::parse
+            .parse_next(&mut new_input(first_format.as_str()))
+            .unwrap();
 
-        let second_format = second_parse.formatted(0);
+        let second_format = second_parse.formatted(0, &FormatOptions::default());
         assert_eq!(expected, second_format);
     }
 }
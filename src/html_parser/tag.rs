@@ -1,9 +1,13 @@
-use crate::formatting::Formatable;
-use crate::html_parser::attribute::Attributes;
+use crate::doc::Doc;
+use crate::formatting::{BracketPlacement, FormatOptions, Formatable};
+use crate::html_parser::attribute::{format_attribute, Attributes};
+use crate::span::{Equivalent, Input, Span};
 use winnow::{
     ascii::multispace0,
-    combinator::{delimited, opt},
-    token::take_while,
+    combinator::{delimited, opt, peek, preceded},
+    error::ErrMode,
+    stream::Stream,
+    token::{any, take_while},
     PResult, Parser,
 };
 
@@ -14,33 +18,46 @@ const VOID_ELEMENT_NAMES: &[&str] = &[
     "track", "wbr",
 ];
 
-pub fn parse_tag_name<'i>(input: &mut &'i str) -> PResult<&'i str> {
+/// Whether the element never has content or a closing tag, like `<br>`. Like every element name,
+/// it's matched regardless of case.
+pub fn is_void_element(name: &str) -> bool {
+    VOID_ELEMENT_NAMES
+        .iter()
+        .any(|void_name| void_name.eq_ignore_ascii_case(name))
+}
+
+/// Parses a tag name, which must start with an ASCII letter so that things like `<!doctype>` or
+/// `a < b` aren't mistaken for tags.
+pub fn parse_tag_name<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    peek(any.verify(|c: &char| c.is_ascii_alphabetic())).parse_next(input)?;
+
     take_while(1.., |c: char| {
-        c.is_ascii() && c != '/' && c != '>' && c != ' '
+        c.is_ascii() && !c.is_ascii_whitespace() && c != '/' && c != '>'
     })
     .parse_next(input)
 }
 
 /// An HTML open tag, like `<a href="google.com">`.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Tag<'i> {
     /// Like 'div'
     pub name: &'i str,
     pub attributes: Attributes<'i>,
     pub variant: ElementVariant,
+    pub span: Span,
 }
 
-impl<'i> PartialEq for Tag<'i> {
-    fn eq(&self, other: &Self) -> bool {
+impl Equivalent for Tag<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
         self.name == other.name
-            && self.attributes == other.attributes
+            && self.attributes.is_equivalent(&other.attributes)
             && self.variant == other.variant
     }
 }
 
 impl<'i> Tag<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let mut parser = delimited(
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let parser = delimited(
             ('<', multispace0),
             (
                 parse_tag_name,
@@ -50,15 +67,18 @@ impl<'i> Tag<'i> {
             (multispace0, '>'),
         );
 
-        parser.parse_peek(input)?;
-
-        parser
-            .map(|(name, attributes, variant)| {
-                if VOID_ELEMENT_NAMES.contains(&name) {
+        // Don't consume anything unless the whole tag parses, but leave the input where a cut
+        // error happened so it can be reported there
+        let start = input.checkpoint();
+        let result = parser
+            .with_span()
+            .map(|((name, attributes, variant), span)| {
+                if is_void_element(name) {
                     return Self {
                         name,
                         attributes,
                         variant: ElementVariant::Void,
+                        span: span.into(),
                     };
                 }
                 Self {
@@ -68,54 +88,88 @@ impl<'i> Tag<'i> {
                         Some(_) => ElementVariant::Void,
                         None => ElementVariant::Normal,
                     },
+                    span: span.into(),
                 }
             })
-            .parse_next(input)
+            .parse_next(input);
+        if let Err(ErrMode::Backtrack(_)) = result {
+            input.reset(&start);
+        }
+        result
     }
 }
 
 impl<'i> Formatable for Tag<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        let mut html = String::new();
-        html.push('<');
-        html.push_str(self.name);
-        for (key, val) in &self.attributes.kvs {
-            html.push(' ');
-            html.push_str(key);
-            if let Some(val) = val {
-                html.push_str("=\"");
-                html.push_str(val);
-                html.push('"');
-            }
-        }
-        html.push('>');
-        html
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        opening_tag_doc(self.name, &self.attributes, false, options)
     }
 }
 
+/// An opening tag like `<a href="/">`, or `<br />` if it's `self_closing`. When it doesn't fit
+/// within the line width, each attribute goes on its own line, one level deeper.
+pub fn opening_tag_doc<'a>(
+    name: &'a str,
+    attributes: &'a Attributes,
+    self_closing: bool,
+    options: &'a FormatOptions,
+) -> Doc<'a> {
+    let end = if self_closing { " />" } else { ">" };
+    if attributes.kvs.is_empty() {
+        return Doc::text(format!("<{name}{end}"));
+    }
+
+    let attributes = attributes.iter().map(|(key, value)| {
+        Doc::concat([
+            Doc::Line,
+            Doc::text(format_attribute(key, value.as_ref(), options)),
+        ])
+    });
+    let end = match options.bracket_placement {
+        BracketPlacement::SameLine => Doc::text(end),
+        BracketPlacement::NewLine if self_closing => Doc::concat([Doc::Line, Doc::text("/>")]),
+        BracketPlacement::NewLine => Doc::concat([Doc::SoftLine, Doc::text(">")]),
+    };
+    Doc::group(Doc::concat([
+        Doc::text("<"),
+        Doc::text(name),
+        Doc::indent(Doc::concat(attributes)),
+        end,
+    ]))
+}
+
 /// An HTML closing tag, like `</a>`.
 #[derive(Debug, PartialEq)]
 pub struct ClosingTag<'i> {
     /// Like 'div'
     pub name: &'i str,
+    pub span: Span,
+}
+
+impl Equivalent for ClosingTag<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
 }
 
 impl<'i> ClosingTag<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let tag = delimited(
-            (multispace0, "</", multispace0),
-            parse_tag_name,
-            (multispace0, ">", multispace0),
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        // Whitespace after the tag is left alone, as it's significant after inline elements
+        let (tag, span) = preceded(
+            multispace0,
+            delimited(("</", multispace0), parse_tag_name, (multispace0, ">")).with_span(),
         )
         .parse_next(input)?;
 
-        Ok(Self { name: tag })
+        Ok(Self {
+            name: tag,
+            span: span.into(),
+        })
     }
 }
 
 impl<'i> Formatable for ClosingTag<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        format!("</{}>", self.name)
+    fn to_doc<'a>(&'a self, _options: &'a FormatOptions) -> Doc<'a> {
+        Doc::text(format!("</{}>", self.name))
     }
 }
 
@@ -125,6 +179,7 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     fn test_link_tag() {
@@ -132,12 +187,13 @@ mod tests {
         let expected = Tag {
             name: "a",
             variant: ElementVariant::Normal,
-            attributes: Attributes {
-                kvs: [("href", Some("https://google.com"))].into_iter().collect(),
-            },
+            attributes: [("href", Some("https://google.com".into()))]
+                .into_iter()
+                .collect(),
+            span: Span::default(),
         };
-        let actual = Tag::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
@@ -146,14 +202,13 @@ mod tests {
         let expected = Tag {
             name: "div",
             variant: ElementVariant::Normal,
-            attributes: Attributes {
-                kvs: [("width", Some("40")), ("height", Some("30"))]
-                    .into_iter()
-                    .collect(),
-            },
+            attributes: [("width", Some("40".into())), ("height", Some("30".into()))]
+                .into_iter()
+                .collect(),
+            span: Span::default(),
         };
-        let actual = Tag::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
@@ -162,14 +217,13 @@ mod tests {
         let expected = Tag {
             name: "div",
             variant: ElementVariant::Void,
-            attributes: Attributes {
-                kvs: [("width", Some("40")), ("height", Some("30"))]
-                    .into_iter()
-                    .collect(),
-            },
+            attributes: [("width", Some("40".into())), ("height", Some("30".into()))]
+                .into_iter()
+                .collect(),
+            span: Span::default(),
         };
-        let actual = Tag::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
@@ -178,41 +232,80 @@ mod tests {
         let expected = Tag {
             name: "div",
             variant: ElementVariant::Void,
-            attributes: Attributes {
-                kvs: [("width", Some("40")), ("height", Some("30"))]
-                    .into_iter()
-                    .collect(),
-            },
+            attributes: [("width", Some("40".into())), ("height", Some("30".into()))]
+                .into_iter()
+                .collect(),
+            span: Span::default(),
+        };
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
+    }
+
+    #[rstest]
+    fn test_tag_with_attributes_on_their_own_lines() {
+        let input = "<link\n\thref=\"a.css\"\n\trel=\"stylesheet\"\n/>";
+        let expected = Tag {
+            name: "link",
+            variant: ElementVariant::Void,
+            attributes: [
+                ("href", Some("a.css".into())),
+                ("rel", Some("stylesheet".into())),
+            ]
+            .into_iter()
+            .collect(),
+            span: Span::default(),
         };
-        let actual = Tag::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
-    #[case("</div>", ClosingTag { name: "div" })]
-    #[case("</ div>", ClosingTag { name: "div" })]
-    #[case("</div >", ClosingTag { name: "div" })]
-    #[case("</ div >", ClosingTag { name: "div" })]
+    #[case(120, "<div width=\"40\" height=\"30\">")]
+    #[case(20, "<div\n\twidth=\"40\"\n\theight=\"30\"\n>")]
+    fn test_formatting_tag(#[case] line_width: usize, #[case] expected: &str) {
+        let options = FormatOptions {
+            line_width,
+            ..FormatOptions::default()
+        };
+        let tag = Tag::parse
+            .parse(new_input("<div width=\"40\" height=\"30\">"))
+            .unwrap();
+        assert_eq!(tag.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case("</div>", ClosingTag { name: "div", span: Span::default() })]
+    #[case("</ div>", ClosingTag { name: "div", span: Span::default() })]
+    #[case("</div >", ClosingTag { name: "div", span: Span::default() })]
+    #[case("</ div >", ClosingTag { name: "div", span: Span::default() })]
     fn test_closing_tag(#[case] input: &str, #[case] expected: ClosingTag) {
-        let actual = ClosingTag::parse.parse(input).unwrap();
-        assert_eq!(expected, actual);
+        let actual = ClosingTag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected);
     }
 
     #[rstest]
     #[case("</div>", "</div>")]
     fn test_tag_doesnt_consume_input(#[case] input: &str, #[case] expected: &str) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         let _ = Tag::parse.parse_next(&mut input);
 
-        assert_eq!(input, expected);
+        assert_eq!(**input, expected);
     }
 
     #[rstest]
     #[case("div")]
     #[case("my-div")]
     fn test_parse_tag_name(#[case] input: &str) {
-        let actual = parse_tag_name.parse(input).unwrap();
+        let actual = parse_tag_name.parse(new_input(input)).unwrap();
         assert_eq!(actual, input);
     }
+
+    #[rstest]
+    #[case("!doctype")]
+    #[case("1div")]
+    #[case(" div")]
+    fn test_parse_tag_name_fails(#[case] input: &str) {
+        assert!(parse_tag_name.parse(new_input(input)).is_err());
+    }
 }
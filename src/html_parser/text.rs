@@ -1,21 +1,258 @@
-use winnow::{token::take_till, PResult, Parser};
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, WhitespaceSensitivity};
+use crate::span::Input;
+use winnow::{
+    error::{ErrMode, ErrorKind, ParserError},
+    stream::Stream,
+    PResult,
+};
 
-/// Parse the text between tags
-pub fn parse_text<'i>(input: &mut &'i str) -> PResult<&'i str> {
-    take_till(0.., '<').parse_next(input)
+/// Parse the text between tags. Text can start with a `<` or `{` that doesn't begin a valid tag,
+/// but never with a closing tag, as that belongs to the parent element.
+pub fn parse_text<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    if input.starts_with("</") {
+        return Err(ErrMode::from_error_kind(input, ErrorKind::Verify));
+    }
+
+    // Anything at the very start has already failed to parse as a tag
+    let end = input
+        .char_indices()
+        .find(|&(i, c)| i > 0 && (c == '<' || is_template_start(&input[i..])))
+        .map_or(input.len(), |(i, _)| i);
+
+    Ok(input.next_slice(end))
+}
+
+fn is_template_start(input: &str) -> bool {
+    input.starts_with("{{") || input.starts_with("{%") || input.starts_with("{#")
+}
+
+/// Formats text at the indent, trimming the blank lines around it and keeping at most
+/// `max_blank_lines` in a row within it. Lines after the first keep their indentation relative to
+/// each other.
+pub fn format_text(text: &str, indent: &str, max_blank_lines: Option<usize>) -> String {
+    let lines = text.split('\n').collect::<Vec<_>>();
+    let Some(start) = lines.iter().position(|line| !line.trim().is_empty()) else {
+        return String::new();
+    };
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .unwrap_or(start);
+
+    // The first line continues on from whatever came before the text, so its indentation is
+    // meaningless
+    let dedent = (start..=end)
+        .filter(|&i| i != 0 && !lines[i].trim().is_empty())
+        .map(|i| indent_width(lines[i]))
+        .min()
+        .unwrap_or(0);
+
+    let mut blank_lines = 0;
+    (start..=end)
+        .filter(|&i| {
+            if !lines[i].trim().is_empty() {
+                blank_lines = 0;
+                return true;
+            }
+            blank_lines += 1;
+            max_blank_lines.is_none_or(|max| blank_lines <= max)
+        })
+        .map(|i| {
+            let line = lines[i].trim_end();
+            if line.is_empty() {
+                String::new()
+            } else if i == 0 {
+                format!("{indent}{}", line.trim_start())
+            } else {
+                format!("{indent}{}", strip_indent(line, dedent))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Like [`format_text`], but with each line broken by a hard line break so that it's indented
+/// wherever the text is printed. `None` when there's no text left.
+pub fn text_doc<'a>(text: &str, max_blank_lines: Option<usize>) -> Option<Doc<'a>> {
+    let text = format_text(text, "", max_blank_lines);
+    if text.is_empty() {
+        return None;
+    }
+    let lines = text.split('\n').map(|line| Doc::text(line.to_string()));
+    Some(Doc::join(Doc::HardLine, lines))
+}
+
+/// Stands in for the parts of a run of inline nodes that aren't plain text while its text is
+/// formatted.
+const PLACEHOLDER: char = '\0';
+
+/// Like [`text_doc`], but for a run of inline nodes like text, variables and inline elements. The
+/// plain text is formatted like any other text, while the rest, like opening tags that can be
+/// wrapped, is kept where it was within its line.
+pub fn inline_run_doc<'a>(run: Vec<Doc<'a>>, options: &FormatOptions) -> Option<Doc<'a>> {
+    let mut pieces = vec![];
+    for doc in run {
+        flatten(doc, &mut pieces);
+    }
+    // A placeholder in the text itself would be mistaken for one of the others
+    let has_placeholder = pieces
+        .iter()
+        .any(|piece| matches!(piece, Doc::Text(text) if text.contains(PLACEHOLDER)));
+
+    let mut text = String::new();
+    let mut embedded = vec![];
+    for piece in pieces {
+        match piece {
+            Doc::Text(piece) => text.push_str(&piece),
+            piece if has_placeholder => text.push_str(&piece.print_flat(options)),
+            piece => {
+                text.push(PLACEHOLDER);
+                embedded.push(piece);
+            }
+        }
+    }
+
+    let text = format_text(&text, "", options.max_blank_lines);
+    if text.is_empty() {
+        return None;
+    }
+    // Lines can only be wrapped where there's whitespace already if all of it is significant
+    let wraps = options.whitespace_sensitivity != WhitespaceSensitivity::Strict;
+    let text_doc = |text: &str, at_line_start: bool| {
+        if wraps {
+            words_doc(text, at_line_start)
+        } else {
+            Doc::text(text.to_string())
+        }
+    };
+    let mut embedded = embedded.into_iter();
+    let lines = text.split('\n').map(|line| {
+        if has_placeholder {
+            return text_doc(line, true);
+        }
+        let mut docs = vec![];
+        for (i, part) in line.split(PLACEHOLDER).enumerate() {
+            if i > 0 {
+                docs.extend(embedded.next());
+            }
+            docs.push(text_doc(part, i == 0));
+        }
+        Doc::concat(docs)
+    });
+    Some(Doc::join(Doc::HardLine, lines.collect::<Vec<_>>()))
+}
+
+/// The text with a break at each run of whitespace between its words, so that lines that are too
+/// long wrap there. The whitespace is kept as it was where it fits, as is the indentation at the
+/// start of a line.
+fn words_doc<'a>(text: &str, at_line_start: bool) -> Doc<'a> {
+    let mut rest = text;
+    let mut docs = vec![];
+    if at_line_start {
+        let indent = rest.len() - rest.trim_start().len();
+        docs.push(Doc::text(rest[..indent].to_string()));
+        rest = &rest[indent..];
+    }
+    while let Some(start) = rest.find(char::is_whitespace) {
+        let end = rest[start..]
+            .find(|c: char| !c.is_whitespace())
+            .map_or(rest.len(), |len| start + len);
+        let space = Doc::text(rest[start..end].to_string());
+        docs.push(Doc::text(rest[..start].to_string()));
+        docs.push(Doc::group(Doc::if_break(Doc::HardLine, space)));
+        rest = &rest[end..];
+    }
+    docs.push(Doc::text(rest.to_string()));
+    Doc::concat(docs)
+}
+
+/// Splits `doc` into its plain text and everything else, in order.
+fn flatten<'a>(doc: Doc<'a>, pieces: &mut Vec<Doc<'a>>) {
+    match doc {
+        Doc::Nil => {}
+        Doc::Concat(docs) => docs.into_iter().for_each(|doc| flatten(doc, pieces)),
+        doc => pieces.push(doc),
+    }
+}
+
+/// The number of whitespace characters at the start of the line.
+fn indent_width(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
+}
+
+/// Removes up to `width` whitespace characters from the start of `line`.
+fn strip_indent(line: &str, width: usize) -> &str {
+    let offset = line
+        .char_indices()
+        .take(width)
+        .take_while(|(_, c)| c.is_whitespace())
+        .last()
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    &line[offset..]
 }
 
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
     use rstest::rstest;
+    use winnow::Parser;
 
     use super::*;
+    use crate::span::new_input;
 
     #[rstest]
     #[case("hello there", "hello there")]
     fn test_text_parses_successfully(#[case] input: &str, #[case] expected: &str) {
-        let actual = parse_text.parse(input).unwrap();
+        let actual = parse_text.parse(new_input(input)).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[rstest]
+    #[case("hello<div>", "hello", "<div>")]
+    #[case("hello{{ a }}", "hello", "{{ a }}")]
+    #[case("hello{% a %}", "hello", "{% a %}")]
+    #[case("hello{# a #}", "hello", "{# a #}")]
+    #[case("a { b } c<div>", "a { b } c", "<div>")]
+    #[case("<!doctype html><div>", "<!doctype html>", "<div>")]
+    #[case("{{ 42 }}<div>", "{{ 42 }}", "<div>")]
+    #[case("a < b</div>", "a ", "< b</div>")]
+    fn test_text_stops_at_tags(
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] remaining: &str,
+    ) {
+        let mut input = new_input(input);
+        let actual = parse_text.parse_next(&mut input).unwrap();
         assert_eq!(actual, expected);
+        assert_eq!(**input, remaining);
+    }
+
+    #[rstest]
+    fn test_text_doesnt_start_with_closing_tag() {
+        assert!(parse_text.parse(new_input("</div>")).is_err());
+    }
+
+    #[rstest]
+    #[case("", "")]
+    #[case("  \n  ", "")]
+    #[case("  hello  ", "\thello")]
+    #[case("\n    one\n      two\n    three\n", "\tone\n\t  two\n\tthree")]
+    #[case("one\n      two\n    three", "\tone\n\t  two\n\tthree")]
+    #[case("\n  one\n\n\n  two\n", "\tone\n\n\n\ttwo")]
+    fn test_formatting_text(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(format_text(input, "\t", None), expected);
+    }
+
+    #[rstest]
+    #[case(Some(0), "\tone\n\ttwo\n\tthree")]
+    #[case(Some(1), "\tone\n\n\ttwo\n\n\tthree")]
+    #[case(Some(2), "\tone\n\n\ttwo\n\n\n\tthree")]
+    fn test_formatting_text_with_max_blank_lines(
+        #[case] max_blank_lines: Option<usize>,
+        #[case] expected: &str,
+    ) {
+        let input = "one\n\ntwo\n\n\n\n\nthree";
+        assert_eq!(format_text(input, "\t", max_blank_lines), expected);
     }
 }
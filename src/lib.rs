@@ -1,3 +1,9 @@
+pub mod config;
+pub mod diagnostic;
+pub mod discover;
+pub mod doc;
+pub mod error;
 pub mod formatting;
 pub mod html_parser;
+pub mod span;
 pub mod template_parser;
@@ -1,103 +1,379 @@
-use clap::Parser as ClapParser;
-use djfmt::{formatting::Formatable, html_parser::node::Node, template_parser::Template};
+use clap::{Parser as ClapParser, Subcommand};
+use djfmt::{
+    config::{ClassOrderKind, Config, ConfigError, IndentKind},
+    diagnostic::Diagnostic,
+    discover::{Manifest, MANIFEST_FILE},
+    error::Error,
+    formatting::{BracketPlacement, FormatOptions, Formatable, QuoteStyle, VoidStyle},
+    html_parser::attribute::{AttributeOrder, ClassOrder},
+    span::Span,
+    template_parser::{registry::TagRegistry, Template},
+};
 use glob::glob;
 use rayon::prelude::*;
 use std::{
-    fs::OpenOptions,
-    io::{Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-/// Simple program to greet a person
+/// Formats Django templates. Settings are read from the nearest `djfmt.toml`, or `[tool.djfmt]`
+/// table in a `pyproject.toml`, to each file, and the options here override them
 #[derive(ClapParser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// List of files or directories to format
-    #[arg()]
-    path: PathBuf,
+    #[arg(required = true)]
+    path: Option<PathBuf>,
     /// Avoid writing any formatted files back; instead, exit with a non-zero status code if any files would have been modified, and zero otherwise
     #[arg(long)]
     check: bool,
+    /// Reorder HTML attributes to put `id` and `class` first, then `name`, `data-*` and `aria-*`, then everything else alphabetically. Attributes are kept in their original order otherwise
+    #[arg(long)]
+    sort_attributes: bool,
+    /// Remove attributes that are set more than once on the same element, keeping the first one like browsers do. Duplicates are reported but kept otherwise
+    #[arg(long)]
+    dedup_attributes: bool,
+    /// Put the classes in `class` attributes in this order. They're kept in the order they were written otherwise, though repeated classes and extra whitespace are always removed
+    #[arg(long, value_enum)]
+    class_order: Option<ClassOrderKind>,
+    /// Indent with this many spaces instead of a tab
+    #[arg(long)]
+    indent_width: Option<usize>,
+    /// How long lines can get before they're wrapped, where they can be. Defaults to 120
+    #[arg(long)]
+    line_width: Option<usize>,
+    /// Wrap attribute values in single quotes rather than double quotes, unless they contain one
+    #[arg(long)]
+    single_quotes: bool,
+    /// Write void elements like `<br>` without a closing slash, rather than as `<br />`
+    #[arg(long)]
+    bare_void_elements: bool,
+    /// Put the `>` of an opening tag whose attributes are wrapped at the end of the last one,
+    /// rather than on its own line
+    #[arg(long)]
+    bracket_same_line: bool,
+    /// The most blank lines to keep in a row within text. They're all kept otherwise
+    #[arg(long)]
+    max_blank_lines: Option<usize>,
+    /// Don't end formatted files with a newline
+    #[arg(long)]
+    no_final_newline: bool,
+    /// Write out closing tags that HTML lets you leave out, like the `</li>` before another `<li>`
+    #[arg(long)]
+    insert_implied_end_tags: bool,
+    /// Turn `{# comments #}` that would go past the line width into `{% comment %}` blocks
+    #[arg(long)]
+    long_comments_as_blocks: bool,
 }
 
-fn format_file_both(path: &PathBuf, check: bool) -> bool {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(path)
-        .unwrap();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the custom tags and filters registered in the `templatetags` modules of a Django project, and write them to a manifest that the `tag-manifest` config setting can point to
+    DiscoverTags {
+        /// The root of the project
+        #[arg(default_value = ".")]
+        root: PathBuf,
+        /// Where to write the manifest. Defaults to `djfmt-tags.toml` in the root
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Exit code for when files were (or with `--check`, would be) reformatted.
+const EXIT_FORMATTED: i32 = 1;
+/// Exit code for when any part of a file couldn't be parsed, which takes precedence over reformatting.
+const EXIT_ERROR: i32 = 2;
+
+impl Args {
+    /// The settings given on the command line, which take precedence over any config file.
+    fn config(&self) -> Config {
+        Config {
+            indent_style: self.indent_width.map(|_| IndentKind::Space),
+            indent_width: self.indent_width,
+            line_width: self.line_width,
+            quote_style: self.single_quotes.then_some(QuoteStyle::Single),
+            void_style: self.bare_void_elements.then_some(VoidStyle::Bare),
+            bracket_placement: self.bracket_same_line.then_some(BracketPlacement::SameLine),
+            max_blank_lines: self.max_blank_lines,
+            final_newline: self.no_final_newline.then_some(false),
+            insert_implied_end_tags: self.insert_implied_end_tags.then_some(true),
+            long_comments_as_blocks: self.long_comments_as_blocks.then_some(true),
+            sort_attributes: self.sort_attributes.then_some(true),
+            dedup_attributes: self.dedup_attributes.then_some(true),
+            class_order: self.class_order,
+            ..Config::default()
+        }
+    }
+}
+
+/// How each file should be formatted.
+struct Settings {
+    check: bool,
+    attribute_order: AttributeOrder,
+    dedup_attributes: bool,
+    class_order: ClassOrder,
+    report_duplicate_attributes: bool,
+    /// The filters from the tag manifest, if there is one, to report any others that are used
+    custom_filters: Option<Vec<String>>,
+    format_options: FormatOptions,
+    tags: TagRegistry,
+}
+
+impl Settings {
+    fn new(check: bool, config: &Config) -> Self {
+        Self {
+            check,
+            attribute_order: if config.sort_attributes.unwrap_or(false) {
+                AttributeOrder::conventional()
+            } else {
+                AttributeOrder::Source
+            },
+            dedup_attributes: config.dedup_attributes.unwrap_or(false),
+            class_order: config.class_order(),
+            report_duplicate_attributes: config.rule_enabled("duplicate-attribute"),
+            custom_filters: config
+                .manifest
+                .as_ref()
+                .filter(|_| config.rule_enabled("unknown-filter"))
+                .map(|manifest| manifest.filters.clone()),
+            format_options: config.format_options(),
+            tags: config.tags(),
+        }
+    }
+}
+
+/// The config for the files in a directory, along with the settings made from it.
+type DirSettings = (Config, Arc<Settings>);
+
+/// Finds the settings for a file. They're made once for each directory, and shared between every
+/// directory with the same config, so that things like the tag registry aren't built for each file.
+fn settings_for<'d>(
+    path: &Path,
+    args: &Args,
+    dirs: &'d mut HashMap<PathBuf, DirSettings>,
+) -> Result<&'d DirSettings, ConfigError> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    if !dirs.contains_key(&dir) {
+        let config = args.config().or(Config::find(&dir)?);
+        let settings = match dirs.values().find(|(other, _)| *other == config) {
+            Some((_, settings)) => Arc::clone(settings),
+            None => Arc::new(Settings::new(args.check, &config)),
+        };
+        dirs.insert(dir.clone(), (config, settings));
+    }
+    Ok(&dirs[&dir])
+}
 
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
+/// What happened to a file that could be formatted.
+struct Outcome {
+    /// Whether the file was (or would be) changed.
+    modified: bool,
+    /// The parts of the file that couldn't be parsed, which are left as they were.
+    errors: Vec<Error>,
+}
 
-    let original_contents = contents.clone();
+/// Why a file was left as it was.
+enum Skipped {
+    /// The file couldn't be parsed at all
+    Error(Box<Error>),
+    /// The formatted output doesn't parse back to the same template
+    NotEquivalent(Diagnostic, String),
+    /// The file couldn't be read, or written back, like when it isn't UTF-8
+    Io(PathBuf, io::Error),
+}
 
-    let parsed_template = Template::parse(&mut contents.as_str()).unwrap();
-    let formatted_template = parsed_template.formatted(0);
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skipped::Error(error) => error.fmt(f),
+            Skipped::NotEquivalent(diagnostic, location) => {
+                write!(f, "{location}: error: {diagnostic}")
+            }
+            Skipped::Io(path, error) => write!(f, "{}: error: {error}", path.to_string_lossy()),
+        }
+    }
+}
 
-    let parsed_html = Node::parse(&mut formatted_template.as_str()).unwrap();
-    let formatted_html = parsed_html.formatted(0);
+/// Formats a file, returning whether it was changed along with any parts that couldn't be parsed.
+fn format_file_both(path: &PathBuf, settings: &Settings) -> Result<Outcome, Skipped> {
+    let contents = fs::read_to_string(path).map_err(|error| Skipped::Io(path.clone(), error))?;
 
-    let formattable = original_contents != formatted_html;
-    if check {
-        return formattable;
+    let mut parsed = Template::parse_source_with_tags(&contents, &settings.tags)
+        .map_err(|error| Skipped::Error(Box::new(error.with_path(path.clone()))))?;
+    let errors = parsed
+        .errors(&contents)
+        .into_iter()
+        .map(|error| error.with_path(path.clone()))
+        .collect();
+    let mut warnings = if settings.report_duplicate_attributes {
+        parsed.duplicate_attributes()
+    } else {
+        vec![]
+    };
+    if let Some(custom_filters) = &settings.custom_filters {
+        warnings.extend(parsed.unknown_filters(custom_filters));
     }
+    for diagnostic in warnings {
+        let (line, col) = diagnostic.span.line_col(&contents);
+        eprintln!(
+            "{}:{line}:{col}: warning: {diagnostic}",
+            path.to_string_lossy()
+        );
+    }
+    if settings.dedup_attributes {
+        parsed.dedup_attributes();
+    }
+    parsed.sort_attributes(&settings.attribute_order);
+    parsed.normalize_classes(&settings.class_order);
+    let formatted = parsed.formatted(0, &settings.format_options);
 
-    file.set_len(0).unwrap();
-    file.seek(SeekFrom::Start(0)).unwrap();
+    // Like black, never write output that doesn't mean the same as the input
+    let equivalent = match Template::parse_source_with_tags(&formatted, &settings.tags) {
+        Ok(reparsed) => parsed.check_equivalent(&reparsed, &settings.format_options),
+        Err(_) => Err(Diagnostic {
+            span: Span::default(),
+            message:
+                "formatting would produce a template that can't be parsed, so it was left as is"
+                    .to_string(),
+        }),
+    };
+    if let Err(diagnostic) = equivalent {
+        let (line, col) = diagnostic.span.line_col(&contents);
+        let location = format!("{}:{line}:{col}", path.to_string_lossy());
+        return Err(Skipped::NotEquivalent(diagnostic, location));
+    }
 
-    file.write_all(formatted_html.as_bytes()).unwrap();
-    file.flush().unwrap();
+    let outcome = Outcome {
+        modified: contents != formatted,
+        errors,
+    };
+    // Files that are already formatted aren't touched, so they can be read-only
+    if outcome.modified && !settings.check {
+        fs::write(path, formatted).map_err(|error| Skipped::Io(path.clone(), error))?;
+    }
+    Ok(outcome)
+}
 
-    formattable
+/// Writes the tags and filters found under `root` to a manifest.
+fn discover_tags(root: &Path, output: Option<&Path>) {
+    let manifest = match Manifest::scan(root) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            eprintln!("error: couldn't scan {}: {error}", root.to_string_lossy());
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    let output = output.map_or_else(|| root.join(MANIFEST_FILE), Path::to_path_buf);
+    let contents = format!(
+        "# Written by `djfmt discover-tags`, so any changes will be lost when it's run again\n\n{}",
+        toml::to_string(&manifest).unwrap()
+    );
+    if let Err(error) = fs::write(&output, contents) {
+        eprintln!(
+            "error: couldn't write {}: {error}",
+            output.to_string_lossy()
+        );
+        std::process::exit(EXIT_ERROR);
+    }
+    println!(
+        "{}: found {} tags and {} filters",
+        output.to_string_lossy(),
+        manifest.tags.len(),
+        manifest.filters.len()
+    );
 }
 
 fn main() {
     let args = Args::parse();
+    if let Some(Command::DiscoverTags { root, output }) = &args.command {
+        discover_tags(root, output.as_deref());
+        return;
+    }
+    // Clap makes sure there's a path when there's no subcommand
+    let target = args.path.as_ref().unwrap();
 
-    if !args.path.exists() {
+    if !target.exists() {
         println!("File does not exist");
         std::process::exit(1);
     }
 
-    if args.path.is_file() {
-        let modified = format_file_both(&args.path, args.check);
-        if modified {
-            println!("{}: formatted", args.path.to_string_lossy());
-            std::process::exit(1);
-        }
-        return;
-    }
+    // Files given directly are always formatted, while the config decides which files in a
+    // directory are
+    let mut any_errors = false;
+    let candidates = if target.is_file() {
+        vec![target.to_path_buf()]
+    } else {
+        let glob_path = format!("{}/**/*", target.to_string_lossy());
+        glob(&glob_path)
+            .unwrap()
+            .filter_map(|entry| match entry {
+                Ok(entry) => entry.is_file().then_some(entry),
+                // Like a directory that can't be read, which leaves the rest to format
+                Err(error) => {
+                    eprintln!(
+                        "{}: error: {}\n",
+                        error.path().to_string_lossy(),
+                        error.error()
+                    );
+                    any_errors = true;
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+    };
 
-    let glob_path = format!("{}/**/*.html", args.path.to_string_lossy());
-    let files = glob(&glob_path)
-        .unwrap()
-        .filter_map(|entry| {
-            let entry = entry.unwrap();
-            if entry.is_file() {
-                Some(entry)
-            } else {
-                None
+    let mut dirs = HashMap::new();
+    let mut files = vec![];
+    for path in candidates {
+        let (config, settings) = match settings_for(&path, &args, &mut dirs) {
+            Ok(dir_settings) => dir_settings,
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(EXIT_ERROR);
             }
-        })
-        .collect::<Vec<_>>();
+        };
+        let absolute = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if target.is_file() || config.includes(&absolute) {
+            files.push((path, Arc::clone(settings)));
+        }
+    }
 
-    let results: Vec<(&PathBuf, bool)> = files
+    // Every file is formatted even if parts of it, or other files, fail to parse
+    let results: Vec<(&PathBuf, Result<Outcome, Skipped>)> = files
         .par_iter()
-        .map(|path| (path, format_file_both(path, args.check)))
+        .map(|(path, settings)| (path, format_file_both(path, settings)))
         .collect();
 
-    let modified_paths = results
-        .iter()
-        .filter(|result| result.1)
-        .map(|result| {
-            println!("{}: formatted", result.0.to_string_lossy());
-            result.0
-        })
-        .collect::<Vec<_>>();
+    let mut any_modified = false;
+    for (path, result) in &results {
+        match result {
+            Ok(outcome) => {
+                for error in &outcome.errors {
+                    eprintln!("{error}\n");
+                    any_errors = true;
+                }
+                if outcome.modified {
+                    println!("{}: formatted", path.to_string_lossy());
+                    any_modified = true;
+                }
+            }
+            Err(error) => {
+                eprintln!("{error}\n");
+                any_errors = true;
+            }
+        }
+    }
 
-    let any_modified = !modified_paths.is_empty();
+    if any_errors {
+        std::process::exit(EXIT_ERROR);
+    }
     if any_modified {
-        std::process::exit(1);
+        std::process::exit(EXIT_FORMATTED);
     }
 }
@@ -0,0 +1,163 @@
+use crate::template_parser::registry::TagRegistry;
+use std::ops::Range;
+use winnow::stream::{Located, Stateful};
+
+/// The input to every parser, which keeps track of how far into the source it is so that parsed
+/// nodes can record their [`Span`], along with the block tags that templates can use.
+pub type Input<'i> = Stateful<Located<&'i str>, &'i TagRegistry>;
+
+/// Creates the input for parsing `source` with Django's built-in tags.
+pub fn new_input(source: &str) -> Input<'_> {
+    new_input_with_tags(source, TagRegistry::builtin())
+}
+
+/// Creates the input for parsing `source` with the tags in `tags`.
+pub fn new_input_with_tags<'i>(source: &'i str, tags: &'i TagRegistry) -> Input<'i> {
+    Stateful {
+        input: Located::new(source),
+        state: tags,
+    }
+}
+
+/// A byte range in the source that something was parsed from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The 1-based line and column of the start of the span.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.start)
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// Equality that ignores where things were parsed from, so that a tree can be compared with one
+/// parsed from differently formatted source.
+pub trait Equivalent {
+    fn is_equivalent(&self, other: &Self) -> bool;
+}
+
+impl<T: Equivalent> Equivalent for [T] {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|(item, other_item)| item.is_equivalent(other_item))
+    }
+}
+
+impl<T: Equivalent> Equivalent for Vec<T> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.as_slice().is_equivalent(other)
+    }
+}
+
+impl<T: Equivalent> Equivalent for Option<T> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(value), Some(other_value)) => value.is_equivalent(other_value),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Equivalent + ?Sized> Equivalent for Box<T> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        (**self).is_equivalent(other)
+    }
+}
+
+/// Asserts that `actual` is equivalent to `expected`, showing how they differ if it isn't.
+#[cfg(test)]
+#[track_caller]
+pub fn assert_equivalent<T: Equivalent + PartialEq + std::fmt::Debug>(actual: &T, expected: &T) {
+    if !actual.is_equivalent(expected) {
+        pretty_assertions::assert_eq!(actual, expected);
+    }
+}
+
+/// A value along with where it was parsed from, for nodes that are nothing more than a value.
+#[derive(Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T: PartialEq> Equivalent for Spanned<T> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(value: T) -> Self {
+        Self {
+            value,
+            span: Span::default(),
+        }
+    }
+}
+
+/// The 1-based line and column of a byte offset into `source`. Columns count characters rather
+/// than bytes.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("abc", 0, (1, 1))]
+    #[case("abc", 2, (1, 3))]
+    #[case("a\nbc", 2, (2, 1))]
+    #[case("a\nbc\nd", 4, (2, 3))]
+    #[case("é\né", 5, (2, 2))]
+    fn test_line_col(
+        #[case] source: &str,
+        #[case] offset: usize,
+        #[case] expected: (usize, usize),
+    ) {
+        assert_eq!(line_col(source, offset), expected)
+    }
+
+    #[rstest]
+    fn test_spans_only_affect_equality() {
+        let value = Spanned {
+            value: "a",
+            span: (0..1).into(),
+        };
+        let moved = Spanned {
+            value: "a",
+            span: (5..6).into(),
+        };
+        assert_ne!(value, moved);
+        assert!(value.is_equivalent(&moved));
+        assert!(!value.is_equivalent(&Spanned::from("b")));
+    }
+}
@@ -1,25 +1,45 @@
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input, Span};
 
 use super::{
     filter::{parse_filter_chain, Filter},
     text::SingleLineTextString,
     variable::parse_variable,
 };
-use winnow::{PResult, Parser};
+use winnow::{
+    ascii::{multispace0, multispace1},
+    combinator::{alt, delimited, not, opt, separated, terminated},
+    stream::Location,
+    token::{one_of, take_while},
+    PResult, Parser,
+};
 
+/// The part of a filter expression that filters are applied to, or a filter's argument.
 #[derive(Debug, PartialEq)]
 pub enum TagArgumentValue<'i> {
     Text(SingleLineTextString<'i>),
     Variable(&'i str),
+    /// A number like `42`, `-2` or `1.5`, kept as it was written
+    Number(&'i str),
+    /// A string marked for translation, like `_("Hello")`
+    Translated(SingleLineTextString<'i>),
+    /// The variables that a `{% for %}` loop unpacks each item into, like `k, v`
+    Unpacked(Vec<&'i str>),
 }
 
 impl<'i> TagArgumentValue<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
         let starts_with_quote = input.starts_with('\'') || input.starts_with('"');
 
         let value = if starts_with_quote {
             let thing = SingleLineTextString::parse.parse_next(input)?;
             TagArgumentValue::Text(thing)
+        } else if input.starts_with("_(") {
+            let text = delimited("_(", SingleLineTextString::parse, ')').parse_next(input)?;
+            TagArgumentValue::Translated(text)
+        } else if let Some(number) = opt(parse_number).parse_next(input)? {
+            TagArgumentValue::Number(number)
         } else {
             let variable = parse_variable.parse_next(input)?;
             TagArgumentValue::Variable(variable)
@@ -29,48 +49,145 @@ impl<'i> TagArgumentValue<'i> {
     }
 }
 
+/// Parses a number the way Django does, which allows a sign or a leading dot, then a digit, then
+/// any digits, dots and exponents.
+fn parse_number<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    (
+        opt(one_of(['-', '+', '.'])),
+        one_of(|c: char| c.is_ascii_digit()),
+        take_while(0.., |c: char| c.is_ascii_digit() || c == '.' || c == 'e'),
+    )
+        .take()
+        .parse_next(input)
+}
+
 impl<'i> Formatable for TagArgumentValue<'i> {
-    fn formatted(&self, indent_level: usize) -> String {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
         match self {
-            TagArgumentValue::Text(text) => text.formatted(indent_level),
-            TagArgumentValue::Variable(variable) => variable.to_string(),
+            TagArgumentValue::Text(text) => text.to_doc(options),
+            TagArgumentValue::Variable(variable) => Doc::text(*variable),
+            TagArgumentValue::Number(number) => Doc::text(*number),
+            TagArgumentValue::Translated(text) => {
+                Doc::concat([Doc::text("_("), text.to_doc(options), Doc::text(")")])
+            }
+            TagArgumentValue::Unpacked(names) => Doc::text(names.join(", ")),
         }
     }
 }
 
+/// What a tag argument is for, which decides how it's written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgumentKind<'i> {
+    /// Just a value, like `"detail"` in `{% url "detail" %}`
+    Positional,
+    /// A named value, like `pk=obj.pk`
+    Keyword(&'i str),
+    /// The variable that a tag stores its result in, like `as link`
+    Target,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct TagArgument<'i> {
+    pub kind: ArgumentKind<'i>,
     pub value: TagArgumentValue<'i>,
     pub filters: Vec<Filter<'i>>,
+    pub span: Span,
+}
+
+impl Equivalent for TagArgument<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.value == other.value
+            && self.filters.is_equivalent(&other.filters)
+    }
 }
 
 impl<'i> TagArgument<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let value = TagArgumentValue::parse.parse_next(input)?;
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        alt((
+            Self::parse_target,
+            Self::parse_keyword,
+            Self::parse_positional,
+        ))
+        .parse_next(input)
+    }
+
+    /// Parses a value along with its filters, like `items|length`.
+    pub fn parse_positional(input: &mut Input<'i>) -> PResult<Self> {
+        let ((value, filters), span) = (TagArgumentValue::parse, parse_filter_chain)
+            .with_span()
+            .parse_next(input)?;
+
+        let argument = Self {
+            kind: ArgumentKind::Positional,
+            value,
+            filters,
+            span: span.into(),
+        };
 
-        let filters = parse_filter_chain.parse_next(input)?;
+        Ok(argument)
+    }
+
+    /// Parses the comma-separated variables that a loop unpacks each item into, like `k, v` or
+    /// `k,v` in `{% for k, v in d.items %}`.
+    pub fn parse_unpacked(input: &mut Input<'i>) -> PResult<Self> {
+        let (names, span) = separated(2.., parse_variable, (multispace0, ',', multispace0))
+            .with_span()
+            .parse_next(input)?;
+
+        Ok(Self {
+            kind: ArgumentKind::Positional,
+            value: TagArgumentValue::Unpacked(names),
+            filters: vec![],
+            span: span.into(),
+        })
+    }
+
+    /// Parses `key=value`, allowing spaces around the `=` even though Django doesn't.
+    fn parse_keyword(input: &mut Input<'i>) -> PResult<Self> {
+        let start = input.location();
+        let key = terminated(
+            take_while(1.., |c: char| c.is_alphanumeric() || c == '_'),
+            (multispace0, '=', not('='), multispace0),
+        )
+        .parse_next(input)?;
 
-        let argument = Self { value, filters };
+        let argument = Self::parse_positional(input)?;
+        Ok(Self {
+            kind: ArgumentKind::Keyword(key),
+            span: (start..argument.span.end).into(),
+            ..argument
+        })
+    }
+
+    /// Parses `as name`.
+    fn parse_target(input: &mut Input<'i>) -> PResult<Self> {
+        let ((_, name), span) = (("as", multispace1), parse_variable)
+            .with_span()
+            .parse_next(input)?;
+
+        let argument = Self {
+            kind: ArgumentKind::Target,
+            value: TagArgumentValue::Variable(name),
+            filters: vec![],
+            span: span.into(),
+        };
 
         Ok(argument)
     }
 }
 
 impl<'i> Formatable for TagArgument<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        let value = self.value.formatted(0);
-        let filters = self
-            .filters
-            .iter()
-            .map(|f| f.formatted(0))
-            .collect::<Vec<String>>()
-            .join(" | ");
-
-        if filters.is_empty() {
-            value
-        } else {
-            format!("{} | {}", value, filters)
-        }
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let mut docs = vec![match self.kind {
+            ArgumentKind::Positional => Doc::Nil,
+            ArgumentKind::Keyword(key) => Doc::text(format!("{key}=")),
+            ArgumentKind::Target => Doc::text("as "),
+        }];
+        // Django splits tag arguments on spaces, so the filters are kept right up against them
+        docs.push(self.value.to_doc(options));
+        docs.extend(self.filters.iter().map(|filter| filter.to_doc(options)));
+        Doc::concat(docs)
     }
 }
 
@@ -80,27 +197,35 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case("argument", TagArgument {
+        kind: ArgumentKind::Positional,
         value: TagArgumentValue::Variable("argument"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case("'argument'", TagArgument {
+        kind: ArgumentKind::Positional,
         value: TagArgumentValue::Text(SingleLineTextString {
             value: "argument",
             startquote_char: '\'',
         }),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case("\"argument\"", TagArgument {
+        kind: ArgumentKind::Positional,
         value: TagArgumentValue::Text(SingleLineTextString {
             value: "argument",
             startquote_char: '"',
         }),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case("\"argument\"|my_filter", TagArgument {
+        kind: ArgumentKind::Positional,
         value: TagArgumentValue::Text(SingleLineTextString {
             value: "argument",
             startquote_char: '"',
@@ -108,9 +233,12 @@ mod tests {
         filters: vec![Filter {
             filter_type: "my_filter",
             argument: None,
+            span: Span::default(),
         }],
+        span: Span::default(),
     })]
     #[case("\"argument\"|my_filter:\"arg\"", TagArgument {
+        kind: ArgumentKind::Positional,
         value: TagArgumentValue::Text(SingleLineTextString {
             value: "argument",
             startquote_char: '"',
@@ -121,17 +249,84 @@ mod tests {
                 value: "arg",
                 startquote_char: '"',
             })),
+            span: Span::default(),
         }],
+        span: Span::default(),
+    })]
+    #[case("-2", TagArgument {
+        kind: ArgumentKind::Positional,
+        value: TagArgumentValue::Number("-2"),
+        filters: vec![],
+        span: Span::default(),
+    })]
+    #[case("1.5e3|add:.5", TagArgument {
+        kind: ArgumentKind::Positional,
+        value: TagArgumentValue::Number("1.5e3"),
+        filters: vec![Filter {
+            filter_type: "add",
+            argument: Some(TagArgumentValue::Number(".5")),
+            span: Span::default(),
+        }],
+        span: Span::default(),
+    })]
+    #[case("_(\"Hello\")|upper", TagArgument {
+        kind: ArgumentKind::Positional,
+        value: TagArgumentValue::Translated(SingleLineTextString {
+            value: "Hello",
+            startquote_char: '"',
+        }),
+        filters: vec![Filter {
+            filter_type: "upper",
+            argument: None,
+            span: Span::default(),
+        }],
+        span: Span::default(),
+    })]
+    #[case("_private", TagArgument {
+        kind: ArgumentKind::Positional,
+        value: TagArgumentValue::Variable("_private"),
+        filters: vec![],
+        span: Span::default(),
+    })]
+    #[case("total=items|length", TagArgument {
+        kind: ArgumentKind::Keyword("total"),
+        value: TagArgumentValue::Variable("items"),
+        filters: vec![Filter {
+            filter_type: "length",
+            argument: None,
+            span: Span::default(),
+        }],
+        span: Span::default(),
+    })]
+    #[case("pk = obj.pk", TagArgument {
+        kind: ArgumentKind::Keyword("pk"),
+        value: TagArgumentValue::Variable("obj.pk"),
+        filters: vec![],
+        span: Span::default(),
+    })]
+    #[case("as link", TagArgument {
+        kind: ArgumentKind::Target,
+        value: TagArgumentValue::Variable("link"),
+        filters: vec![],
+        span: Span::default(),
+    })]
+    #[case("assets", TagArgument {
+        kind: ArgumentKind::Positional,
+        value: TagArgumentValue::Variable("assets"),
+        filters: vec![],
+        span: Span::default(),
     })]
     fn test_parsing_filter_chain(#[case] input: &str, #[case] expected: TagArgument) {
-        let actual = TagArgument::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = TagArgument::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
     }
 
     #[rstest]
     #[case(TagArgumentValue::Variable("my_var"), "my_var")]
+    #[case(TagArgumentValue::Number("-0.5"), "-0.5")]
+    #[case(TagArgumentValue::Translated(SingleLineTextString { value: "it\\'s", startquote_char: '\'' }), "_('it\\'s')")]
     fn test_formatting_tag_argument_value(#[case] input: TagArgumentValue, #[case] expected: &str) {
-        let actual = input.formatted(0);
+        let actual = input.formatted(0, &FormatOptions::default());
         assert_eq!(actual, expected)
     }
 }
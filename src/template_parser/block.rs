@@ -0,0 +1,311 @@
+use super::{registry::BlockDefinition, tag::Tag};
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::html_parser::node::{child_nodes_doc, is_blank, Node};
+use crate::span::{Equivalent, Input, Span, Spanned};
+use winnow::{
+    error::{AddContext, ContextError, ErrMode, StrContext, StrContextValue},
+    stream::{Location, Stream},
+    PResult, Parser,
+};
+
+/// A section of a block that starts at an intermediate tag, like `{% else %}…`.
+#[derive(Debug, PartialEq)]
+pub struct Branch<'i, N> {
+    pub tag: Tag<'i>,
+    pub children: Vec<N>,
+}
+
+/// A block tag along with its end tag and everything in between, e.g.
+/// `{% if a %}…{% elif b %}…{% else %}…{% endif %}`.
+#[derive(Debug, PartialEq)]
+pub struct Block<'i, N> {
+    pub opening_tag: Tag<'i>,
+    pub children: Vec<N>,
+    pub branches: Vec<Branch<'i, N>>,
+    pub closing_tag: Tag<'i>,
+    /// Whether the children are the body exactly as it was written, like in `{% verbatim %}`
+    pub raw: bool,
+}
+
+impl<N: Equivalent> Equivalent for Branch<'_, N> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.tag.is_equivalent(&other.tag) && self.children.is_equivalent(&other.children)
+    }
+}
+
+impl<N: Equivalent> Equivalent for Block<'_, N> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.opening_tag.is_equivalent(&other.opening_tag)
+            && self.children.is_equivalent(&other.children)
+            && self.branches.is_equivalent(&other.branches)
+            && self.closing_tag.is_equivalent(&other.closing_tag)
+            && self.raw == other.raw
+    }
+}
+
+impl<'i, N: From<Spanned<&'i str>>> Block<'i, N> {
+    /// Parses the rest of a block after its opening tag has been consumed. `parse_children` is
+    /// used for the nodes between each tag, and must stop at any boundary tag. The body of a raw
+    /// block is taken as text instead.
+    pub fn parse_rest<P>(
+        opening_tag: Tag<'i>,
+        definition: &BlockDefinition,
+        mut parse_children: P,
+        input: &mut Input<'i>,
+    ) -> PResult<Self>
+    where
+        P: Parser<Input<'i>, Vec<N>, ContextError>,
+    {
+        if definition.raw {
+            let (children, closing_tag) = parse_raw_body(definition, input)?;
+            return Ok(Self {
+                opening_tag,
+                children,
+                branches: vec![],
+                closing_tag,
+                raw: true,
+            });
+        }
+
+        let children = parse_children.parse_next(input)?;
+        let mut branches: Vec<Branch<'i, N>> = vec![];
+
+        loop {
+            let start = input.checkpoint();
+            let Ok(tag) = Tag::parse.parse_next(input) else {
+                input.reset(&start);
+                return Err(block_error(input, &start, definition));
+            };
+
+            if tag.tag_type == definition.end_tag {
+                return Ok(Self {
+                    opening_tag,
+                    children,
+                    branches,
+                    closing_tag: tag,
+                    raw: false,
+                });
+            }
+
+            if !definition.intermediate_tags.contains(&tag.tag_type) {
+                input.reset(&start);
+                return Err(block_error(input, &start, definition));
+            }
+
+            let children = parse_children.parse_next(input)?;
+            branches.push(Branch { tag, children });
+        }
+    }
+}
+
+impl<'i, N> Block<'i, N> {
+    /// From the start of the opening tag to the end of the closing tag.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.opening_tag.span.start,
+            end: self.closing_tag.span.end,
+        }
+    }
+}
+
+/// Takes everything up to the block's end tag as text, without parsing any of it.
+fn parse_raw_body<'i, N: From<Spanned<&'i str>>>(
+    definition: &BlockDefinition,
+    input: &mut Input<'i>,
+) -> PResult<(Vec<N>, Tag<'i>)> {
+    let mut length = 0;
+    loop {
+        let Some(offset) = input[length..].find("{%") else {
+            input.next_slice(input.len());
+            let end = input.checkpoint();
+            return Err(block_error(input, &end, definition));
+        };
+        length += offset;
+
+        let mut rest = *input;
+        rest.next_slice(length);
+        if let Ok(tag) = Tag::parse.parse_next(&mut rest) {
+            if tag.tag_type == definition.end_tag {
+                let start = input.location();
+                let value = input.next_slice(length);
+                let children = if value.is_empty() {
+                    vec![]
+                } else {
+                    vec![N::from(Spanned {
+                        value,
+                        span: (start..input.location()).into(),
+                    })]
+                };
+                *input = rest;
+                return Ok((children, tag));
+            }
+        }
+        length += "{%".len();
+    }
+}
+
+/// The error for a block that's unclosed or closed by the wrong tag.
+fn block_error<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+    definition: &BlockDefinition,
+) -> ErrMode<ContextError> {
+    let mut error =
+        ContextError::new().add_context(input, start, StrContext::Label(definition.name));
+    for expected in definition
+        .intermediate_tags
+        .iter()
+        .chain([&definition.end_tag])
+    {
+        error = error.add_context(
+            input,
+            start,
+            StrContext::Expected(StrContextValue::StringLiteral(expected)),
+        );
+    }
+    ErrMode::Cut(error)
+}
+
+/// The error for a boundary tag like `{% endif %}` with no block open.
+pub fn unexpected_boundary_error<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+) -> ErrMode<ContextError> {
+    ErrMode::Cut(
+        ContextError::new()
+            .add_context(input, start, StrContext::Label("block"))
+            .add_context(
+                input,
+                start,
+                StrContext::Expected(StrContextValue::Description("a matching opening tag")),
+            ),
+    )
+}
+
+impl<'i> Formatable for Block<'i, Node<'i>> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        // The body of a raw block is kept exactly as it was, as reindenting it could change it
+        if self.raw {
+            let mut docs = vec![self.opening_tag.to_doc(options)];
+            for child in &self.children {
+                if let Node::Text(text) = child {
+                    docs.push(Doc::text(text.value));
+                }
+            }
+            docs.push(self.closing_tag.to_doc(options));
+            return Doc::concat(docs);
+        }
+
+        // Keep empty blocks like `{% block extra_head %}{% endblock %}` on a single line
+        if self.branches.is_empty() && is_blank(&self.children) {
+            return Doc::concat([
+                self.opening_tag.to_doc(options),
+                self.closing_tag.to_doc(options),
+            ]);
+        }
+
+        let sections = [(&self.opening_tag, &self.children)].into_iter().chain(
+            self.branches
+                .iter()
+                .map(|branch| (&branch.tag, &branch.children)),
+        );
+        let mut docs = vec![];
+        for (tag, children) in sections {
+            docs.push(tag.to_doc(options));
+            if !is_blank(children) {
+                docs.push(Doc::indent(Doc::concat([
+                    Doc::HardLine,
+                    child_nodes_doc(children, false, options),
+                ])));
+            }
+            docs.push(Doc::HardLine);
+        }
+        docs.push(self.closing_tag.to_doc(options));
+        Doc::concat(docs)
+    }
+}
+
+impl<'i> Block<'i, Node<'i>> {
+    /// The block as part of the text around it, with its tags and body flowing along with the
+    /// text, if everything within it can.
+    pub fn inline_doc<'a>(
+        &'a self,
+        indent_level: usize,
+        options: &'a FormatOptions,
+    ) -> Option<Doc<'a>> {
+        if self.raw {
+            return Some(Doc::group(self.to_doc(options)));
+        }
+        let sections = [(&self.opening_tag, &self.children)].into_iter().chain(
+            self.branches
+                .iter()
+                .map(|branch| (&branch.tag, &branch.children)),
+        );
+        let mut docs = vec![];
+        for (tag, children) in sections {
+            docs.push(Doc::group(tag.to_doc(options)));
+            for child in children {
+                docs.push(child.inline_doc(indent_level, options)?);
+            }
+        }
+        docs.push(Doc::group(self.closing_tag.to_doc(options)));
+        Some(Doc::concat(docs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::span::new_input;
+    use crate::template_parser::Template;
+
+    #[rstest]
+    #[case::empty(
+        "{% block body %}\n{% endblock body %}",
+        "{% block body %}{% endblock body %}\n"
+    )]
+    #[case::text(
+        "{%block title%}\n        Notify App\n    {%endblock title%}",
+        "{% block title %}\n\tNotify App\n{% endblock title %}\n"
+    )]
+    #[case::inline_text(
+        "{% if a %}yes{% else %}no{% endif %}",
+        "{% if a %}\n\tyes\n{% else %}\n\tno\n{% endif %}\n"
+    )]
+    #[case::branches(
+        "{% if a %}\none\n{% elif b %}\n  two\n{% else %}\n    three\n{% endif %}",
+        "{% if a %}\n\tone\n{% elif b %}\n\ttwo\n{% else %}\n\tthree\n{% endif %}\n"
+    )]
+    #[case::elements(
+        "{% if a %}\n    <div>\n        {{ b }}\n    </div>\n{% endif %}",
+        "{% if a %}\n\t<div>\n\t\t{{ b }}\n\t</div>\n{% endif %}\n"
+    )]
+    #[case::nested(
+        "{% for a in b %}{% if a %}{{ a }}{% endif %}{% endfor %}",
+        "{% for a in b %}\n\t{% if a %}\n\t\t{{ a }}\n\t{% endif %}\n{% endfor %}\n"
+    )]
+    #[case::unpacking(
+        "{% for k,v in d.items %}{{ k }}{% endfor %}{% for a , b in c %}{% endfor %}",
+        "{% for k, v in d.items %}\n\t{{ k }}\n{% endfor %}\n{% for a, b in c %}{% endfor %}\n"
+    )]
+    #[case::raw(
+        "<div>\n{%verbatim%}\n  {{ a }}{% if %}\n{%endverbatim%}</div>",
+        "<div>\n\t{% verbatim %}\n  {{ a }}{% if %}\n{% endverbatim %}\n</div>\n"
+    )]
+    #[case::translation(
+        "<p>{% blocktrans count n=a|length %}\n  One {{ n }}  item\n{% plural %}{{ n }} items{% endblocktrans %}</p>",
+        "<p>\n\t{% blocktrans count n=a|length %}\n  One {{ n }}  item\n{% plural %}{{ n }} items{% endblocktrans %}\n</p>\n"
+    )]
+    #[case::inside_element(
+        "<ul>{% for a in b %}<li>{{ a }}</li>{% endfor %}</ul>",
+        "<ul>\n\t{% for a in b %}\n\t\t<li>\n\t\t\t{{ a }}\n\t\t</li>\n\t{% endfor %}\n</ul>\n"
+    )]
+    fn test_formatting_block(#[case] input: &str, #[case] expected: &str) {
+        let template = Template::parse.parse(new_input(input)).unwrap();
+        assert_eq!(template.formatted(0, &FormatOptions::default()), expected)
+    }
+}
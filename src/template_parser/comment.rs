@@ -1,36 +1,114 @@
 use winnow::{
-    ascii::multispace0,
-    combinator::{alt, delimited, peek},
+    ascii::{multispace0, multispace1},
+    combinator::{alt, delimited, opt, peek, preceded},
     error::{ErrorKind, ParserError},
     prelude::*,
     stream::{AsChar, Compare, Offset, Stream, StreamIsPartial},
     token::{literal, take_until},
 };
 
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::Input;
+
+/// Which of Django's two comment syntaxes a comment was written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentKind<'i> {
+    /// `{# comment #}`
+    SingleLine,
+    /// `{% comment "label" %}comment{% endcomment %}`, with the label being optional
+    Block { label: Option<&'i str> },
+}
 
 /// A comment tag. Can either be in the single line form (`{# comment #}`) or the multi-line form
 /// (`{% comment %}
 /// {% endcomment %}`).
-#[derive(Debug, PartialEq)]
-pub struct Comment<'i>(pub &'i str);
+#[derive(Debug)]
+pub struct Comment<'i> {
+    /// The text of a single line comment without the space around it, or the body of a block
+    /// comment exactly as written
+    pub text: &'i str,
+    pub kind: CommentKind<'i>,
+}
+
+/// A single line comment is the same as a block comment with the same text, as that's what it's
+/// turned into when it's too long.
+impl PartialEq for Comment<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.kind, other.kind) {
+            (CommentKind::SingleLine, CommentKind::SingleLine) => self.text == other.text,
+            (CommentKind::Block { label }, CommentKind::Block { label: other_label }) => {
+                label == other_label && self.text == other.text
+            }
+            _ => self.text.trim() == other.text.trim(),
+        }
+    }
+}
 
 impl Formatable for Comment<'_> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        self.0.to_string()
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        match self.kind {
+            CommentKind::SingleLine => {
+                let single_line = if self.text.is_empty() {
+                    Doc::text("{# #}")
+                } else {
+                    Doc::text(format!("{{# {} #}}", self.text))
+                };
+                if !options.long_comments_as_blocks {
+                    return single_line;
+                }
+                let block = Doc::concat([
+                    Doc::text("{% comment %}"),
+                    Doc::indent(Doc::concat([Doc::HardLine, Doc::text(self.text)])),
+                    Doc::HardLine,
+                    Doc::text("{% endcomment %}"),
+                ]);
+                Doc::group(Doc::if_break(block, single_line))
+            }
+            CommentKind::Block { label: None } => {
+                Doc::text(format!("{{% comment %}}{}{{% endcomment %}}", self.text))
+            }
+            CommentKind::Block { label: Some(label) } => Doc::text(format!(
+                "{{% comment {label} %}}{}{{% endcomment %}}",
+                self.text
+            )),
+        }
     }
 }
 
 impl<'i> Comment<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
         alt((parse_single_line_comment, parse_multi_line_comment)).parse_next(input)
     }
+
+    pub fn single_line(text: &'i str) -> Self {
+        Self {
+            text,
+            kind: CommentKind::SingleLine,
+        }
+    }
+
+    /// Whether the comment goes on its own line, rather than flowing along with the text around
+    /// it.
+    pub fn is_block(&self, indent_level: usize, options: &FormatOptions) -> bool {
+        matches!(self.kind, CommentKind::Block { .. })
+            || self.converts_to_block(indent_level, options)
+    }
+
+    /// Whether a single line comment would be too long at the indent, and should be written in the
+    /// block form instead.
+    fn converts_to_block(&self, indent_level: usize, options: &FormatOptions) -> bool {
+        let width = options.width(&options.indent(indent_level)) + options.width(self.text) + 6;
+        self.kind == CommentKind::SingleLine
+            && options.long_comments_as_blocks
+            && width > options.line_width
+    }
 }
 
-pub fn parse_single_line_comment<'i>(input: &mut &'i str) -> PResult<Comment<'i>> {
+pub fn parse_single_line_comment<'i>(input: &mut Input<'i>) -> PResult<Comment<'i>> {
     let contents = delimited("{#", take_until(0.., "#}"), "#}").parse_next(input)?;
 
-    Ok(Comment(contents.trim()))
+    Ok(Comment::single_line(contents.trim()))
 }
 
 fn escape_tag<I, O, E>(parser: impl Parser<I, O, E>) -> impl Parser<I, O, E>
@@ -46,8 +124,14 @@ where
     )
 }
 
-pub fn parse_multi_line_comment<'i>(input: &mut &'i str) -> PResult<Comment<'i>> {
-    escape_tag("comment").parse_next(input)?;
+pub fn parse_multi_line_comment<'i>(input: &mut Input<'i>) -> PResult<Comment<'i>> {
+    let label = escape_tag(preceded(
+        "comment",
+        opt(preceded(multispace1, take_until(0.., "%}"))),
+    ))
+    .parse_next(input)?
+    .map(str::trim)
+    .filter(|label| !label.is_empty());
 
     let start = input.checkpoint();
     let mut end = input.checkpoint();
@@ -73,7 +157,10 @@ pub fn parse_multi_line_comment<'i>(input: &mut &'i str) -> PResult<Comment<'i>>
 
     let _end_comment = escape_tag("endcomment").parse_next(input)?;
 
-    Ok(Comment(comment))
+    Ok(Comment {
+        text: comment,
+        kind: CommentKind::Block { label },
+    })
 }
 
 #[cfg(test)]
@@ -83,51 +170,118 @@ mod tests {
     use winnow::error::ErrorKind;
 
     use super::*;
+    use crate::span::new_input;
 
     #[rstest]
     #[case::empty_comment("{% comment %}", "")]
     #[case::empty_comment("{%comment%}", "")]
     fn test_escape_tag(#[case] input: &str, #[case] expected: &str) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         escape_tag::<_, _, ErrorKind>("comment")
             .parse_next(&mut input)
             .unwrap();
 
-        assert_eq!(input, expected)
+        assert_eq!(**input, expected)
+    }
+
+    fn block<'i>(text: &'i str, label: Option<&'i str>) -> Comment<'i> {
+        Comment {
+            text,
+            kind: CommentKind::Block { label },
+        }
     }
 
     #[rstest]
-    #[case::single_line_no_spaces("{#comment#}", Comment("comment"))]
-    #[case::single_line_spaces("{# comment #}", Comment("comment"))]
-    #[case::single_line_excessive_spaces("{#  comment  #}", Comment("comment"))]
-    #[case::single_line_excessive_spaces_and_hashes("{#  #comment#  #}", Comment("#comment#"))]
+    #[case::single_line_no_spaces("{#comment#}", Comment::single_line("comment"))]
+    #[case::single_line_spaces("{# comment #}", Comment::single_line("comment"))]
+    #[case::single_line_excessive_spaces("{#  comment  #}", Comment::single_line("comment"))]
+    #[case::single_line_excessive_spaces_and_hashes(
+        "{#  #comment#  #}",
+        Comment::single_line("#comment#")
+    )]
     fn test_single_line_comment_parsing(#[case] input: &str, #[case] expected: Comment) {
-        let actual = parse_single_line_comment.parse(input).unwrap();
+        let actual = parse_single_line_comment.parse(new_input(input)).unwrap();
+        assert_eq!(actual.kind, expected.kind);
         assert_eq!(actual, expected)
     }
 
     #[rstest]
-    #[case::multi_line_no_spaces("{%comment%}{%endcomment%}", Comment(""))]
-    #[case::multi_line_spaces("{% comment %}{% endcomment %}", Comment(""))]
+    #[case::multi_line_no_spaces("{%comment%}{%endcomment%}", block("", None))]
+    #[case::multi_line_spaces("{% comment %}{% endcomment %}", block("", None))]
     #[case::multi_line_spaces_with_content(
         "{% comment %}this is a comment{% endcomment %}",
-        Comment("this is a comment")
+        block("this is a comment", None)
     )]
     #[case::multi_line_spaces_with_multi_line_content(
         "{% comment %}this is a comment\n{% endcomment %}",
-        Comment("this is a comment\n")
+        block("this is a comment\n", None)
     )]
     #[case::multi_line_spaces_with_multi_line_content_and_variable(
         "{% comment %}this is a comment\n{{variable}}{% endcomment %}",
-        Comment("this is a comment\n{{variable}}")
+        block("this is a comment\n{{variable}}", None)
     )]
     #[case::multi_line_spaces_with_multi_line_content_and_variable_and_block(
         "{% comment %}this is a comment\n{{variable}}{% block %}{% endcomment %}",
-        Comment("this is a comment\n{{variable}}{% block %}")
+        block("this is a comment\n{{variable}}{% block %}", None)
+    )]
+    #[case::label(
+        "{% comment \"Optional note\" %}a{% endcomment %}",
+        block("a", Some("\"Optional note\""))
     )]
+    #[case::label_without_quotes("{%comment  note  %}a{% endcomment %}", block("a", Some("note")))]
     fn test_multi_line_comment_parsing(#[case] input: &str, #[case] expected: Comment) {
-        let actual = parse_multi_line_comment.parse(input).unwrap();
+        let actual = parse_multi_line_comment.parse(new_input(input)).unwrap();
+        assert_eq!(actual.kind, expected.kind);
         assert_eq!(actual, expected)
     }
+
+    #[rstest]
+    #[case("{% commentary %}{% endcomment %}")]
+    #[case("{% comment %}unclosed")]
+    fn test_invalid_multi_line_comment(#[case] input: &str) {
+        assert!(parse_multi_line_comment.parse(new_input(input)).is_err());
+    }
+
+    #[rstest]
+    #[case::single_line("{#comment#}", "{# comment #}")]
+    #[case::single_line_spaces("{#   a  b   #}", "{# a  b #}")]
+    #[case::empty_single_line("{##}", "{# #}")]
+    #[case::block_kept_verbatim(
+        "{%comment%}\n  a\n\t{{ b }}  {%endcomment%}",
+        "{% comment %}\n  a\n\t{{ b }}  {% endcomment %}"
+    )]
+    #[case::block_with_label(
+        "{% comment   'why'   %} a {% endcomment %}",
+        "{% comment 'why' %} a {% endcomment %}"
+    )]
+    fn test_formatting_comment(#[case] input: &str, #[case] expected: &str) {
+        let actual = Comment::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &FormatOptions::default()), expected)
+    }
+
+    #[rstest]
+    #[case::fits(false, 20, 0, "{# a long comment #}")]
+    #[case::not_enabled(false, 10, 0, "{# a long comment #}")]
+    #[case::too_long(true, 10, 0, "{% comment %}\n\ta long comment\n{% endcomment %}")]
+    #[case::too_long_once_indented(
+        true,
+        20,
+        1,
+        "{% comment %}\n\t\ta long comment\n\t{% endcomment %}"
+    )]
+    fn test_converting_long_comments(
+        #[case] long_comments_as_blocks: bool,
+        #[case] line_width: usize,
+        #[case] indent_level: usize,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            long_comments_as_blocks,
+            line_width,
+            ..FormatOptions::default()
+        };
+        let comment = Comment::single_line("a long comment");
+        assert_eq!(comment.formatted(indent_level, &options), expected)
+    }
 }
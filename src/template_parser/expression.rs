@@ -0,0 +1,328 @@
+use super::argument::{TagArgument, TagArgumentValue};
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input};
+use winnow::{
+    ascii::{multispace0, multispace1},
+    combinator::{alt, not, opt},
+    error::{AddContext, ContextError, ErrMode, StrContext, StrContextValue},
+    stream::Stream,
+    token::one_of,
+    PResult, Parser,
+};
+
+/// The words that can't be used as variables in a condition.
+const KEYWORDS: &[&str] = &["and", "or", "not", "in", "is"];
+
+/// How tightly `not` binds its operand, between `and` and `in` like in Django's smartif.
+const NOT_BINDING_POWER: u8 = 8;
+
+/// An operator between two parts of a condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Or,
+    And,
+    In,
+    NotIn,
+    Is,
+    IsNot,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+}
+
+impl Operator {
+    fn parse(input: &mut Input<'_>) -> PResult<Self> {
+        alt((
+            "==".value(Operator::Equal),
+            "!=".value(Operator::NotEqual),
+            "<=".value(Operator::LessThanOrEqual),
+            ">=".value(Operator::GreaterThanOrEqual),
+            "<".value(Operator::LessThan),
+            ">".value(Operator::GreaterThan),
+            keyword("or").value(Operator::Or),
+            keyword("and").value(Operator::And),
+            keyword("in").value(Operator::In),
+            ("not", multispace1, keyword("in")).value(Operator::NotIn),
+            ("is", multispace1, keyword("not")).value(Operator::IsNot),
+            keyword("is").value(Operator::Is),
+        ))
+        .parse_next(input)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Or => "or",
+            Operator::And => "and",
+            Operator::In => "in",
+            Operator::NotIn => "not in",
+            Operator::Is => "is",
+            Operator::IsNot => "is not",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::LessThan => "<",
+            Operator::GreaterThan => ">",
+            Operator::LessThanOrEqual => "<=",
+            Operator::GreaterThanOrEqual => ">=",
+        }
+    }
+
+    /// How tightly the operator binds, from Django's smartif.
+    fn binding_power(&self) -> u8 {
+        match self {
+            Operator::Or => 6,
+            Operator::And => 7,
+            Operator::In | Operator::NotIn => 9,
+            _ => 10,
+        }
+    }
+}
+
+/// The condition of an `{% if %}` or `{% elif %}` tag, like `a and not b or c in d`.
+#[derive(Debug, PartialEq)]
+pub enum Expression<'i> {
+    /// A value to test, like `user.is_staff` or `items|length`
+    Operand(TagArgument<'i>),
+    Not(Box<Expression<'i>>),
+    Binary {
+        operator: Operator,
+        left: Box<Expression<'i>>,
+        right: Box<Expression<'i>>,
+    },
+}
+
+impl Equivalent for Expression<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expression::Operand(operand), Expression::Operand(other)) => {
+                operand.is_equivalent(other)
+            }
+            (Expression::Not(expression), Expression::Not(other)) => {
+                expression.is_equivalent(other)
+            }
+            (
+                Expression::Binary {
+                    operator,
+                    left,
+                    right,
+                },
+                Expression::Binary {
+                    operator: other_operator,
+                    left: other_left,
+                    right: other_right,
+                },
+            ) => {
+                operator == other_operator
+                    && left.is_equivalent(other_left)
+                    && right.is_equivalent(other_right)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'i> Expression<'i> {
+    /// Parses a whole condition, failing with a cut error that points at whatever isn't valid.
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        Self::parse_binding(0, input)
+    }
+
+    /// Parses operators that bind more tightly than `binding_power`, like Django's Pratt parser.
+    fn parse_binding(binding_power: u8, input: &mut Input<'i>) -> PResult<Self> {
+        multispace0.parse_next(input)?;
+        let mut left = if opt(keyword("not")).parse_next(input)?.is_some() {
+            Expression::Not(Box::new(Self::parse_binding(NOT_BINDING_POWER, input)?))
+        } else {
+            Expression::Operand(parse_operand(input)?)
+        };
+
+        loop {
+            let start = input.checkpoint();
+            multispace0.parse_next(input)?;
+            let operator = match opt(Operator::parse).parse_next(input)? {
+                Some(operator) if operator.binding_power() > binding_power => operator,
+                _ => {
+                    input.reset(&start);
+                    return Ok(left);
+                }
+            };
+            let right = Self::parse_binding(operator.binding_power(), input)?;
+            left = Expression::Binary {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+    }
+
+    /// Every value tested by the condition, in order.
+    pub fn operands(&self) -> Vec<&TagArgument<'i>> {
+        match self {
+            Expression::Operand(argument) => vec![argument],
+            Expression::Not(expression) => expression.operands(),
+            Expression::Binary { left, right, .. } => {
+                let mut operands = left.operands();
+                operands.extend(right.operands());
+                operands
+            }
+        }
+    }
+}
+
+impl<'i> Formatable for Expression<'i> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        match self {
+            Expression::Operand(argument) => argument.to_doc(options),
+            Expression::Not(expression) => {
+                Doc::concat([Doc::text("not "), expression.to_doc(options)])
+            }
+            Expression::Binary {
+                operator,
+                left,
+                right,
+            } => Doc::concat([
+                left.to_doc(options),
+                Doc::text(format!(" {} ", operator.as_str())),
+                right.to_doc(options),
+            ]),
+        }
+    }
+}
+
+/// Parses a condition and what's left of the tag, which must be nothing but its end.
+pub fn parse_condition<'i>(input: &mut Input<'i>) -> PResult<Expression<'i>> {
+    let condition = Expression::parse.parse_next(input)?;
+    multispace0.parse_next(input)?;
+    if !input.starts_with("%}") {
+        let start = input.checkpoint();
+        return Err(condition_error(input, &start, "an operator"));
+    }
+    Ok(condition)
+}
+
+fn parse_operand<'i>(input: &mut Input<'i>) -> PResult<TagArgument<'i>> {
+    let start = input.checkpoint();
+    match TagArgument::parse_positional.parse_next(input) {
+        Ok(argument) if !is_keyword(&argument) => Ok(argument),
+        _ => {
+            input.reset(&start);
+            Err(condition_error(input, &start, "a value"))
+        }
+    }
+}
+
+fn is_keyword(argument: &TagArgument) -> bool {
+    matches!(argument.value, TagArgumentValue::Variable(name) if KEYWORDS.contains(&name))
+        && argument.filters.is_empty()
+}
+
+/// Matches a word that isn't just the start of a longer name, like `in` but not `index`.
+fn keyword<'i>(word: &'static str) -> impl Parser<Input<'i>, &'i str, ContextError> {
+    (
+        word,
+        not(one_of(|c: char| {
+            c.is_alphanumeric() || c == '_' || c == '.'
+        })),
+    )
+        .take()
+}
+
+fn condition_error<'i>(
+    input: &Input<'i>,
+    start: &<Input<'i> as Stream>::Checkpoint,
+    expected: &'static str,
+) -> ErrMode<ContextError> {
+    ErrMode::Cut(
+        ContextError::new()
+            .add_context(input, start, StrContext::Label("condition"))
+            .add_context(
+                input,
+                start,
+                StrContext::Expected(StrContextValue::Description(expected)),
+            ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::span::{assert_equivalent, new_input, Span};
+    use crate::template_parser::argument::ArgumentKind;
+
+    fn operand(name: &str) -> Box<Expression<'_>> {
+        Box::new(Expression::Operand(TagArgument {
+            kind: ArgumentKind::Positional,
+            value: TagArgumentValue::Variable(name),
+            filters: vec![],
+            span: Span::default(),
+        }))
+    }
+
+    #[rstest]
+    #[case::single("a", *operand("a"))]
+    #[case::not("not a", Expression::Not(operand("a")))]
+    #[case::or_binds_loosest("a and b or c", Expression::Binary {
+        operator: Operator::Or,
+        left: Box::new(Expression::Binary { operator: Operator::And, left: operand("a"), right: operand("b") }),
+        right: operand("c"),
+    })]
+    #[case::and_binds_tighter("a or b and c", Expression::Binary {
+        operator: Operator::Or,
+        left: operand("a"),
+        right: Box::new(Expression::Binary { operator: Operator::And, left: operand("b"), right: operand("c") }),
+    })]
+    #[case::not_binds_looser_than_comparisons("not a == b", Expression::Not(Box::new(Expression::Binary {
+        operator: Operator::Equal,
+        left: operand("a"),
+        right: operand("b"),
+    })))]
+    #[case::not_binds_tighter_than_and("not a and b", Expression::Binary {
+        operator: Operator::And,
+        left: Box::new(Expression::Not(operand("a"))),
+        right: operand("b"),
+    })]
+    #[case::left_associative("a or b or c", Expression::Binary {
+        operator: Operator::Or,
+        left: Box::new(Expression::Binary { operator: Operator::Or, left: operand("a"), right: operand("b") }),
+        right: operand("c"),
+    })]
+    #[case::not_in("a not in b", Expression::Binary { operator: Operator::NotIn, left: operand("a"), right: operand("b") })]
+    #[case::is_not("a is not b", Expression::Binary { operator: Operator::IsNot, left: operand("a"), right: operand("b") })]
+    #[case::without_spaces("a<=b", Expression::Binary { operator: Operator::LessThanOrEqual, left: operand("a"), right: operand("b") })]
+    #[case::keyword_prefix("index in notes", Expression::Binary { operator: Operator::In, left: operand("index"), right: operand("notes") })]
+    fn test_parsing_expression(#[case] input: &str, #[case] expected: Expression) {
+        let actual = Expression::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
+    }
+
+    #[rstest]
+    #[case("a and not b or c in d", "a and not b or c in d")]
+    #[case("x==1", "x == 1")]
+    #[case("x|length>=2", "x|length >= 2")]
+    #[case("not  a   is not   None", "not a is not None")]
+    #[case("a!='b'", "a != 'b'")]
+    #[case("-1<x", "-1 < x")]
+    fn test_formatting_expression(#[case] input: &str, #[case] expected: &str) {
+        let actual = Expression::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &FormatOptions::default()), expected)
+    }
+
+    #[rstest]
+    #[case::dangling_and("a and %}", 6)]
+    #[case::empty(" %}", 1)]
+    #[case::keyword_operand("and b %}", 0)]
+    #[case::missing_operator("a b %}", 2)]
+    #[case::not_without_operand("not %}", 4)]
+    fn test_parsing_invalid_condition(#[case] input: &str, #[case] offset: usize) {
+        let mut stream = new_input(input);
+        let result = parse_condition.parse_next(&mut stream);
+        assert!(matches!(result, Err(ErrMode::Cut(_))));
+        assert_eq!(input.len() - stream.len(), offset);
+    }
+}
@@ -1,34 +1,119 @@
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input, Span};
 
 use super::{argument::TagArgumentValue, variable::parse_variable};
 use winnow::{
     ascii::multispace0,
-    combinator::{delimited, opt, separated},
+    combinator::{delimited, opt, preceded, repeat},
     error::{ErrMode, ErrorKind, ParserError},
+    stream::Location,
     PResult, Parser,
 };
 
-pub fn parse_filter_chain<'i>(input: &mut &'i str) -> PResult<Vec<Filter<'i>>> {
-    let _ = opt(delimited(multispace0, '|', multispace0)).parse_next(input)?;
+/// The filters that come with Django, including the ones from the `i18n`, `l10n`, `tz` and
+/// `humanize` libraries.
+pub const BUILTIN_FILTERS: &[&str] = &[
+    "add",
+    "addslashes",
+    "apnumber",
+    "capfirst",
+    "center",
+    "cut",
+    "date",
+    "default",
+    "default_if_none",
+    "dictsort",
+    "dictsortreversed",
+    "divisibleby",
+    "escape",
+    "escapejs",
+    "escapeseq",
+    "filesizeformat",
+    "first",
+    "floatformat",
+    "force_escape",
+    "get_digit",
+    "intcomma",
+    "intword",
+    "iriencode",
+    "join",
+    "json_script",
+    "language_bidi",
+    "language_name",
+    "language_name_local",
+    "language_name_translated",
+    "last",
+    "length",
+    "length_is",
+    "linebreaks",
+    "linebreaksbr",
+    "linenumbers",
+    "ljust",
+    "localize",
+    "localtime",
+    "lower",
+    "make_list",
+    "naturalday",
+    "naturaltime",
+    "ordinal",
+    "phone2numeric",
+    "pluralize",
+    "pprint",
+    "random",
+    "rjust",
+    "safe",
+    "safeseq",
+    "slice",
+    "slugify",
+    "stringformat",
+    "striptags",
+    "time",
+    "timesince",
+    "timeuntil",
+    "timezone",
+    "title",
+    "truncatechars",
+    "truncatechars_html",
+    "truncatewords",
+    "truncatewords_html",
+    "unlocalize",
+    "unordered_list",
+    "upper",
+    "urlencode",
+    "urlize",
+    "urlizetrunc",
+    "utc",
+    "wordcount",
+    "wordwrap",
+    "yesno",
+];
 
-    let mut parse_filter = separated(0.., Filter::parse, (multispace0, '|', multispace0));
-    parse_filter.parse_next(input)
+/// Parses the filters applied to a value, each after a `|` like in `|default:"none"|upper`.
+pub fn parse_filter_chain<'i>(input: &mut Input<'i>) -> PResult<Vec<Filter<'i>>> {
+    repeat(
+        0..,
+        preceded((multispace0, '|', multispace0), Filter::parse),
+    )
+    .parse_next(input)
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Filter<'i> {
     pub filter_type: &'i str,
     pub argument: Option<TagArgumentValue<'i>>,
+    pub span: Span,
 }
 
-impl<'i> PartialEq for Filter<'i> {
-    fn eq(&self, other: &Self) -> bool {
+impl Equivalent for Filter<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
         self.filter_type == other.filter_type && self.argument == other.argument
     }
 }
 
 impl<'i> Filter<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let start = input.location();
         let filter_type = parse_variable.parse_next(input)?;
 
         let semicolon = opt(delimited(multispace0, ':', multispace0)).parse_next(input)?;
@@ -42,6 +127,7 @@ impl<'i> Filter<'i> {
         let filter = Self {
             filter_type,
             argument,
+            span: (start..input.location()).into(),
         };
 
         Ok(filter)
@@ -49,14 +135,13 @@ impl<'i> Filter<'i> {
 }
 
 impl<'i> Formatable for Filter<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        let return_string = format!("|{}", self.filter_type).to_string();
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
         match &self.argument {
-            Some(argument) => match argument {
-                TagArgumentValue::Text(text) => return_string + &format!(":\"{}\"", text.value),
-                TagArgumentValue::Variable(variable) => return_string + &format!(":{}", variable),
-            },
-            None => return_string,
+            Some(argument) => Doc::concat([
+                Doc::text(format!("|{}:", self.filter_type)),
+                argument.to_doc(options),
+            ]),
+            None => Doc::text(format!("|{}", self.filter_type)),
         }
     }
 }
@@ -69,40 +154,51 @@ mod tests {
     use crate::template_parser::text::SingleLineTextString;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case::no_filters("", vec![])]
-    #[case::single_simple_filter("my_filter", vec![Filter {
-        filter_type: "my_filter",
-        argument: None,
-    }])]
     #[case::single_simple_filter("|my_filter", vec![Filter {
         filter_type: "my_filter",
         argument: None,
+        span: Span::default(),
     }])]
-    #[case::multiple_simple_filters("my_filter|my_filter2", vec![Filter {
+    #[case::multiple_simple_filters("|my_filter|my_filter2", vec![Filter {
         filter_type: "my_filter",
         argument: None,
+        span: Span::default(),
     }, Filter {
         filter_type: "my_filter2",
         argument: None,
+        span: Span::default(),
     }])]
-    #[case::multiple_simple_filters_with_spaces("my_filter | my_filter2", vec![Filter {
+    #[case::multiple_simple_filters_with_spaces(" | my_filter | my_filter2", vec![Filter {
         filter_type: "my_filter",
         argument: None,
+        span: Span::default(),
     }, Filter {
         filter_type: "my_filter2",
         argument: None,
+        span: Span::default(),
     }])]
     fn test_parsing_filter_chain(#[case] input: &str, #[case] expected: Vec<Filter>) {
-        let actual = parse_filter_chain.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = parse_filter_chain.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
+    }
+
+    #[rstest]
+    #[case::no_pipe("my_filter")]
+    #[case::second_without_pipe("|my_filter my_filter2")]
+    #[case::dangling_pipe("|my_filter|")]
+    fn test_parsing_filter_chain_fails(#[case] input: &str) {
+        assert!(parse_filter_chain.parse(new_input(input)).is_err());
     }
 
     #[rstest]
     #[case::no_argument("my_filter", Filter {
         filter_type: "my_filter",
         argument: None,
+        span: Span::default(),
     })]
     #[case::single_argument("my_filter:\"my_arg\"", Filter {
         filter_type: "my_filter",
@@ -112,6 +208,7 @@ mod tests {
                 startquote_char: '"',
             })
         ),
+        span: Span::default(),
     })]
     #[case::single_argument("my_filter:'my_arg'", Filter {
         filter_type: "my_filter",
@@ -121,10 +218,11 @@ mod tests {
                 startquote_char: '\'',
             })
         ),
+        span: Span::default(),
     })]
     fn test_filter_parsing(#[case] input: &str, #[case] expected: Filter) {
-        let actual = Filter::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = Filter::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
     }
 
     #[rstest]
@@ -137,7 +235,7 @@ mod tests {
     #[case("my_filter:'my_arg")]
     #[case("my_filter:'my_arg\"")]
     fn test_filter_parsing_unsuccessful(#[case] input: &str) {
-        let actual = Filter::parse.parse(input);
+        let actual = Filter::parse.parse(new_input(input));
         assert!(actual.is_err())
     }
 
@@ -145,6 +243,7 @@ mod tests {
     #[case::no_argument(Filter {
         filter_type: "my_filter",
         argument: None,
+        span: Span::default(),
     },"|my_filter")]
     #[case::single_argument(Filter {
         filter_type: "my_filter",
@@ -154,9 +253,10 @@ mod tests {
                 startquote_char: '"',
             })
         ),
+        span: Span::default(),
     }, "|my_filter:\"my_arg\"")]
     fn test_formatting_filter(#[case] input: Filter, #[case] expected: String) {
-        let actual = input.formatted(0);
+        let actual = input.formatted(0, &FormatOptions::default());
         assert_eq!(actual, expected)
     }
 }
@@ -1,84 +1,260 @@
-use crate::formatting::Formatable;
-use comment::Comment;
-use tag::Tag;
-use variable::VariableTag;
-use winnow::{PResult, Parser};
-
-mod argument;
-mod comment;
-mod filter;
-mod tag;
-mod text;
-mod utils;
-mod variable;
+use crate::diagnostic::Diagnostic;
+use crate::doc::Doc;
+use crate::error::Error;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::html_parser::{
+    attribute::{AttributeOrder, ClassOrder},
+    node::{
+        child_nodes_doc, find_difference, for_each_element, for_each_element_mut, for_each_filter,
+        for_each_node, for_each_template_syntax, is_blank, parse_child_nodes, Node, Raw,
+    },
+};
+use crate::span::{new_input_with_tags, Input, Span};
+use filter::BUILTIN_FILTERS;
+use registry::TagRegistry;
+use winnow::{error::ContextError, stream::Location, PResult, Parser};
 
-#[derive(Debug, PartialEq)]
-pub enum Node<'a> {
-    Variable(VariableTag<'a>),
-    Tag(Tag<'a>),
-    Comment(&'a str),
-    Text(&'a str),
-}
+pub mod argument;
+pub mod block;
+pub mod comment;
+pub mod expression;
+pub mod filter;
+pub mod registry;
+pub mod tag;
+pub mod text;
+mod utils;
+pub mod variable;
 
+/// A whole template file, made up of HTML and template syntax nested within each other.
 #[derive(Debug, PartialEq)]
 pub struct Template<'a> {
     nodes: Vec<Node<'a>>,
+    /// What the template was parsed from, which its spans point into
+    source: &'a str,
 }
 
 impl<'i> Template<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let mut nodes = vec![];
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let source: &'i str = *input.input;
+        let mut nodes = parse_child_nodes.parse_next(input)?;
+
+        // Whatever stopped the nodes being parsed, like a `</` that doesn't start a closing tag,
+        // is kept as is and parsing carries on after it
         while !input.is_empty() {
-            // Peek at the input to ensure the parser can successfully parse the next node
-            if Comment::parse.parse_peek(input).is_ok() {
-                if let Ok(variable) = Comment::parse.parse_next(input) {
-                    nodes.push(Node::Comment(variable.0));
-                    continue;
-                }
+            let error_offset = input.location();
+            nodes.push(Node::Raw(Raw::parse_token(
+                input,
+                error_offset,
+                ContextError::new(),
+            )));
+            nodes.extend(parse_child_nodes.parse_next(input)?);
+        }
+
+        Ok(Self { nodes, source })
+    }
+
+    /// Parses a whole template that only uses Django's built-in block tags. Anything that can't
+    /// be parsed is kept as it was written, and reported by [`Template::errors`].
+    pub fn parse_source(source: &'i str) -> Result<Self, Box<Error>> {
+        Self::parse_source_with_tags(source, TagRegistry::builtin())
+    }
+
+    /// Like [`Template::parse_source`], but for templates that use the block tags in `tags`.
+    pub fn parse_source_with_tags(
+        source: &'i str,
+        tags: &'i TagRegistry,
+    ) -> Result<Self, Box<Error>> {
+        Self::parse
+            .parse(new_input_with_tags(source, tags))
+            .map_err(|error| Box::new(Error::new(source, error.offset(), error.inner())))
+    }
+
+    /// The errors for every part of the template that couldn't be parsed. `source` must be the
+    /// input the template was parsed from.
+    pub fn errors(&self, source: &str) -> Vec<Error> {
+        let mut errors = vec![];
+        for_each_node(&self.nodes, &mut |node| {
+            if let Some((offset, error)) = node.parse_error() {
+                errors.push(Error::new(source, offset, error));
             }
+        });
+        errors
+    }
+
+    /// Reorders the attributes of every element in the template.
+    pub fn sort_attributes(&mut self, order: &AttributeOrder) {
+        for_each_element_mut(&mut self.nodes, &mut |element| {
+            element.attributes.sort(order);
+        });
+    }
 
-            if variable::VariableTag::parse.parse_peek(input).is_ok() {
-                if let Ok(variable) = variable::VariableTag::parse.parse_next(input) {
-                    nodes.push(Node::Variable(variable));
-                    continue;
-                }
+    /// Tidies up the `class` attribute of every element in the template, putting the classes in
+    /// `order`.
+    pub fn normalize_classes(&mut self, order: &ClassOrder) {
+        for_each_element_mut(&mut self.nodes, &mut |element| {
+            let classes = element
+                .attributes
+                .kvs
+                .iter_mut()
+                .filter(|attribute| attribute.key.eq_ignore_ascii_case("class"))
+                .filter_map(|attribute| attribute.value.as_mut());
+            for value in classes {
+                value.normalize_classes(order);
             }
+        });
+    }
 
-            if tag::Tag::parse.parse_peek(input).is_ok() {
-                if let Ok(tag) = tag::Tag::parse.parse_next(input) {
-                    nodes.push(Node::Tag(tag));
-                    continue;
-                }
+    /// Reports every attribute that's set more than once on the same element.
+    pub fn duplicate_attributes(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for_each_element(&self.nodes, &mut |element| {
+            for attribute in element.attributes.duplicates() {
+                diagnostics.push(Diagnostic {
+                    span: attribute.span,
+                    message: format!(
+                        "duplicate attribute `{}` on `<{}>`",
+                        attribute.key, element.name
+                    ),
+                });
             }
+        });
+        diagnostics
+    }
 
-            if text::parse_text.parse_peek(input).is_ok() {
-                if let Ok(text) = text::parse_text.parse_next(input) {
-                    if !text.is_empty() {
-                        nodes.push(Node::Text(text));
-                        continue;
-                    }
-                }
+    /// Reports every filter that's neither built into Django nor one of `custom_filters`.
+    pub fn unknown_filters(&self, custom_filters: &[String]) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for_each_filter(&self.nodes, &mut |filter| {
+            let name = filter.filter_type;
+            if !BUILTIN_FILTERS.contains(&name) && !custom_filters.iter().any(|f| f == name) {
+                diagnostics.push(Diagnostic {
+                    span: filter.span,
+                    message: format!("unknown filter `{name}`"),
+                });
             }
+        });
+        diagnostics
+    }
+
+    /// Removes duplicate attributes from every element, keeping the first occurrence.
+    pub fn dedup_attributes(&mut self) {
+        for_each_element_mut(&mut self.nodes, &mut |element| {
+            element.attributes.dedup();
+        });
+    }
+
+    /// Checks that `formatted` is this template after formatting, with the same elements,
+    /// attributes, template tags and text. Only whitespace that doesn't affect the output, and the
+    /// way tags are spaced, may differ, where the whitespace sensitivity in `options` decides which
+    /// whitespace next to elements and comments affects it. Reports where the first difference is
+    /// otherwise.
+    ///
+    /// Template tags are compared by their source as well as how they parsed, as some corruptions,
+    /// like `10px` becoming `10|px`, parse the same.
+    pub fn check_equivalent(
+        &self,
+        formatted: &Template,
+        options: &FormatOptions,
+    ) -> Result<(), Diagnostic> {
+        let span = Span {
+            start: 0,
+            end: self.nodes.last().map_or(0, |node| node.span().end),
+        };
+        find_difference(&self.nodes, &formatted.nodes, span, false, options)
+            .or_else(|| self.find_syntax_difference(formatted))
+            .map_or(Ok(()), |span| {
+                Err(Diagnostic {
+                    span,
+                    message: "formatting would change the template here, so it was left as is"
+                        .to_string(),
+                })
+            })
+    }
 
-            break;
+    /// Finds the first template tag or variable whose source differs from its counterpart in
+    /// `other` by more than spacing and quotes.
+    fn find_syntax_difference(&self, other: &Template) -> Option<Span> {
+        let mut spans = vec![];
+        for_each_template_syntax(&self.nodes, &mut |span| spans.push(span));
+        let mut other_spans = vec![];
+        for_each_template_syntax(&other.nodes, &mut |span| other_spans.push(span));
+        if spans.len() != other_spans.len() {
+            return spans.first().copied();
         }
+        spans
+            .into_iter()
+            .zip(other_spans)
+            .find(|(span, other_span)| {
+                normalize_template_syntax(&self.source[span.range()])
+                    != normalize_template_syntax(&other.source[other_span.range()])
+            })
+            .map(|(span, _)| span)
+    }
+}
 
-        Ok(Self { nodes })
+/// Normalizes the source of a template tag or variable, like `{% url 'home' %}`, to what's left
+/// after formatting it regardless of the options: the delimiters are dropped, whitespace is
+/// collapsed, and removed around operators, and strings use double quotes where they can.
+fn normalize_template_syntax(source: &str) -> String {
+    let inner = source
+        .get(2..source.len().saturating_sub(2))
+        .unwrap_or(source);
+    let is_operator = |c: char| "|:=,<>!".contains(c);
+    let mut normalized = String::new();
+    let mut space = false;
+    let mut chars = inner.trim().chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        let after_word = normalized
+            .chars()
+            .next_back()
+            .is_some_and(|last| last.is_alphanumeric() || last == '_');
+        let after_operator = normalized.chars().next_back().is_some_and(is_operator);
+        let is_quote = c == '"' || c == '\'';
+        if (space || (is_quote && after_word)) && !after_operator && !is_operator(c) {
+            normalized.push(' ');
+        }
+        space = false;
+        if !is_quote {
+            normalized.push(c);
+            continue;
+        }
+        let mut content = String::new();
+        let mut closed = false;
+        while let Some(next) = chars.next() {
+            if next == c {
+                closed = true;
+                break;
+            }
+            content.push(next);
+            if next == '\\' {
+                content.extend(chars.next());
+            }
+        }
+        let quote = if content.contains(['"', '\\']) {
+            c
+        } else {
+            '"'
+        };
+        normalized.push(quote);
+        normalized.push_str(&content);
+        if closed {
+            normalized.push(quote);
+        }
     }
+    normalized
 }
 
 impl<'i> Formatable for Template<'i> {
-    fn formatted(&self, indent_level: usize) -> String {
-        let mut result = String::new();
-        for node in &self.nodes {
-            match node {
-                Node::Variable(var) => result.push_str(&var.formatted(indent_level)),
-                Node::Tag(tag) => result.push_str(&tag.formatted(indent_level)),
-                Node::Comment(comment) => result.push_str(&comment.formatted(indent_level)),
-                Node::Text(text) => result.push_str(text),
-            }
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let mut docs = vec![child_nodes_doc(&self.nodes, false, options)];
+        if options.final_newline && !is_blank(&self.nodes) {
+            docs.push(Doc::HardLine);
         }
-        result
+        Doc::concat(docs)
     }
 }
 
@@ -88,52 +264,715 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::config::CustomTag;
+    use crate::formatting::{
+        BracketPlacement, IndentStyle, QuoteStyle, VoidStyle, WhitespaceSensitivity,
+    };
+    use crate::span::{assert_equivalent, new_input, Span};
+    use argument::{ArgumentKind, TagArgument, TagArgumentValue};
+    use block::{Block, Branch};
+    use comment::Comment;
+    use expression::Expression;
+    use tag::Tag;
+    use variable::VariableTag;
+
+    #[rstest]
+    #[case::empty_template("", vec![])]
+    #[case("text", vec![
+        Node::Text("text".into()),
+    ])]
+    #[case("{{text}}", vec![
+        Node::Variable(VariableTag { value: TagArgumentValue::Variable("text"), filters: vec![], span: Span::default() }),
+    ])]
+    #[case("hello{{text}}world", vec![
+        Node::Text("hello".into()),
+        Node::Variable(VariableTag { value: TagArgumentValue::Variable("text"), filters: vec![], span: Span::default() }),
+        Node::Text("world".into()),
+    ])]
+    #[case("hello{{text}}there{% thing %}world", vec![
+        Node::Text("hello".into()),
+        Node::Variable(VariableTag { value: TagArgumentValue::Variable("text"), filters: vec![], span: Span::default() }),
+        Node::Text("there".into()),
+        Node::Tag(Tag { tag_type: "thing", arguments: vec![], condition: None, span: Span::default() }),
+        Node::Text("world".into()),
+    ])]
+    #[case("hello{{text}}there{# comment #}again{% thing %}world", vec![
+        Node::Text("hello".into()),
+        Node::Variable(VariableTag { value: TagArgumentValue::Variable("text"), filters: vec![], span: Span::default() }),
+        Node::Text("there".into()),
+        Node::TemplateComment(Comment::single_line("comment").into()),
+        Node::Text("again".into()),
+        Node::Tag(Tag { tag_type: "thing", arguments: vec![], condition: None, span: Span::default() }),
+        Node::Text("world".into()),
+    ])]
+    fn test_parsing_template(#[case] input: &str, #[case] expected: Vec<Node>) {
+        let actual = Template::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual.nodes, &expected)
+    }
+
+    #[rstest]
+    #[case("{% if a %}yes{% endif %}", vec![
+        Node::Block(Block {
+            opening_tag: Tag { tag_type: "if", arguments: vec![], condition: Some(Box::new(Expression::Operand(TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("a"), filters: vec![], span: Span::default() }))), span: Span::default() },
+            children: vec![Node::Text("yes".into())],
+            branches: vec![],
+            closing_tag: Tag { tag_type: "endif", arguments: vec![], condition: None, span: Span::default() },
+            raw: false,
+        }),
+    ])]
+    #[case("{% if a %}a{% elif b %}b{% else %}c{% endif %}", vec![
+        Node::Block(Block {
+            opening_tag: Tag { tag_type: "if", arguments: vec![], condition: Some(Box::new(Expression::Operand(TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("a"), filters: vec![], span: Span::default() }))), span: Span::default() },
+            children: vec![Node::Text("a".into())],
+            branches: vec![
+                Branch {
+                    tag: Tag { tag_type: "elif", arguments: vec![], condition: Some(Box::new(Expression::Operand(TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("b"), filters: vec![], span: Span::default() }))), span: Span::default() },
+                    children: vec![Node::Text("b".into())],
+                },
+                Branch {
+                    tag: Tag { tag_type: "else", arguments: vec![], condition: None, span: Span::default() },
+                    children: vec![Node::Text("c".into())],
+                },
+            ],
+            closing_tag: Tag { tag_type: "endif", arguments: vec![], condition: None, span: Span::default() },
+            raw: false,
+        }),
+    ])]
+    #[case("{% block body %}{% for x in y %}{{ x }}{% empty %}none{% endfor %}{% endblock %}", vec![
+        Node::Block(Block {
+            opening_tag: Tag { tag_type: "block", arguments: vec![TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("body"), filters: vec![], span: Span::default() }], condition: None, span: Span::default() },
+            children: vec![Node::Block(Block {
+                opening_tag: Tag { tag_type: "for", arguments: vec![
+                    TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("x"), filters: vec![], span: Span::default() },
+                    TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("in"), filters: vec![], span: Span::default() },
+                    TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("y"), filters: vec![], span: Span::default() },
+                ], condition: None, span: Span::default() },
+                children: vec![
+                    Node::Variable(VariableTag { value: TagArgumentValue::Variable("x"), filters: vec![], span: Span::default() }),
+                ],
+                branches: vec![Branch {
+                    tag: Tag { tag_type: "empty", arguments: vec![], condition: None, span: Span::default() },
+                    children: vec![Node::Text("none".into())],
+                }],
+                closing_tag: Tag { tag_type: "endfor", arguments: vec![], condition: None, span: Span::default() },
+                raw: false,
+            })],
+            branches: vec![],
+            closing_tag: Tag { tag_type: "endblock", arguments: vec![], condition: None, span: Span::default() },
+            raw: false,
+        }),
+    ])]
+    fn test_parsing_nested_blocks(#[case] input: &str, #[case] expected: Vec<Node>) {
+        let actual = Template::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual.nodes, &expected)
+    }
+
+    #[rstest]
+    #[case::unclosed("{% if a %}yes", "invalid if\nexpected `elif`, `else`, `endif`")]
+    #[case::mismatched(
+        "{% if a %}yes{% endfor %}",
+        "invalid if\nexpected `elif`, `else`, `endif`"
+    )]
+    #[case::wrong_intermediate(
+        "{% for a in b %}{% else %}{% endfor %}",
+        "invalid for\nexpected `empty`, `endfor`"
+    )]
+    #[case::unopened("yes{% endif %}", "invalid block\nexpected a matching opening tag")]
+    fn test_parsing_invalid_blocks(#[case] input: &str, #[case] expected: &str) {
+        let parsed = Template::parse.parse(new_input(input)).unwrap();
+        let errors: Vec<_> = parsed
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Raw(raw) => Some(raw.error.to_string()),
+                _ => None,
+            })
+            .collect();
+        // Later errors can follow on from the first one, like a closing tag left without its block
+        assert_eq!(errors[0], expected);
+    }
+
+    #[rstest]
+    #[case::stray_closing_tag(
+        "<div>a</span>b</div>\n<p>c</p>",
+        "<div>\n\ta</span>b\n</div>\n<p>\n\tc\n</p>\n"
+    )]
+    #[case::nested_stray_closing_tag(
+        "<section><div>a</span>b</div></section>",
+        "<section>\n\t<div>\n\t\ta</span>b\n\t</div>\n</section>\n"
+    )]
+    #[case::unclosed_element(
+        "<section><div>a</section>",
+        "<section>\n\t<div>\n\t\ta\n</section>\n"
+    )]
+    #[case::unclosed_block("<p>{% if a %}<b>x</b></p>", "<p>\n\t{% if a %}<b>x</b>\n</p>\n")]
+    #[case::unopened_block("a{% endif %}<i>b</i>", "a{% endif %}<i>b</i>\n")]
+    fn test_recovering_from_invalid_input(#[case] input: &str, #[case] expected: &str) {
+        let parsed = Template::parse_source(input).unwrap();
+        assert_eq!(parsed.errors(input).len(), 1);
+        assert_eq!(parsed.formatted(0, &FormatOptions::default()), expected);
+    }
+
+    #[rstest]
+    #[case::unit_after_number("{% spacer 10px %}", "invalid tag: expected `%}`, found `px`")]
+    #[case::size(
+        "{% thumbnail img 100x100 %}",
+        "invalid tag: expected `%}`, found `x100`"
+    )]
+    #[case::touching_arguments(
+        "{% icon \"user\"size %}",
+        "invalid tag: expected `%}`, found `size`"
+    )]
+    #[case::touching_strings("{% cycle \"a\"\"b\" %}", "invalid tag: expected `%}`, found `\"b\"`")]
+    #[case::variable("{{ 2px }}", "invalid variable: expected `}}`, found `px`")]
+    #[case::in_attribute(
+        "<div style=\"width: {{ 2px }}\">a</div>",
+        "invalid variable: expected `}}`, found `px`"
+    )]
+    fn test_keeping_unparseable_template_syntax(#[case] input: &str, #[case] expected: &str) {
+        let parsed = Template::parse_source(input).unwrap();
+        // An opening tag that can't be parsed leaves its closing tag without it, too
+        let errors = parsed.errors(input);
+        assert_eq!(errors[0].message(), expected);
+        assert_eq!(
+            parsed.formatted(0, &FormatOptions::default()),
+            format!("{input}\n")
+        );
+    }
+
+    #[rstest]
+    fn test_invalid_opening_tag_doesnt_report_its_end_tag() {
+        let input = "{% if a and %}\n\tb\n{% else %}\n\tc\n{% endif %}";
+        let parsed = Template::parse_source(input).unwrap();
+        let errors = parsed.errors(input);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message(),
+            "invalid condition: expected a value, found `%}`"
+        );
+    }
+
+    #[rstest]
+    fn test_sorting_attributes() {
+        let input =
+            "<a href=\"/\" class=\"a\">{% if a %}<b title=\"b\" id=\"b\"></b>{% endif %}</a>";
+        let mut parsed = Template::parse.parse(new_input(input)).unwrap();
+        parsed.sort_attributes(&AttributeOrder::conventional());
+
+        let expected =
+            "<a class=\"a\" href=\"/\">{% if a %}<b id=\"b\" title=\"b\"></b>{% endif %}</a>\n";
+        assert_eq!(parsed.formatted(0, &FormatOptions::default()), expected);
+    }
+
+    #[rstest]
+    fn test_normalizing_classes() {
+        let input = "<div class=\"b  a b\">{% if a %}<p CLASS=\"c\n\t\ta\" title=\"b  a\"></p>{% endif %}</div>";
+        let mut parsed = Template::parse.parse(new_input(input)).unwrap();
+        parsed.normalize_classes(&ClassOrder::Alphabetical);
+
+        let expected = "<div class=\"a b\">\n\t{% if a %}\n\t\t<p CLASS=\"a c\" title=\"b  a\"></p>\n\t{% endif %}\n</div>\n";
+        let formatted = parsed.formatted(0, &FormatOptions::default());
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse.parse(new_input(&formatted)).unwrap();
+        assert_eq!(
+            parsed.check_equivalent(&reparsed, &FormatOptions::default()),
+            Ok(())
+        );
+    }
+
+    #[rstest]
+    fn test_duplicate_attributes() {
+        let input = "<div>\n\t{% if a %}<input value=\"a\" value=\"b\">{% endif %}\n</div>";
+        let mut parsed = Template::parse.parse(new_input(input)).unwrap();
+
+        let diagnostics = parsed.duplicate_attributes();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "duplicate attribute `value` on `<input>`"
+        );
+        assert_eq!(diagnostics[0].span.range(), 34..43);
+        assert_eq!(diagnostics[0].span.line_col(input), (2, 29));
+
+        // Both values are kept unless they're explicitly removed
+        assert!(parsed
+            .formatted(0, &FormatOptions::default())
+            .contains("value=\"a\" value=\"b\""));
+        parsed.dedup_attributes();
+        assert!(parsed
+            .formatted(0, &FormatOptions::default())
+            .contains("<input value=\"a\" />"));
+        assert!(parsed.duplicate_attributes().is_empty());
+    }
 
     #[rstest]
-    #[case::empty_template("", Template { nodes: vec![] })]
-    #[case("text", Template { nodes: vec![
-        Node::Text("text"),
-    ] })]
-    #[case("{{text}}", Template { nodes: vec![
-        Node::Variable(VariableTag { tag_type: "text", filters: vec![] }),
-    ] })]
-    #[case("hello{{text}}world", Template { nodes: vec![
-        Node::Text("hello"),
-        Node::Variable(VariableTag { tag_type: "text", filters: vec![] }),
-        Node::Text("world"),
-    ] })]
-    #[case("hello{{text}}there{% thing %}world", Template { nodes: vec![
-        Node::Text("hello"),
-        Node::Variable(VariableTag { tag_type: "text", filters: vec![] }),
-        Node::Text("there"),
-        Node::Tag(Tag { tag_type: "thing", arguments: vec![] }),
-        Node::Text("world"),
-    ] })]
-    #[case("hello{{text}}there{# comment #}again{% thing %}world", Template { nodes: vec![
-        Node::Text("hello"),
-        Node::Variable(VariableTag { tag_type: "text", filters: vec![] }),
-        Node::Text("there"),
-        Node::Comment("comment"),
-        Node::Text("again"),
-        Node::Tag(Tag { tag_type: "thing", arguments: vec![] }),
-        Node::Text("world"),
-    ] })]
-    fn test_parsing_template(#[case] input: &str, #[case] expected: Template) {
-        let actual = Template::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+    #[case("{{ a|upper|money|price }}", vec!["price"])]
+    #[case("{% if a|length > 1 %}{% for x in a|unknown %}{% endfor %}{% endif %}", vec!["unknown"])]
+    #[case("<a href=\"{{ url|slug }}\">{{ a|default:b|markdown }}</a>", vec!["slug", "markdown"])]
+    fn test_unknown_filters(#[case] input: &str, #[case] expected: Vec<&str>) {
+        let parsed = Template::parse_source(input).unwrap();
+        let actual = parsed
+            .unknown_filters(&["money".to_string()])
+            .iter()
+            .map(|diagnostic| &input[diagnostic.span.range()])
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
+
+    #[rstest]
+    #[case("<div>\n    {% if a %}{{ a|b:\"c\" }}{% endif %}\n</div>")]
+    #[case("hello {{ name }}, welcome  to\n\n   <b>the site</b>")]
+    #[case("<a href='{%url \"home\"%}'>{%for x in y%}{{x}} {%empty%}none{%endfor%}</a>")]
+    #[case("<p>a</span>b</p>{% if a %}")]
+    #[case("<!doctype HTML>\n<html><![CDATA[ x ]]></html>")]
+    fn test_formatted_template_is_equivalent(#[case] input: &str) {
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &FormatOptions::default());
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert_eq!(
+            parsed.check_equivalent(&reparsed, &FormatOptions::default()),
+            Ok(())
+        );
+    }
+
+    #[rstest]
+    #[case::text("<p>a b</p>", "<p>a c</p>", 3..6)]
+    #[case::significant_space("{{ a }} {{ b }}", "{{ a }}{{ b }}", 7..8)]
+    #[case::attribute("<p id=\"a\">x</p>", "<p id=\"b\">x</p>", 0..15)]
+    #[case::tag_argument("{% url 'a' %}", "{% url 'b' %}", 0..13)]
+    #[case::filter("{{ a|b }}", "{{ a|c }}", 0..9)]
+    #[case::filter_argument("{{ a|b:1 }}", "{{ a|b:2 }}", 0..11)]
+    #[case::missing_node("<p>a</p><br>", "<p>a</p>", 8..12)]
+    #[case::extra_node("<p>a</p>", "<p>a</p><br>", 0..8)]
+    #[case::branch("{% if a %}b{% else %}c{% endif %}", "{% if a %}b{% else %}d{% endif %}", 21..22)]
+    #[case::preformatted_whitespace("<pre>a  b</pre>", "<pre>a b</pre>", 0..15)]
+    #[case::whitespace_around_inline_element("a<span>b</span>c", "a\n<span>b</span>\nc", 0..1)]
+    #[case::whitespace_around_comment("a<!-- b -->c", "a\n<!-- b -->\nc", 0..1)]
+    #[case::spaces_around_block_in_text(
+        "word{% if a %}x{% endif %}word",
+        "word {% if a %}x{% endif %} word",
+        0..4
+    )]
+    #[case::block_in_text_on_its_own_lines(
+        "<p>word{% if a %}x{% endif %}word</p>",
+        "<p>\n\tword\n\t{% if a %}\n\t\tx\n\t{% endif %}\n\tword\n</p>",
+        3..7
+    )]
+    #[case::space_within_block_in_inline_element(
+        "<b>{% if a %}x{% endif %}</b>",
+        "<b>{% if a %} x {% endif %}</b>",
+        13..14
+    )]
+    #[case::split_arguments("{% cycle \"a\"\"b\" %}", "{% cycle \"a\" \"b\" %}", 0..18)]
+    #[case::split_number("{% mytag 1-2 %}", "{% mytag 1 -2 %}", 0..15)]
+    #[case::number("{{ 1.5 }}", "{{ 1.50 }}", 0..9)]
+    fn test_detecting_changes(
+        #[case] input: &str,
+        #[case] formatted: &str,
+        #[case] span: std::ops::Range<usize>,
+    ) {
+        let parsed = Template::parse_source(input).unwrap();
+        let reparsed = Template::parse_source(formatted).unwrap();
+        let diagnostic = parsed
+            .check_equivalent(&reparsed, &FormatOptions::default())
+            .unwrap_err();
+        assert_eq!(diagnostic.span.range(), span);
+    }
+
+    #[rstest]
+    #[case::spacing("{%include\"a.html\"%}", "{% include \"a.html\" %}")]
+    #[case::operators("{{ a | default : 'b' }}", "{{ a|default:\"b\" }}")]
+    #[case::condition("{% if a==b and not c %}", "{% if a == b and not c %}")]
+    #[case::quotes("{% url 'say \"hi\"' %}", "{% url 'say \"hi\"' %}")]
+    fn test_normalizing_template_syntax(#[case] source: &str, #[case] formatted: &str) {
+        assert_eq!(
+            normalize_template_syntax(source),
+            normalize_template_syntax(formatted)
+        );
+    }
+
+    #[rstest]
+    #[case::split_arguments("{% cycle \"a\"\"b\" %}", "{% cycle \"a\" \"b\" %}")]
+    #[case::split_number("{% mytag 1-2 %}", "{% mytag 1 -2 %}")]
+    #[case::unit_as_filter("{% spacer 10px %}", "{% spacer 10|px %}")]
+    #[case::string_content("{{ a|b:\"c d\" }}", "{{ a|b:\"c  d\" }}")]
+    #[case::quote_within_string("{% url 'say \"hi\"' %}", "{% url \"say \"hi\"\" %}")]
+    fn test_normalizing_template_syntax_keeps_changes(
+        #[case] source: &str,
+        #[case] corrupted: &str,
+    ) {
+        assert_ne!(
+            normalize_template_syntax(source),
+            normalize_template_syntax(corrupted)
+        );
+    }
+
+    #[rstest]
+    #[case::css(WhitespaceSensitivity::Css, false)]
+    #[case::strict(WhitespaceSensitivity::Strict, false)]
+    #[case::ignore(WhitespaceSensitivity::Ignore, true)]
+    fn test_detecting_whitespace_changes_around_inline_elements(
+        #[case] whitespace_sensitivity: WhitespaceSensitivity,
+        #[case] equivalent: bool,
+    ) {
+        let options = FormatOptions {
+            whitespace_sensitivity,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source("a<span>b</span>c").unwrap();
+        let reparsed = Template::parse_source("a\n<span>b</span>\nc").unwrap();
+        assert_eq!(
+            parsed.check_equivalent(&reparsed, &options).is_ok(),
+            equivalent
+        );
+    }
+
+    #[rstest]
+    fn test_formatting_with_options() {
+        let input =
+            "<div class='a'>{% if a %}<br>\n\n\n{{ a }}<img src=\"it's.png\">{% endif %}</div>";
+        let options = FormatOptions {
+            indent_style: IndentStyle::Spaces(2),
+            quote_style: QuoteStyle::Single,
+            void_style: VoidStyle::Bare,
+            max_blank_lines: Some(1),
+            final_newline: false,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+
+        let expected = "<div class='a'>\n  {% if a %}\n    <br>\n\n    {{ a }}<img src=\"it's.png\">\n  {% endif %}\n</div>";
+        assert_eq!(parsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::preserve(
+        QuoteStyle::Preserve,
+        "{{ x|default:'say \"hi\"' }}{% url 'home' %}{% if a == \"b\" %}{% endif %}"
+    )]
+    #[case::prefer_double(
+        QuoteStyle::Double,
+        "{{ x|default:'say \"hi\"' }}{% url \"home\" %}{% if a == \"b\" %}{% endif %}"
+    )]
+    #[case::prefer_single(
+        QuoteStyle::Single,
+        "{{ x|default:'say \"hi\"' }}{% url 'home' %}{% if a == 'b' %}{% endif %}"
+    )]
+    fn test_formatting_template_quotes(
+        #[case] template_quote_style: QuoteStyle,
+        #[case] expected: &str,
+    ) {
+        let input = "{{ x|default:'say \"hi\"' }}{% url 'home' %}{% if a == \"b\" %}{% endif %}";
+        let options = FormatOptions {
+            template_quote_style,
+            final_newline: false,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options).replace('\n', "");
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert!(parsed.check_equivalent(&reparsed, &options).is_ok());
+    }
+
+    #[rstest]
+    #[case::single_line("<p>a {#note#} b</p>", false, "<p>\n\ta {# note #} b\n</p>\n")]
+    #[case::block(
+        "<div>{%comment \"why\"%}\n  <p>{{ a }}</p>\n{%endcomment%}</div>",
+        false,
+        "<div>\n\t{% comment \"why\" %}\n  <p>{{ a }}</p>\n{% endcomment %}\n</div>\n"
+    )]
+    #[case::converted(
+        "<div>\n{# a comment that is much too long #}\n</div>",
+        true,
+        "<div>\n\t{% comment %}\n\t\ta comment that is much too long\n\t{% endcomment %}\n</div>\n"
+    )]
+    fn test_formatting_comments(
+        #[case] input: &str,
+        #[case] long_comments_as_blocks: bool,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            line_width: 20,
+            long_comments_as_blocks,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options);
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert!(parsed.check_equivalent(&reparsed, &options).is_ok());
+    }
+
+    #[rstest]
+    #[case::pre(
+        "<div><pre>\n a  {{ b }}\n</pre></div>",
+        "<div>\n\t<pre>\n a  {{ b }}\n</pre>\n</div>\n"
+    )]
+    #[case::script(
+        "<body>\n<script>\n  if (a < b) {\n    c();\n  }\n</script>\n</body>",
+        "<body>\n\t<script>\n\t\tif (a < b) {\n\t\t  c();\n\t\t}\n\t</script>\n</body>\n"
+    )]
+    #[case::verbatim(
+        "<div>{% verbatim %}<p>{{ vue }}</p>{% endverbatim %}</div>",
+        "<div>\n\t{% verbatim %}<p>{{ vue }}</p>{% endverbatim %}\n</div>\n"
+    )]
+    fn test_formatting_raw_text(#[case] input: &str, #[case] expected: &str) {
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &FormatOptions::default());
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert!(parsed
+            .check_equivalent(&reparsed, &FormatOptions::default())
+            .is_ok());
+    }
+
+    #[rstest]
+    #[case::list(
+        "<ul><li>one<li>two</ul>",
+        "<ul>\n\t<li>\n\t\tone\n\t<li>\n\t\ttwo\n</ul>\n",
+        "<ul>\n\t<li>\n\t\tone\n\t</li>\n\t<li>\n\t\ttwo\n\t</li>\n</ul>\n"
+    )]
+    #[case::paragraphs(
+        "<p>one<p>two<div>three</div>",
+        "<p>\n\tone\n<p>\n\ttwo\n<div>\n\tthree\n</div>\n",
+        "<p>\n\tone\n</p>\n<p>\n\ttwo\n</p>\n<div>\n\tthree\n</div>\n"
+    )]
+    #[case::definitions(
+        "<dl><dt>a<dd>b<dt>c</dl>",
+        "<dl>\n\t<dt>\n\t\ta\n\t<dd>\n\t\tb\n\t<dt>\n\t\tc\n</dl>\n",
+        "<dl>\n\t<dt>\n\t\ta\n\t</dt>\n\t<dd>\n\t\tb\n\t</dd>\n\t<dt>\n\t\tc\n\t</dt>\n</dl>\n"
+    )]
+    #[case::table(
+        "<table><thead><tr><th>a<tbody><tr><td>b<td>c<tr><td>d</table>",
+        "<table>\n\t<thead>\n\t\t<tr>\n\t\t\t<th>\n\t\t\t\ta\n\t<tbody>\n\t\t<tr>\n\t\t\t<td>\n\t\t\t\tb\n\t\t\t<td>\n\t\t\t\tc\n\t\t<tr>\n\t\t\t<td>\n\t\t\t\td\n</table>\n",
+        "<table>\n\t<thead>\n\t\t<tr>\n\t\t\t<th>\n\t\t\t\ta\n\t\t\t</th>\n\t\t</tr>\n\t</thead>\n\t<tbody>\n\t\t<tr>\n\t\t\t<td>\n\t\t\t\tb\n\t\t\t</td>\n\t\t\t<td>\n\t\t\t\tc\n\t\t\t</td>\n\t\t</tr>\n\t\t<tr>\n\t\t\t<td>\n\t\t\t\td\n\t\t\t</td>\n\t\t</tr>\n\t</tbody>\n</table>\n"
+    )]
+    #[case::options(
+        "<select><option>a<option selected>b</select>",
+        "<select>\n\t<option>\n\t\ta\n\t<option selected>\n\t\tb\n</select>\n",
+        "<select>\n\t<option>\n\t\ta\n\t</option>\n\t<option selected>\n\t\tb\n\t</option>\n</select>\n"
+    )]
+    #[case::template_tags(
+        "<ul>{% for a in b %}<li>{{ a }}{% endfor %}</ul>",
+        "<ul>\n\t{% for a in b %}\n\t\t<li>\n\t\t\t{{ a }}\n\t{% endfor %}\n</ul>\n",
+        "<ul>\n\t{% for a in b %}\n\t\t<li>\n\t\t\t{{ a }}\n\t\t</li>\n\t{% endfor %}\n</ul>\n"
+    )]
+    fn test_formatting_implied_end_tags(
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] expected_with_end_tags: &str,
+    ) {
+        let parsed = Template::parse_source(input).unwrap();
+        let options = FormatOptions {
+            insert_implied_end_tags: true,
+            ..FormatOptions::default()
+        };
+
+        for (options, expected) in [
+            (FormatOptions::default(), expected),
+            (options, expected_with_end_tags),
+        ] {
+            let formatted = parsed.formatted(0, &options);
+            assert_eq!(formatted, expected);
+            let reparsed = Template::parse_source(&formatted).unwrap();
+            assert_eq!(parsed.check_equivalent(&reparsed, &options), Ok(()));
+            assert_eq!(reparsed.formatted(0, &options), expected);
+        }
+    }
+
+    #[rstest]
+    #[case::css(
+        WhitespaceSensitivity::Css,
+        "<p>\n\tClick <a\n\t\thref=\"/\"\n\t>here</a> now\n</p>\n<div>\n\t<span>a</span>\n</div>\n"
+    )]
+    #[case::strict(
+        WhitespaceSensitivity::Strict,
+        "<p>Click <a\n\thref=\"/\"\n>here</a> now</p><div><span>a</span></div>\n"
+    )]
+    #[case::ignore(
+        WhitespaceSensitivity::Ignore,
+        "<p>\n\tClick\n\t<a href=\"/\">\n\t\there\n\t</a>\n\tnow\n</p>\n<div>\n\t<span>a</span>\n</div>\n"
+    )]
+    fn test_formatting_inline_elements(
+        #[case] whitespace_sensitivity: WhitespaceSensitivity,
+        #[case] expected: &str,
+    ) {
+        let input = "<p>Click <a href=\"/\">here</a> now</p><div><span>a</span></div>";
+        let options = FormatOptions {
+            whitespace_sensitivity,
+            line_width: 19,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options);
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert_eq!(parsed.check_equivalent(&reparsed, &options), Ok(()));
+        assert_eq!(reparsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::block_in_inline_element(
+        "<p>Price:<span>{% if a %}1{% else %}2{% endif %}</span>.</p>",
+        "<p>\n\tPrice:<span>{% if a %}1{% else %}2{% endif %}</span>.\n</p>\n"
+    )]
+    #[case::block_in_text(
+        "<p>word{% if a %}x{% endif %}word</p>",
+        "<p>\n\tword{% if a %}x{% endif %}word\n</p>\n"
+    )]
+    #[case::text_and_block_element_in_inline_element(
+        "<p><span>a<div>x</div>b</span></p>",
+        "<p>\n\t<span>a\n\t\t<div>\n\t\t\tx\n\t\t</div>\n\t\tb</span>\n</p>\n"
+    )]
+    #[case::block_element_in_inline_element(
+        "<p>Click<a href=\"/\"><div>x</div></a>now</p>",
+        "<p>\n\tClick<a href=\"/\">\n\t\t<div>\n\t\t\tx\n\t\t</div>\n\t</a>now\n</p>\n"
+    )]
+    #[case::comment("<p>a<!-- c -->b</p>", "<p>\n\ta<!-- c -->b\n</p>\n")]
+    fn test_formatting_inline_nodes_that_dont_fit_on_a_line(
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions::default();
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options);
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert_eq!(parsed.check_equivalent(&reparsed, &options), Ok(()));
+        assert_eq!(reparsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    fn test_wrapping_text_around_template_syntax() {
+        let input =
+            "<p>Over the {{ a|default:\"lazy dog\" }} and {% url \"x\" a b %} <!-- c d --> c</p>";
+        let expected = "<p>\n\tOver the\n\t{{ a|default:\"lazy dog\" }}\n\tand {% url \"x\" a b %}\n\t<!-- c d --> c\n</p>\n";
+        let options = FormatOptions {
+            line_width: 27,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        assert_eq!(parsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::css(
+        WhitespaceSensitivity::Css,
+        "<p>\n\tThe quick brown fox jumps over the lazy\n\tdog <a href=\"/\">and  keeps running</a>\n\tuntil night.\n</p>\n"
+    )]
+    #[case::strict(
+        WhitespaceSensitivity::Strict,
+        "<p>The quick brown fox jumps over the lazy dog <a\n\thref=\"/\"\n>and  keeps running</a> until night.</p>\n"
+    )]
+    #[case::ignore(
+        WhitespaceSensitivity::Ignore,
+        "<p>\n\tThe quick brown fox jumps over the lazy\n\tdog <a href=\"/\">and  keeps running</a>\n\tuntil night.\n</p>\n"
+    )]
+    fn test_wrapping_long_text(
+        #[case] whitespace_sensitivity: WhitespaceSensitivity,
+        #[case] expected: &str,
+    ) {
+        let input = "<p>The quick brown fox jumps over the lazy dog <a href=\"/\">and  keeps running</a> until night.</p>";
+        let options = FormatOptions {
+            whitespace_sensitivity,
+            line_width: 43,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options);
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert_eq!(parsed.check_equivalent(&reparsed, &options), Ok(()));
+        assert_eq!(reparsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    #[case::new_line(
+        BracketPlacement::NewLine,
+        "<head>\n\t<link\n\t\thref=\"{% static 'a.css' %}\"\n\t\trel=\"stylesheet\"\n\t\ttype=\"text/css\"\n\t/>\n</head>\n<p>\n\tSearch <input\n\t\ttype=\"text\"\n\t\tname=\"q\"\n\t\tplaceholder=\"Search\"\n\t/> now\n</p>\n<div class=\"a\">\n\tb\n</div>\n"
+    )]
+    #[case::same_line(
+        BracketPlacement::SameLine,
+        "<head>\n\t<link\n\t\thref=\"{% static 'a.css' %}\"\n\t\trel=\"stylesheet\"\n\t\ttype=\"text/css\" />\n</head>\n<p>\n\tSearch <input\n\t\ttype=\"text\"\n\t\tname=\"q\"\n\t\tplaceholder=\"Search\" /> now\n</p>\n<div class=\"a\">\n\tb\n</div>\n"
+    )]
+    fn test_formatting_long_opening_tags(
+        #[case] bracket_placement: BracketPlacement,
+        #[case] expected: &str,
+    ) {
+        let input = "<head><link href='{%static \"a.css\"%}'\n      rel=\"stylesheet\"\n      type=\"text/css\"/></head>\n<p>Search <input type=\"text\" name=\"q\" placeholder=\"Search\"> now</p><div class=\"a\">b</div>";
+        let options = FormatOptions {
+            line_width: 40,
+            bracket_placement,
+            template_quote_style: QuoteStyle::Single,
+            ..FormatOptions::default()
+        };
+        let parsed = Template::parse_source(input).unwrap();
+        let formatted = parsed.formatted(0, &options);
+
+        assert_eq!(formatted, expected);
+        let reparsed = Template::parse_source(&formatted).unwrap();
+        assert_eq!(parsed.check_equivalent(&reparsed, &options), Ok(()));
+        assert_eq!(reparsed.formatted(0, &options), expected);
+    }
+
+    #[rstest]
+    fn test_formatting_conditions() {
+        let input = "{% if x==1 and not  y %}a{% elif z|length>2 or w not in v %}b{% endif %}";
+        let expected = "{% if x == 1 and not y %}\n\ta\n{% elif z|length > 2 or w not in v %}\n\tb\n{% endif %}\n";
+        let parsed = Template::parse_source(input).unwrap();
+        assert_eq!(parsed.formatted(0, &FormatOptions::default()), expected);
+    }
+
+    #[rstest]
+    fn test_parsing_custom_tags() {
+        let tags = TagRegistry::with_custom_tags(&[
+            CustomTag {
+                name: "component".to_string(),
+                end_tag: None,
+                intermediate_tags: vec!["slot".to_string()],
+                raw: false,
+            },
+            CustomTag {
+                name: "markdown".to_string(),
+                end_tag: None,
+                intermediate_tags: vec![],
+                raw: true,
+            },
+        ]);
+        let input = "{% component %}a{% slot %}b{% endcomponent %}{% markdown %}*  {{ c }}{% endmarkdown %}";
+        let parsed = Template::parse_source_with_tags(input, &tags).unwrap();
+
+        let expected = "{% component %}\n\ta\n{% slot %}\n\tb\n{% endcomponent %}\n{% markdown %}*  {{ c }}{% endmarkdown %}\n";
+        assert_eq!(parsed.formatted(0, &FormatOptions::default()), expected);
+
+        // Without the registry they're just standalone tags
+        let unregistered = Template::parse_source(input).unwrap();
+        assert!(!unregistered
+            .nodes
+            .iter()
+            .any(|node| matches!(node, Node::Block(_))));
     }
 
     #[rstest]
     fn test_formatting_multiple_times_doesnt_change_output() {
-        let expected = "<div></div>";
-        let parsed = Template::parse.parse(expected).unwrap();
+        let expected = "<div>\n\t{% if a %}\n\t\t{{ a }}\n\t{% endif %}\n</div>\n";
+        let parsed = Template::parse.parse(new_input(expected)).unwrap();
 
-        let first_format = parsed.formatted(0);
+        let first_format = parsed.formatted(0, &FormatOptions::default());
         assert_eq!(expected, first_format);
 
-        let second_parse = Template::parse.parse(first_format.as_str()).unwrap();
+        let second_parse = Template::parse
+            .parse(new_input(first_format.as_str()))
+            .unwrap();
 
-        let second_format = second_parse.formatted(0);
+        let second_format = second_parse.formatted(0, &FormatOptions::default());
         assert_eq!(expected, second_format);
     }
 }
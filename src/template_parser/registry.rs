@@ -0,0 +1,309 @@
+use crate::config::CustomTag;
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex},
+};
+
+/// A block tag that Django pairs with an end tag, like `{% if %}…{% endif %}`.
+///
+/// Names are `&'static str` as parse errors refer to them, so the ones from config files are
+/// leaked with [`intern`], once per distinct name no matter how often the registry is rebuilt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDefinition {
+    pub name: &'static str,
+    pub end_tag: &'static str,
+    /// Tags that split the block into branches, like `{% else %}` in an `{% if %}` block
+    pub intermediate_tags: &'static [&'static str],
+    /// Whether everything up to the end tag is kept exactly as it was written rather than parsed,
+    /// like in `{% verbatim %}`
+    pub raw: bool,
+}
+
+impl BlockDefinition {
+    /// Whether the tag ends or splits this block.
+    pub fn is_boundary(&self, tag_type: &str) -> bool {
+        self.end_tag == tag_type || self.intermediate_tags.contains(&tag_type)
+    }
+}
+
+/// Django's built-in block tags, including the ones from the `i18n`, `l10n`, `tz` and `cache`
+/// libraries. The body of `{% blocktrans %}` is the message to translate, which has to stay as it
+/// was to match its translations.
+const BLOCK_DEFINITIONS: &[BlockDefinition] = &[
+    BlockDefinition {
+        name: "autoescape",
+        end_tag: "endautoescape",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "block",
+        end_tag: "endblock",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "blocktrans",
+        end_tag: "endblocktrans",
+        intermediate_tags: &["plural"],
+        raw: true,
+    },
+    BlockDefinition {
+        name: "blocktranslate",
+        end_tag: "endblocktranslate",
+        intermediate_tags: &["plural"],
+        raw: true,
+    },
+    BlockDefinition {
+        name: "cache",
+        end_tag: "endcache",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "filter",
+        end_tag: "endfilter",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "for",
+        end_tag: "endfor",
+        intermediate_tags: &["empty"],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "if",
+        end_tag: "endif",
+        intermediate_tags: &["elif", "else"],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "ifchanged",
+        end_tag: "endifchanged",
+        intermediate_tags: &["else"],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "language",
+        end_tag: "endlanguage",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "localize",
+        end_tag: "endlocalize",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "localtime",
+        end_tag: "endlocaltime",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "spaceless",
+        end_tag: "endspaceless",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "timezone",
+        end_tag: "endtimezone",
+        intermediate_tags: &[],
+        raw: false,
+    },
+    BlockDefinition {
+        name: "verbatim",
+        end_tag: "endverbatim",
+        intermediate_tags: &[],
+        raw: true,
+    },
+    BlockDefinition {
+        name: "with",
+        end_tag: "endwith",
+        intermediate_tags: &[],
+        raw: false,
+    },
+];
+
+/// The block tags that templates can use, which decides how the tags are paired up into blocks
+/// and what gets indented. Starts off with Django's built-in tags.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagRegistry {
+    definitions: Vec<BlockDefinition>,
+}
+
+impl Default for TagRegistry {
+    fn default() -> Self {
+        Self {
+            definitions: BLOCK_DEFINITIONS.to_vec(),
+        }
+    }
+}
+
+static BUILTIN: LazyLock<TagRegistry> = LazyLock::new(TagRegistry::default);
+
+impl TagRegistry {
+    /// Django's built-in tags, for parsing templates that don't use any others.
+    pub fn builtin() -> &'static Self {
+        &BUILTIN
+    }
+
+    /// The built-in tags along with the custom ones from a config file.
+    pub fn with_custom_tags(custom_tags: &[CustomTag]) -> Self {
+        let mut registry = Self::default();
+        for tag in custom_tags {
+            registry.register(tag.into());
+        }
+        registry
+    }
+
+    /// Adds a block tag, replacing any existing one with the same name.
+    pub fn register(&mut self, definition: BlockDefinition) {
+        self.definitions
+            .retain(|existing| existing.name != definition.name);
+        self.definitions.push(definition);
+    }
+
+    /// Looks up the block definition for an opening tag name like `if`.
+    pub fn find(&self, tag_type: &str) -> Option<&BlockDefinition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == tag_type)
+    }
+
+    /// Whether the tag closes or splits some block, meaning it can't appear on its own.
+    pub fn is_boundary(&self, tag_type: &str) -> bool {
+        self.definitions
+            .iter()
+            .any(|definition| definition.is_boundary(tag_type))
+    }
+}
+
+impl From<&CustomTag> for BlockDefinition {
+    fn from(tag: &CustomTag) -> Self {
+        let end_tag = match &tag.end_tag {
+            Some(end_tag) => intern(end_tag),
+            None => intern(&format!("end{}", tag.name)),
+        };
+        Self {
+            name: intern(&tag.name),
+            end_tag,
+            intermediate_tags: intern_all(&tag.intermediate_tags),
+            raw: tag.raw,
+        }
+    }
+}
+
+/// Makes a tag name live for the rest of the program, leaking each distinct name only once.
+pub fn intern(name: &str) -> &'static str {
+    static NAMES: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Mutex::default);
+
+    let mut names = NAMES.lock().unwrap();
+    if let Some(interned) = names.get(name) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(name.into());
+    names.insert(interned);
+    interned
+}
+
+/// Like [`intern`], for a list of tag names.
+pub fn intern_all(names: &[String]) -> &'static [&'static str] {
+    static LISTS: LazyLock<Mutex<HashSet<&'static [&'static str]>>> = LazyLock::new(Mutex::default);
+
+    let names = names.iter().map(|name| intern(name)).collect::<Vec<_>>();
+    let mut lists = LISTS.lock().unwrap();
+    if let Some(interned) = lists.get(names.as_slice()) {
+        return interned;
+    }
+    let interned: &'static [&'static str] = Box::leak(names.into_boxed_slice());
+    lists.insert(interned);
+    interned
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("if", Some("endif"))]
+    #[case("for", Some("endfor"))]
+    #[case("block", Some("endblock"))]
+    #[case("endif", None)]
+    #[case("load", None)]
+    fn test_finding_block_definition(#[case] tag_type: &str, #[case] expected: Option<&str>) {
+        let actual = TagRegistry::builtin()
+            .find(tag_type)
+            .map(|definition| definition.end_tag);
+        assert_eq!(actual, expected)
+    }
+
+    #[rstest]
+    #[case("endif", true)]
+    #[case("else", true)]
+    #[case("empty", true)]
+    #[case("if", false)]
+    #[case("endcustom", false)]
+    fn test_boundary_tags(#[case] tag_type: &str, #[case] expected: bool) {
+        assert_eq!(TagRegistry::builtin().is_boundary(tag_type), expected)
+    }
+
+    #[rstest]
+    fn test_registering_custom_tags() {
+        let registry = TagRegistry::with_custom_tags(&[
+            CustomTag {
+                name: "component".to_string(),
+                end_tag: None,
+                intermediate_tags: vec!["slot".to_string()],
+                raw: false,
+            },
+            CustomTag {
+                name: "cache".to_string(),
+                end_tag: Some("stopcache".to_string()),
+                intermediate_tags: vec![],
+                raw: true,
+            },
+        ]);
+
+        assert_eq!(
+            registry.find("component"),
+            Some(&BlockDefinition {
+                name: "component",
+                end_tag: "endcomponent",
+                intermediate_tags: &["slot"],
+                raw: false,
+            })
+        );
+        assert!(registry.is_boundary("slot"));
+        assert_eq!(
+            registry.find("cache").map(|cache| cache.end_tag),
+            Some("stopcache")
+        );
+        assert!(!registry.is_boundary("endcache"));
+        assert!(TagRegistry::builtin().find("component").is_none());
+    }
+
+    #[rstest]
+    fn test_registering_again_reuses_names() {
+        let tag = CustomTag {
+            name: "tabs".to_string(),
+            end_tag: None,
+            intermediate_tags: vec!["tab".to_string(), "pane".to_string()],
+            raw: false,
+        };
+        let first = BlockDefinition::from(&tag);
+        let second = BlockDefinition::from(&tag);
+        assert!(std::ptr::eq(first.name, second.name));
+        assert!(std::ptr::eq(first.end_tag, second.end_tag));
+        assert!(std::ptr::eq(
+            first.intermediate_tags,
+            second.intermediate_tags
+        ));
+    }
+}
@@ -1,60 +1,111 @@
 use super::argument::TagArgument;
+use super::expression::{parse_condition, Expression};
 use super::variable::parse_variable;
-use crate::formatting::Formatable;
-use winnow::ascii::multispace1;
-use winnow::combinator::separated;
-use winnow::{ascii::multispace0, combinator::delimited, error::ParserError, PResult, Parser};
+use crate::doc::Doc;
+use crate::error::cut_unparsed_token;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input, Span};
+use winnow::combinator::repeat;
+use winnow::{
+    ascii::{multispace0, multispace1},
+    combinator::{delimited, opt, preceded},
+    error::ParserError,
+    stream::Stream,
+    PResult, Parser,
+};
+
+/// The tags that take a condition rather than a list of arguments.
+const CONDITION_TAGS: &[&str] = &["if", "elif"];
 
 /// A tag in a template. Can either be a simple tag (`{% my_tag %}`) or a tag with arguments
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Tag<'i> {
     pub tag_type: &'i str,
     pub arguments: Vec<TagArgument<'i>>,
+    /// The condition of an `{% if %}` or `{% elif %}`, which takes the place of its arguments
+    pub condition: Option<Box<Expression<'i>>>,
+    pub span: Span,
 }
 
-impl<'i> PartialEq for Tag<'i> {
-    fn eq(&self, other: &Self) -> bool {
+impl Equivalent for Tag<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
         self.tag_type == other.tag_type
+            && self.arguments.is_equivalent(&other.arguments)
+            && self.condition.is_equivalent(&other.condition)
     }
 }
 
 impl<'i> Tag<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let (tag_type, arguments) = generic_tag((
-            parse_variable,
-            separated(
-                0..,
-                delimited(multispace0, TagArgument::parse, multispace0),
-                multispace1,
-            ),
-        ))
-        .parse_next(input)?;
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let start = input.checkpoint();
+        let ((tag_type, arguments, condition), span) = generic_tag(|input: &mut Input<'i>| {
+            let tag_type = parse_variable.parse_next(input)?;
+            if CONDITION_TAGS.contains(&tag_type) {
+                let condition = parse_condition.parse_next(input)?;
+                return Ok((tag_type, vec![], Some(Box::new(condition))));
+            }
+            // Only a loop can unpack into several variables, as other tags take commas literally
+            let unpacked = match tag_type {
+                "for" => {
+                    opt(preceded(multispace0, TagArgument::parse_unpacked)).parse_next(input)?
+                }
+                _ => None,
+            };
+            let first = match unpacked {
+                Some(unpacked) => Some(unpacked),
+                None => opt(preceded(multispace0, TagArgument::parse)).parse_next(input)?,
+            };
+            let Some(first) = first else {
+                return Ok((tag_type, vec![], None));
+            };
+            // The first argument can touch the tag's name, like in `{%include"a.html"%}`, but the
+            // rest are split at whitespace like Django does, so ones that touch like `"a""b"`
+            // aren't a tag that can be parsed
+            let mut arguments = vec![first];
+            let rest: Vec<_> =
+                repeat(0.., preceded(multispace1, TagArgument::parse)).parse_next(input)?;
+            arguments.extend(rest);
+            Ok((tag_type, arguments, None))
+        })
+        .with_span()
+        .parse_next(input)
+        .map_err(|error| cut_unparsed_token(input, &start, "tag", "%}", error))?;
 
         let tag = Self {
             tag_type,
             arguments,
+            condition,
+            span: span.into(),
         };
         Ok(tag)
     }
 }
 
 impl<'i> Formatable for Tag<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        let mut formatted = String::new();
-        formatted.push_str("{% ");
-        formatted.push_str(self.tag_type);
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        // Django tags can't span lines, so there's nowhere to break them
+        let mut docs = vec![Doc::text("{% "), Doc::text(self.tag_type)];
         for argument in &self.arguments {
-            formatted.push(' ');
-            formatted.push_str(&argument.formatted(0));
+            docs.push(Doc::text(" "));
+            docs.push(argument.to_doc(options));
+        }
+        if let Some(condition) = &self.condition {
+            docs.push(Doc::text(" "));
+            docs.push(condition.to_doc(options));
         }
-        formatted.push_str(" %}");
-        formatted
+        docs.push(Doc::text(" %}"));
+        Doc::concat(docs)
     }
 }
 
-pub fn generic_tag<'i, O, E>(parser: impl Parser<&'i str, O, E>) -> impl Parser<&'i str, O, E>
+/// Parses just the start of a tag up to its type, like the `if` in `{% if a %}`.
+pub fn parse_tag_type<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
+    preceded(("{%", multispace0), parse_variable).parse_next(input)
+}
+
+pub fn generic_tag<'i, O, E>(parser: impl Parser<Input<'i>, O, E>) -> impl Parser<Input<'i>, O, E>
 where
-    E: ParserError<&'i str>,
+    E: ParserError<Input<'i>>,
 {
     delimited(("{%", multispace0), parser, (multispace0, "%}"))
 }
@@ -64,28 +115,90 @@ mod tests {
     use pretty_assertions::assert_eq;
     use rstest::rstest;
 
-    use crate::template_parser::{argument::TagArgumentValue, text::SingleLineTextString};
+    use crate::template_parser::{
+        argument::{ArgumentKind, TagArgumentValue},
+        text::SingleLineTextString,
+    };
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case::no_argument("{%my_tag%}", Tag {
-        tag_type: "my_tag", arguments: vec![]
+        tag_type: "my_tag", arguments: vec![],
+        condition: None,
+        span: Span::default(),
     })]
     #[case::no_argument_with_spaces("{% my_tag %}", Tag {
-        tag_type: "my_tag", arguments: vec![]
+        tag_type: "my_tag", arguments: vec![],
+        condition: None,
+        span: Span::default(),
     })]
     #[case::single_argument_with_spaces("{% my_tag \"my_arg\" %}", Tag {
         tag_type: "my_tag", arguments: vec![TagArgument {
+            kind: ArgumentKind::Positional,
             value: TagArgumentValue::Text(SingleLineTextString {
                 value: "my_arg",
                 startquote_char: '"',
             }),
             filters: vec![],
-        }]
+            span: Span::default(),
+        }],
+        condition: None,
+        span: Span::default(),
+    })]
+    #[case::multiple_arguments("{% for x in y %}", Tag {
+        tag_type: "for", arguments: vec![
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("x"), filters: vec![], span: Span::default() },
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("in"), filters: vec![], span: Span::default() },
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("y"), filters: vec![], span: Span::default() },
+        ],
+        condition: None,
+        span: Span::default(),
+    })]
+    #[case::unpacking("{% for k,v in d %}", Tag {
+        tag_type: "for", arguments: vec![
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Unpacked(vec!["k", "v"]), filters: vec![], span: Span::default() },
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("in"), filters: vec![], span: Span::default() },
+            TagArgument { kind: ArgumentKind::Positional, value: TagArgumentValue::Variable("d"), filters: vec![], span: Span::default() },
+        ],
+        condition: None,
+        span: Span::default(),
     })]
     fn test_tag_parses_successfully(#[case] input: &str, #[case] expected: Tag) {
-        let actual = Tag::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
+    }
+
+    #[rstest]
+    #[case(
+        "{% url 'detail' pk=obj.pk as link %}",
+        "{% url 'detail' pk=obj.pk as link %}"
+    )]
+    #[case(
+        "{% include \"x.html\" with a=b only %}",
+        "{% include \"x.html\" with a=b only %}"
+    )]
+    #[case("{%with total = items|length%}", "{% with total=items|length %}")]
+    #[case("{%include\"x.html\"%}", "{% include \"x.html\" %}")]
+    #[case(
+        "{% cycle 'odd' 'even'  as  row silent %}",
+        "{% cycle 'odd' 'even' as row silent %}"
+    )]
+    #[case(
+        "{% blocktranslate count counter=list|length %}",
+        "{% blocktranslate count counter=list|length %}"
+    )]
+    fn test_formatting_tag(#[case] input: &str, #[case] expected: &str) {
+        let actual = Tag::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &FormatOptions::default()), expected)
+    }
+
+    #[rstest]
+    #[case::quoted_strings("{% cycle \"a\"\"b\" %}")]
+    #[case::number_and_negative_number("{% mytag 1-2 %}")]
+    #[case::string_and_variable("{% icon \"user\"size %}")]
+    fn test_tag_with_touching_arguments_fails(#[case] input: &str) {
+        assert!(Tag::parse.parse(new_input(input)).is_err());
     }
 }
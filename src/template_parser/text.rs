@@ -1,30 +1,41 @@
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::Input;
 use winnow::combinator::delimited;
 use winnow::error::ParserError;
 use winnow::token::take_while;
 use winnow::{
-    combinator::{alt, rest},
+    combinator::{alt, repeat, rest},
     error::{ErrMode, ErrorKind},
     stream::AsChar,
-    token::take_until,
+    token::{any, take_until},
     PResult, Parser,
 };
 
 /// Parses all non-template syntax text
-pub fn parse_text<'i>(input: &mut &'i str) -> PResult<&'i str> {
+pub fn parse_text<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
     let valid_token_starts = ("{%", "{{", "{#");
     alt((take_until(0.., valid_token_starts), rest)).parse_next(input)
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct SingleLineTextString<'i> {
     pub value: &'i str,
     pub startquote_char: char,
 }
 
+/// Strings are the same whichever quote they're in, unless it's escaped within them.
+impl PartialEq for SingleLineTextString<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+            && (self.startquote_char == other.startquote_char || !self.value.contains('\\'))
+    }
+}
+
 impl<'i> SingleLineTextString<'i> {
-    /// Parses a single line text string e.g. `'my_text'` or `"my_text"`
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
+    /// Parses a single line text string e.g. `'my_text'` or `"my_text"`. The quote it's wrapped
+    /// in can appear within it when escaped with a backslash, like `"say \"hi\""`.
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
         let starts_with_single_quote = input.starts_with('\'');
         let starts_with_double_quote = input.starts_with('"');
 
@@ -36,9 +47,17 @@ impl<'i> SingleLineTextString<'i> {
 
         let value = delimited(
             startquote_char,
-            take_while(0.., move |c: char| {
-                c.is_ascii() && c != startquote_char && !c.is_newline()
-            }),
+            repeat::<_, _, (), _, _>(
+                0..,
+                alt((
+                    ('\\', any.verify(|c: &char| !c.is_newline())).void(),
+                    take_while(1.., move |c: char| {
+                        c != startquote_char && c != '\\' && !c.is_newline()
+                    })
+                    .void(),
+                )),
+            )
+            .take(),
             startquote_char,
         )
         .parse_next(input)?;
@@ -49,20 +68,29 @@ impl<'i> SingleLineTextString<'i> {
         };
         Ok(tag)
     }
+
+    /// The quote to write the string in. Strings with escapes keep their quote, as changing it
+    /// would mean changing what's escaped.
+    fn quote_char(&self, options: &FormatOptions) -> char {
+        if self.value.contains('\\') {
+            return self.startquote_char;
+        }
+        options
+            .template_quote_style
+            .quote_for(self.value, Some(self.startquote_char))
+    }
 }
 
 impl<'i> Formatable for SingleLineTextString<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        format!(
-            "{}{}{}",
-            self.startquote_char, self.value, self.startquote_char
-        )
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let quote = self.quote_char(options);
+        Doc::text(format!("{quote}{}{quote}", self.value))
     }
 }
 
 impl Formatable for &str {
-    fn formatted(&self, _indent_level: usize) -> String {
-        self.to_string()
+    fn to_doc<'a>(&'a self, _options: &'a FormatOptions) -> Doc<'a> {
+        Doc::text(*self)
     }
 }
 
@@ -72,6 +100,8 @@ mod tests {
     use rstest::rstest;
 
     use super::*;
+    use crate::formatting::QuoteStyle;
+    use crate::span::new_input;
 
     #[rstest]
     #[case::take_all_text("\n", "\n", "")]
@@ -86,12 +116,12 @@ mod tests {
         #[case] expected_extracted: &str,
         #[case] expected_remaining: &str,
     ) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         let actual = parse_text.parse_next(&mut input).unwrap();
 
         assert_eq!(actual, expected_extracted);
-        assert_eq!(input, expected_remaining);
+        assert_eq!(**input, expected_remaining);
     }
 
     #[rstest]
@@ -99,16 +129,40 @@ mod tests {
     #[case("'my_text'", SingleLineTextString{ value: "my_text", startquote_char: '\'' }, "")]
     #[case("\"my_text's\"", SingleLineTextString{ value: "my_text's", startquote_char: '\"' }, "")]
     #[case("\"my_text's\"thing", SingleLineTextString{ value: "my_text's", startquote_char: '\"' }, "thing")]
+    #[case("'it\\'s'", SingleLineTextString{ value: "it\\'s", startquote_char: '\'' }, "")]
+    #[case("\"a\\\\\" b", SingleLineTextString{ value: "a\\\\", startquote_char: '\"' }, " b")]
+    #[case("\"Café\"", SingleLineTextString{ value: "Café", startquote_char: '\"' }, "")]
     fn test_parsing_single_line_text_string(
         #[case] input: &str,
         #[case] expected_extracted: SingleLineTextString,
         #[case] expected_remaining: &str,
     ) {
-        let mut input = input;
+        let mut input = new_input(input);
 
         let actual = SingleLineTextString::parse.parse_next(&mut input).unwrap();
 
         assert_eq!(actual, expected_extracted);
-        assert_eq!(input, expected_remaining);
+        assert_eq!(**input, expected_remaining);
+    }
+
+    #[rstest]
+    #[case::preserve_double("\"a\"", QuoteStyle::Preserve, "\"a\"")]
+    #[case::preserve_single("'a'", QuoteStyle::Preserve, "'a'")]
+    #[case::prefer_double("'a'", QuoteStyle::Double, "\"a\"")]
+    #[case::prefer_single("\"a\"", QuoteStyle::Single, "'a'")]
+    #[case::avoid_inner_quote("'say \"hi\"'", QuoteStyle::Double, "'say \"hi\"'")]
+    #[case::avoid_inner_apostrophe("\"it's\"", QuoteStyle::Single, "\"it's\"")]
+    #[case::keep_escaped("'it\\'s'", QuoteStyle::Double, "'it\\'s'")]
+    fn test_formatting_single_line_text_string(
+        #[case] input: &str,
+        #[case] template_quote_style: QuoteStyle,
+        #[case] expected: &str,
+    ) {
+        let options = FormatOptions {
+            template_quote_style,
+            ..FormatOptions::default()
+        };
+        let actual = SingleLineTextString::parse.parse(new_input(input)).unwrap();
+        assert_eq!(actual.formatted(0, &options), expected);
     }
 }
@@ -1,17 +1,17 @@
+use super::argument::TagArgumentValue;
 use super::filter::{parse_filter_chain, Filter};
-use crate::formatting::Formatable;
+use crate::doc::Doc;
+use crate::error::cut_unparsed_token;
+use crate::formatting::{FormatOptions, Formatable};
+use crate::span::{Equivalent, Input, Span};
 use winnow::combinator::peek;
+use winnow::stream::Stream;
 use winnow::token::take;
-use winnow::{
-    ascii::multispace0,
-    combinator::{delimited, opt},
-    token::take_while,
-    PResult, Parser,
-};
+use winnow::{ascii::multispace0, combinator::delimited, token::take_while, PResult, Parser};
 
 /// Parses a variable name, which can contain alphanumeric characters and underscores but must start with an
 /// alphabetic character or underscore.
-pub fn parse_variable<'i>(input: &mut &'i str) -> PResult<&'i str> {
+pub fn parse_variable<'i>(input: &mut Input<'i>) -> PResult<&'i str> {
     let mut rest_chars = take_while(0.., |c: char| c.is_alphanumeric() || c == '_' || c == '.');
 
     peek(take(1usize))
@@ -21,48 +21,44 @@ pub fn parse_variable<'i>(input: &mut &'i str) -> PResult<&'i str> {
     rest_chars.parse_next(input)
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct VariableTag<'i> {
-    pub tag_type: &'i str,
+    /// What the filters are applied to, usually a variable but possibly a literal
+    pub value: TagArgumentValue<'i>,
     pub filters: Vec<Filter<'i>>,
+    pub span: Span,
 }
 
-impl<'i> Formatable for VariableTag<'i> {
-    fn formatted(&self, _indent_level: usize) -> String {
-        let mut formatted = String::new();
-        formatted.push_str("{{ ");
-        formatted.push_str(self.tag_type);
-        for filter in &self.filters {
-            formatted.push_str(&filter.formatted(0));
-        }
-        formatted.push_str(" }}");
-        formatted
+impl Equivalent for VariableTag<'_> {
+    fn is_equivalent(&self, other: &Self) -> bool {
+        self.value == other.value && self.filters.is_equivalent(&other.filters)
     }
 }
 
-impl<'i> PartialEq for VariableTag<'i> {
-    fn eq(&self, other: &Self) -> bool {
-        self.tag_type == other.tag_type
+impl<'i> Formatable for VariableTag<'i> {
+    fn to_doc<'a>(&'a self, options: &'a FormatOptions) -> Doc<'a> {
+        let mut docs = vec![Doc::text("{{ "), self.value.to_doc(options)];
+        docs.extend(self.filters.iter().map(|filter| filter.to_doc(options)));
+        docs.push(Doc::text(" }}"));
+        Doc::concat(docs)
     }
 }
 
 impl<'i> VariableTag<'i> {
-    pub fn parse(input: &mut &'i str) -> PResult<Self> {
-        let tag = delimited(
+    pub fn parse(input: &mut Input<'i>) -> PResult<Self> {
+        let start = input.checkpoint();
+        let ((value, filters), span) = delimited(
             ("{{", multispace0),
-            (
-                parse_variable,
-                (
-                    delimited(multispace0, opt('|'), multispace0),
-                    parse_filter_chain,
-                ),
-            ),
+            (TagArgumentValue::parse, parse_filter_chain),
             (multispace0, "}}"),
         )
-        .parse_next(input)?;
+        .with_span()
+        .parse_next(input)
+        .map_err(|error| cut_unparsed_token(input, &start, "variable", "}}", error))?;
         let tag = Self {
-            tag_type: tag.0,
-            filters: tag.1 .1,
+            value,
+            filters,
+            span: span.into(),
         };
         Ok(tag)
     }
@@ -70,53 +66,63 @@ impl<'i> VariableTag<'i> {
 
 #[cfg(test)]
 mod tests {
-    use pretty_assertions::assert_eq;
     use rstest::rstest;
 
-    use crate::template_parser::{argument::TagArgumentValue, text::SingleLineTextString};
+    use crate::template_parser::text::SingleLineTextString;
 
     use super::*;
+    use crate::span::{assert_equivalent, new_input};
 
     #[rstest]
     #[case::no_properties("{{ my_var }}", VariableTag {
-        tag_type: "my_var",
+        value: TagArgumentValue::Variable("my_var"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case::single_property("{{my_var.property}}", VariableTag {
-        tag_type: "my_var.property",
+        value: TagArgumentValue::Variable("my_var.property"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case::single_property_index("{{my_var.0}}", VariableTag {
-        tag_type: "my_var.0",
+        value: TagArgumentValue::Variable("my_var.0"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case::nested_property("{{my_var.property.nested}}", VariableTag {
-        tag_type: "my_var.property.nested",
+        value: TagArgumentValue::Variable("my_var.property.nested"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case::nested_property_index("{{my_var.0.1}}", VariableTag {
-        tag_type: "my_var.0.1",
+        value: TagArgumentValue::Variable("my_var.0.1"),
         filters: vec![],
+        span: Span::default(),
     })]
     #[case::single_filter("{{ my_var|my_filter }}", VariableTag {
-        tag_type: "my_var",
+        value: TagArgumentValue::Variable("my_var"),
         filters: vec![Filter {
             filter_type: "my_filter",
             argument: None,
+            span: Span::default(),
         }],
+        span: Span::default(),
     })]
     #[case::multiple_filters("{{ my_var|my_filter|my_filter2 }}", VariableTag {
-        tag_type: "my_var",
+        value: TagArgumentValue::Variable("my_var"),
         filters: vec![Filter {
             filter_type: "my_filter",
             argument: None,
+            span: Span::default(),
         }, Filter {
             filter_type: "my_filter2",
             argument: None,
+            span: Span::default(),
         }],
+        span: Span::default(),
     })]
     #[case::multiple_filters_with_arguments("{{ my_var|my_filter:\"arg1\"|my_filter2:\"arg2\" }}", VariableTag {
-        tag_type: "my_var",
+        value: TagArgumentValue::Variable("my_var"),
         filters: vec![Filter {
             filter_type: "my_filter",
             argument: Some(
@@ -125,6 +131,7 @@ mod tests {
                     startquote_char: '"',
                 })
             ),
+            span: Span::default(),
         }, Filter {
             filter_type: "my_filter2",
             argument: Some(
@@ -133,10 +140,12 @@ mod tests {
                     startquote_char: '"',
                 })
             ),
+            span: Span::default(),
         }],
+        span: Span::default(),
     })]
     #[case::multiple_filters_with_spaced_arguments("{{ my_var | my_filter : \"arg1\" | my_filter2:\"arg2\" }}", VariableTag {
-        tag_type: "my_var",
+        value: TagArgumentValue::Variable("my_var"),
         filters: vec![Filter {
             filter_type: "my_filter",
             argument: Some(
@@ -145,6 +154,7 @@ mod tests {
                     startquote_char: '"',
                 })
             ),
+            span: Span::default(),
         }, Filter {
             filter_type: "my_filter2",
             argument: Some(
@@ -153,11 +163,51 @@ mod tests {
                     startquote_char: '"',
                 })
             ),
+            span: Span::default(),
+        }],
+        span: Span::default(),
+    })]
+    #[case::number("{{ 42 }}", VariableTag {
+        value: TagArgumentValue::Number("42"),
+        filters: vec![],
+        span: Span::default(),
+    })]
+    #[case::string_literal("{{ \"lit\"|upper }}", VariableTag {
+        value: TagArgumentValue::Text(SingleLineTextString {
+            value: "lit",
+            startquote_char: '"',
+        }),
+        filters: vec![Filter {
+            filter_type: "upper",
+            argument: None,
+            span: Span::default(),
         }],
+        span: Span::default(),
+    })]
+    #[case::negative_argument("{{ price|floatformat:-2 }}", VariableTag {
+        value: TagArgumentValue::Variable("price"),
+        filters: vec![Filter {
+            filter_type: "floatformat",
+            argument: Some(TagArgumentValue::Number("-2")),
+            span: Span::default(),
+        }],
+        span: Span::default(),
+    })]
+    #[case::translated_argument("{{ x|default:_(\"none\") }}", VariableTag {
+        value: TagArgumentValue::Variable("x"),
+        filters: vec![Filter {
+            filter_type: "default",
+            argument: Some(TagArgumentValue::Translated(SingleLineTextString {
+                value: "none",
+                startquote_char: '"',
+            })),
+            span: Span::default(),
+        }],
+        span: Span::default(),
     })]
     fn test_parsing_variable_tag(#[case] input: &str, #[case] expected: VariableTag) {
-        let actual = VariableTag::parse.parse(input).unwrap();
-        assert_eq!(actual, expected)
+        let actual = VariableTag::parse.parse(new_input(input)).unwrap();
+        assert_equivalent(&actual, &expected)
     }
 
     #[rstest]
@@ -168,7 +218,7 @@ mod tests {
     #[case::no_properties("_MY0VAR0")]
     #[case::no_properties("_MY0V_AR0__")]
     fn test_parse_variable(#[case] input: &str) {
-        parse_variable.parse(input).unwrap();
+        parse_variable.parse(new_input(input)).unwrap();
     }
 
     #[rstest]
@@ -176,7 +226,19 @@ mod tests {
     #[case("0myvar")]
     #[case("my-var")]
     fn test_parse_variable_fails(#[case] input: &str) {
-        let actual = parse_variable.parse(input);
+        let actual = parse_variable.parse(new_input(input));
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    #[case::unterminated_string("{{ \"lit }}")]
+    #[case::unterminated_translation("{{ _(\"lit\" }}")]
+    #[case::sign_without_digits("{{ -x }}")]
+    #[case::dangling_argument("{{ x|add: }}")]
+    #[case::unit_after_number("{{ 2px }}")]
+    #[case::filter_without_pipe("{{ \"user\"size }}")]
+    fn test_parsing_variable_tag_fails(#[case] input: &str) {
+        let actual = VariableTag::parse.parse(new_input(input));
         assert!(actual.is_err());
     }
 }
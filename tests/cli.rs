@@ -0,0 +1,34 @@
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use std::fs;
+use std::process::Command;
+
+#[rstest]
+#[case::check(true)]
+#[case::write(false)]
+fn test_unreadable_file_doesnt_stop_the_run(#[case] check: bool) {
+    let root = std::env::temp_dir().join(format!("djfmt-cli-{check}-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("latin1.html"), b"<p>caf\xe9</p>").unwrap();
+    fs::write(root.join("b.html"), "<div>a</div>").unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_djfmt"));
+    if check {
+        command.arg("--check");
+    }
+    let output = command.arg(&root).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let formatted = fs::read_to_string(root.join("b.html")).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains("latin1.html: error:"), "{stderr}");
+    assert!(stdout.contains("b.html: formatted"), "{stdout}");
+    let expected = if check {
+        "<div>a</div>"
+    } else {
+        "<div>\n\ta\n</div>\n"
+    };
+    assert_eq!(formatted, expected);
+}
@@ -1,5 +1,9 @@
 use djfmt::html_parser::node::Node;
-use djfmt::{formatting::Formatable, template_parser::Template};
+use djfmt::span::new_input;
+use djfmt::{
+    formatting::{FormatOptions, Formatable},
+    template_parser::Template,
+};
 use rstest::rstest;
 use similar::{Algorithm, TextDiff};
 use std::fs;
@@ -41,8 +45,8 @@ fn test_django_formatting() {
         let input_content = fs::read_to_string(input_path).expect("Failed to read input file");
         let expected = fs::read_to_string(expected_path).expect("Failed to read expected file");
 
-        let parsed = Template::parse(&mut input_content.as_str()).unwrap();
-        let actual = parsed.formatted(0);
+        let parsed = Template::parse_source(&input_content).unwrap();
+        let actual = parsed.formatted(0, &FormatOptions::default());
 
         if actual != expected {
             println!("Differences found in file: {:?}", relative_path);
@@ -73,8 +77,8 @@ fn test_html_formatting() {
         let input_content = fs::read_to_string(input_path).expect("Failed to read input file");
         let expected = fs::read_to_string(expected_path).expect("Failed to read expected file");
 
-        let parsed = Node::parse(&mut input_content.as_str()).unwrap();
-        let actual = parsed.formatted(0);
+        let parsed = Node::parse(&mut new_input(input_content.as_str())).unwrap();
+        let actual = parsed.formatted(0, &FormatOptions::default());
 
         if actual != expected {
             println!("Differences found in file: {:?}", relative_path);
@@ -83,3 +87,21 @@ fn test_html_formatting() {
         }
     }
 }
+
+#[rstest]
+fn test_filter_expression_corpus() {
+    let corpus = fs::read_to_string("./tests/corpus/filter_expressions.txt")
+        .expect("Failed to read the corpus");
+
+    for expression in corpus.lines().filter(|line| !line.is_empty()) {
+        // Each expression should work both as a variable and as a tag argument
+        for input in [
+            format!("{{{{ {expression} }}}}\n"),
+            format!("{{% firstof {expression} %}}\n"),
+        ] {
+            let parsed = Template::parse_source(&input).unwrap();
+            assert!(parsed.errors(&input).is_empty(), "Failed to parse: {input}");
+            assert_eq!(parsed.formatted(0, &FormatOptions::default()), input);
+        }
+    }
+}